@@ -1,4 +1,6 @@
 use super::*;
+use crate::hex;
+use super::errors::Severity;
 
 #[test]
 fn test_range() {
@@ -1325,3 +1327,621 @@ fn macro_second_call_reports_second_line() {
     let err = result.unwrap_err();
     assert_eq!(err.line_number, 5);
 }
+
+struct TestContext;
+
+impl expressions::DebugContext for TestContext {
+    fn register(&self, name: &str) -> Option<i32> {
+        match name {
+            "A" => Some(0xFF),
+            "HL" => Some(0x0900),
+            "SP" => Some(0x0FFE),
+            "Z" => Some(1),
+            "CY" => Some(0),
+            _ => None,
+        }
+    }
+
+    fn read_memory(&self, address: u16) -> u8 {
+        match address {
+            0x0FFE => 0x34,
+            0x0FFF => 0x12,
+            _ => 0,
+        }
+    }
+}
+
+#[test]
+fn condition_reads_registers_memory_and_labels() {
+    let mut assembler = Assembler::new();
+    assembler.assemble("ORG 0800H
+    BUFEND: DS 10H
+    HLT
+    ").unwrap();
+
+    let met = |text: &str| assembler.parse_condition(text).unwrap().is_met(&TestContext);
+    assert!(met("A = 0FFH AND HL > BUFEND"));
+    assert!(!met("a = 0ffh and hl < bufend"));
+    assert!(met("{SP} = 1234H"));
+    assert!(met("[SP] = 1234H"));
+    assert!(met("1234H = [SP] AND [SP] <> BUFEND"));
+    assert!(met("[SP] EQ 34H AND [SP + 1] = 12H"));
+    assert!(met("Z <> CY"));
+    assert!(met("A / 0 = 0"));
+    assert!(met("HL - BUFEND >= 100H"));
+    assert!(!met("HL - BUFEND > 100H"));
+    assert!(met("HIGH HL = HIGH(BUFEND + 100H) AND LOW HL = LOW BUFEND"));
+}
+
+#[test]
+fn condition_rejects_unknown_labels_and_unbalanced_brackets() {
+    let assembler = Assembler::new();
+    assert!(assembler.parse_condition("A = NOWHERE").is_err());
+    assert!(assembler.parse_condition("[HL = 0").is_err());
+    assert!(assembler.parse_condition("{HL] = 0").is_err());
+    assert_eq!(assembler.parse_condition("20H + 1").unwrap().constant_value(), Some(0x21));
+    assert_eq!(assembler.parse_condition("A + 1").unwrap().constant_value(), None);
+}
+
+#[test]
+fn condition_rejects_labels_named_like_registers_or_flags() {
+    let mut assembler = Assembler::new();
+    assembler.assemble("ORG 0800H
+    P: DS 1
+    LOOP: HLT
+    ").unwrap();
+
+    let err = assembler.parse_condition("P = 0").unwrap_err();
+    assert_eq!(err.token, "P");
+    assert!(assembler.parse_condition("s = 0 AND Z = 0").is_ok());
+    assert_eq!(assembler.parse_condition("LOOP").unwrap().constant_value(), Some(0x0801));
+}
+
+#[test]
+fn source_map_records_code_data_and_macro_ranges() {
+    use source_map::{MacroExpansion, RangeKind};
+    let mut assembler = Assembler::new();
+    assembler.assemble("ORG 800H
+    TWICE MACRO
+        INR A
+        INR A
+    ENDM
+    START: MVI A,1
+    ; comment
+    TWICE
+    DB 1,2
+    BUF: DS 4
+    HLT
+    ").unwrap();
+
+    let map = assembler.source_map();
+    let ranges: Vec<(u16, u16, usize, RangeKind)> =
+        map.ranges().iter().map(|r| (r.start, r.len, r.line, r.kind)).collect();
+    assert_eq!(ranges, vec![
+        (0x0800, 2, 6, RangeKind::Code),
+        (0x0802, 1, 8, RangeKind::Code),
+        (0x0803, 1, 8, RangeKind::Code),
+        (0x0804, 2, 9, RangeKind::Data),
+        (0x0806, 4, 10, RangeKind::Reserved),
+        (0x080A, 1, 11, RangeKind::Code),
+    ]);
+    assert_eq!(map.ranges()[0].macro_expansion, None);
+    assert_eq!(map.ranges()[2].macro_expansion, Some(MacroExpansion { name: "TWICE".into(), id: 0 }));
+
+    assert_eq!(map.address_for_line(0, 7), Some(0x0802));
+    assert_eq!(map.address_for_line(0, 9), Some(0x080A));
+    assert_eq!(map.address_for_line(0, 12), None);
+    assert_eq!(map.line_for_address(0x0800), Some((0, 6)));
+    assert_eq!(map.line_for_address(0x0801), Some((0, 6)));
+    assert_eq!(map.line_for_address(0x0803), Some((0, 8)));
+    assert_eq!(map.line_for_address(0x0808), Some((0, 10)));
+    assert_eq!(map.line_for_address(0x080B), None);
+}
+
+#[test]
+fn listing_shows_bytes_macro_expansions_and_symbols() {
+    let source = "ORG 800H
+COUNT EQU 3
+TWICE MACRO R
+    INR R
+    INR R
+ENDM
+START: MVI A,COUNT
+    TWICE B
+MSG: DB 'HELLO'
+BUF: DS 2
+    JMP START";
+    let mut assembler = Assembler::new();
+    assembler.assemble(source).unwrap();
+    let listing = assembler.listing(source);
+    let lines: Vec<&str> = listing.lines().collect();
+
+    assert_eq!(lines[0], "ADDR  CODE         LINE   SOURCE");
+    assert_eq!(lines[1], "                      1   ORG 800H");
+    assert_eq!(lines[7], "0800  3E 03           7   START: MVI A,COUNT");
+    assert_eq!(lines[8], "                      8       TWICE B");
+    assert_eq!(lines[9], "0802  04              8+      INR B");
+    assert_eq!(lines[10], "0803  04              8+      INR B");
+    assert_eq!(lines[11], "0804  48 45 4C 4C     9   MSG: DB 'HELLO'");
+    assert_eq!(lines[12], "0808  4F");
+    assert_eq!(lines[13], "0809                 10   BUF: DS 2");
+    assert_eq!(lines[14], "080B  C3 00 08       11       JMP START");
+    assert!(listing.ends_with("\nSYMBOLS\nBUF              LABEL  0809\nCOUNT            EQU    0003\nMSG              LABEL  0804\nSTART            LABEL  0800\n"));
+}
+
+#[test]
+fn listing_shows_what_each_line_emitted() {
+    let source = "ORG 0
+    JMP NEXT
+    ORG 1
+    DB 0
+    WAIT MACRO
+    LOOP:
+    ; count down
+    DCR A
+    JNZ LOOP
+    ENDM
+NEXT: WAIT";
+    let mut assembler = Assembler::new();
+    assembler.assemble(source).unwrap();
+    let listing = assembler.listing(source);
+    let lines: Vec<&str> = listing.lines().collect();
+
+    // the forward reference overwrote DB 0, each line still shows its own bytes
+    assert_eq!(lines[2], "0000  C3 02 00        2       JMP NEXT");
+    assert_eq!(lines[4], "0001  00              4       DB 0");
+    assert_eq!(lines[11], "                     11   NEXT: WAIT");
+    assert_eq!(lines[12], "                     11+      LOOP:");
+    assert_eq!(lines[13], "                     11+      ; count down");
+    assert_eq!(lines[14], "0002  3D             11+      DCR A");
+    assert_eq!(lines[15], "0003  C2 02 00       11+      JNZ LOOP");
+}
+
+#[test]
+fn hex_and_srecord_output_cover_only_written_segments() {
+    let source = "ORG 0
+START: JMP MAIN
+ORG 100H
+MAIN: MVI A,0
+    DB 1,2
+    DS 2
+    DB 3
+    END START";
+    let mut assembler = Assembler::new();
+    assembler.assemble(source).unwrap();
+
+    let segments = assembler.segments();
+    assert_eq!(segments, vec![
+        Segment { start: 0x0000, data: vec![0xC3, 0x00, 0x01] },
+        Segment { start: 0x0100, data: vec![0x3E, 0x00, 0x01, 0x02] },
+        Segment { start: 0x0106, data: vec![0x03] },
+    ]);
+    assert_eq!(assembler.start_address(), Some(0x0000));
+
+    let intel = hex::write_intel_hex(&segments, assembler.start_address());
+    assert_eq!(intel, ":03000000C3000139\n:040100003E000102BA\n:0101060003F5\n:0400000300000000F9\n:00000001FF\n");
+
+    let srecord = hex::write_srecord(&segments[..1], assembler.start_address(), "PROG");
+    assert_eq!(srecord, "S007000050524F47C0\nS1060000C3000135\nS5030001FB\nS9030000FC\n");
+}
+
+#[test]
+fn hex_output_splits_long_segments_and_omits_start_without_end_operand() {
+    let mut assembler = Assembler::new();
+    assembler.assemble("ORG 10H\nDB 0\nEND").unwrap();
+    assert_eq!(assembler.start_address(), None);
+
+    let mut assembler = Assembler::new();
+    assembler.assemble("ORG 10H\nDB 0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0").unwrap();
+    let intel = hex::write_intel_hex(&assembler.segments(), assembler.start_address());
+    let lines: Vec<&str> = intel.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with(":10001000"));
+    assert_eq!(lines[1], ":020020000000DE");
+    assert_eq!(lines[2], ":00000001FF");
+}
+
+#[test]
+fn assemble_reports_every_failing_line() {
+    let source = "START: MVI A,1\nFOO B\n  MOV X,A\n  JMP NOWHERE\n  JMP START";
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble(source).unwrap_err();
+    assert_eq!(err.line_number, 2);
+
+    let errors: Vec<_> = assembler
+        .diagnostics()
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| (d.line_number, d.columns.clone()))
+        .collect();
+    assert_eq!(errors, vec![(2, 0..3), (3, 6..7), (4, 2..13)]);
+}
+
+#[test]
+fn assemble_warns_about_truncation_unused_labels_and_org_overlaps() {
+    let source = "ORG 0\nSTART: RST 10001H\nUNUSED: NOP\nORG 1\nDB 1,2\nJMP START";
+    let mut assembler = Assembler::new();
+    assembler.assemble(source).unwrap();
+
+    let warnings: Vec<_> = assembler
+        .diagnostics()
+        .iter()
+        .map(|d| (d.severity, d.line_number, d.columns.clone(), d.message.as_str()))
+        .collect();
+    assert_eq!(warnings, vec![
+        (Severity::Warning, 2, 11..17, "10001H is truncated to 8 bits (01H)"),
+        (Severity::Warning, 3, 0..6, "Label UNUSED is never used"),
+        (Severity::Warning, 5, 0..6, "0001H-0001H overlaps an earlier ORG region"),
+    ]);
+}
+
+#[test]
+fn forward_reference_in_8bit_operand_only_patches_one_byte() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("MVI A,LBL\nHLT\nLBL: NOP").unwrap();
+    assert_eq!(&memory[0..4], &[0x3E, 0x03, 0x76, 0x00]);
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("MVI A,LBL\nORG 300H\nLBL: NOP").unwrap_err();
+    assert_eq!(err.line_number, 1);
+    assert!(err.message.contains("does not fit in 8 bits (-128..255)"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("MVI A,300H").unwrap_err();
+    assert!(err.message.contains("does not fit in 8 bits (-128..255)"));
+}
+
+fn include_test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("mcs8sim_include_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    dir
+}
+
+#[test]
+fn include_resolves_relative_to_the_including_file() {
+    let dir = include_test_dir("relative");
+    std::fs::write(dir.join("lib/ports.inc"), "CONOUT EQU 85H\nINCLUDE \"rst.inc\"\n").unwrap();
+    std::fs::write(dir.join("lib/rst.inc"), "PUTC: OUT CONOUT\n    RET\n").unwrap();
+    let source = "INCLUDE \"lib/ports.inc\"\nSTART: MVI A,41H\n    CALL PUTC\n    JMP START";
+
+    let mut assembler = Assembler::new();
+    assembler.set_source_path(dir.join("main.asm"));
+    let memory = assembler.assemble(source).unwrap();
+    assert_eq!(&memory[0..3], &[0xD3, 0x85, 0xC9]);
+    assert_eq!(&memory[3..8], &[0x3E, 0x41, 0xCD, 0x00, 0x00]);
+
+    let map = assembler.source_map();
+    assert_eq!(map.files(), &["".to_string(), "lib/ports.inc".to_string(), "rst.inc".to_string()]);
+    let putc = map.range_at(0x0000).unwrap();
+    assert_eq!((putc.file, putc.line), (2, 1));
+    assert_eq!(map.line_for_address(0x0003), Some((0, 2)));
+    assert_eq!(map.line_for_address(0x0000), Some((2, 1)));
+    assert_eq!(map.enclosing_line(2, 1, 0), Some(1));
+    assert_eq!(map.enclosing_line(2, 1, 1), Some(2));
+    assert_eq!(map.enclosing_line(1, 1, 2), None);
+    // a breakpoint on the INCLUDE line stops at the first included instruction
+    assert_eq!(map.address_for_line(0, 1), Some(0x0000));
+    assert_eq!(map.address_for_line(2, 2), Some(0x0002));
+    assert_eq!(map.address_for_line(0, 2), Some(0x0003));
+
+    let listing = assembler.listing(source);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[1], "                      1   INCLUDE \"lib/ports.inc\"");
+    assert_eq!(lines[2], "                              ; lib/ports.inc");
+    assert_eq!(lines[3], "                      1       CONOUT EQU 85H");
+    assert_eq!(lines[4], "                      2       INCLUDE \"rst.inc\"");
+    assert_eq!(lines[5], "                                  ; rst.inc");
+    assert_eq!(lines[6], "0000  D3 85           1           PUTC: OUT CONOUT");
+    assert_eq!(lines[7], "0002  C9              2               RET");
+    assert_eq!(lines[8], "0003  3E 41           2   START: MVI A,41H");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn include_reports_errors_in_the_included_file_and_cycles() {
+    let dir = include_test_dir("errors");
+    std::fs::write(dir.join("bad.inc"), "NOP\nMOV X,A\n").unwrap();
+    std::fs::write(dir.join("a.inc"), "INCLUDE 'b.inc'\n").unwrap();
+    std::fs::write(dir.join("b.inc"), "NOP\nINCLUDE \"a.inc\"\n").unwrap();
+
+    let mut assembler = Assembler::new();
+    assembler.set_source_path(dir.join("main.asm"));
+    let err = assembler.assemble("NOP\nINCLUDE \"bad.inc\"").unwrap_err();
+    assert_eq!(err.file.as_deref(), Some("bad.inc"));
+    assert_eq!(err.line_number, 2);
+    assert_eq!(err.line_text, "MOV X,A");
+    assert!(err.to_string().starts_with("Error in bad.inc line 2"));
+
+    let mut assembler = Assembler::new();
+    assembler.set_source_path(dir.join("main.asm"));
+    let err = assembler.assemble("INCLUDE \"a.inc\"").unwrap_err();
+    assert_eq!((err.file.as_deref(), err.line_number), (Some("b.inc"), 2));
+    assert!(err.message.contains("already being included"));
+
+    let mut assembler = Assembler::new();
+    assembler.set_source_path(dir.join("main.asm"));
+    let err = assembler.assemble("INCLUDE \"missing.inc\"").unwrap_err();
+    assert_eq!((err.file, err.line_number), (None, 1));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn else_and_elseif_pick_the_first_true_branch() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        BOARD EQU 2
+        IF BOARD - 2
+            MVI A, 1
+        ELSEIF BOARD - 1
+            MVI A, 2
+        ELSEIF BOARD
+            MVI A, 3
+        ELSE
+            MVI A, 4
+        ENDIF
+        IF 0
+            MVI B, 1
+        ELSE
+            MVI B, 2
+        ENDIF
+    ").unwrap();
+
+    assert_eq!(&memory[0..4], &[0x3E, 0x02, 0x06, 0x02]);
+    assert_eq!(assembler.memory_pointer, 4);
+}
+
+#[test]
+fn conditions_inside_skipped_blocks_are_not_evaluated() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        IF 0
+            IF UNDEFINED
+                MVI A, 1
+            ELSE
+                MVI A, 2
+            ENDIF
+        ELSEIF 1
+            IF 0
+                MVI A, 3
+            ELSE
+                MVI A, 4
+            ENDIF
+        ENDIF
+    ").unwrap();
+
+    assert_eq!(&memory[0..2], &[0x3E, 0x04]);
+    assert_eq!(assembler.memory_pointer, 2);
+}
+
+#[test]
+fn ifdef_and_ifndef_test_symbols_and_macros() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        SIMULATOR EQU 1
+        IFDEF SIMULATOR
+            MVI A, 1
+        ENDIF
+        IFNDEF SIMULATOR
+            MVI A, 2
+        ENDIF
+        IFNDEF PUTC
+        PUTC MACRO
+            OUT 1
+        ENDM
+        ENDIF
+        IFDEF PUTC
+            PUTC
+        ELSE
+            HLT
+        ENDIF
+    ").unwrap();
+
+    assert_eq!(&memory[0..4], &[0x3E, 0x01, 0xD3, 0x01]);
+    assert!(assembler.diagnostics().is_empty());
+}
+
+#[test]
+fn conditionals_nest_inside_macro_bodies() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        LOADA MACRO V
+            IF V / 256
+                LXI H, V
+            ELSE
+                MVI A, V
+            ENDIF
+        ENDM
+        LOADA 5
+        LOADA 300H
+    ").unwrap();
+
+    assert_eq!(&memory[0..5], &[0x3E, 0x05, 0x21, 0x00, 0x03]);
+}
+
+#[test]
+fn dangling_else_and_unclosed_if_report_their_lines() {
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("NOP\nELSE").unwrap_err();
+    assert_eq!(err.line_number, 2);
+    assert!(err.message.contains("ELSE without matching IF"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("IF 1\nELSE\nELSE\nENDIF").unwrap_err();
+    assert_eq!(err.line_number, 3);
+    assert!(err.message.contains("ELSE after ELSE of the IF on line 1"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("NOP\nIF 1\nNOP\nIF 0\nENDIF").unwrap_err();
+    assert_eq!(err.line_number, 2);
+    assert!(err.message.contains("IF without matching ENDIF"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("OPEN MACRO\nIF 1\nENDM\nNOP\nOPEN\nENDIF").unwrap_err();
+    assert_eq!(err.line_number, 5);
+    assert!(err.message.contains("in macro OPEN"));
+    assert_eq!(assembler.diagnostics().iter().filter(|d| d.severity == Severity::Error).count(), 2);
+}
+
+#[test]
+fn rept_unrolls_its_body_with_local_labels() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("REPT 3\nLOCAL SKIP\n    JMP SKIP\nSKIP: INR A\nENDM\nREPT 0\nNOP\nENDM").unwrap();
+
+    assert_eq!(&memory[0..12], &[0xC3, 0x03, 0x00, 0x3C, 0xC3, 0x07, 0x00, 0x3C, 0xC3, 0x0B, 0x00, 0x3C]);
+    assert_eq!(assembler.memory_pointer, 12);
+    // expanded lines map back to the body
+    let map = assembler.source_map();
+    assert_eq!(map.line_for_address(0x0004), Some((0, 3)));
+    assert_eq!(map.line_for_address(0x000B), Some((0, 4)));
+}
+
+#[test]
+fn irp_and_irpc_substitute_one_value_per_pass() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        IRP R,<B, C,D>
+            INR R
+        ENDM
+        IRP R,<>
+            INR R
+        ENDM
+        IRPC D,135
+            DB D*2
+        ENDM
+    ").unwrap();
+
+    assert_eq!(&memory[0..6], &[0x04, 0x0C, 0x14, 2, 6, 10]);
+    assert_eq!(assembler.memory_pointer, 6);
+}
+
+#[test]
+fn exitm_ends_only_the_innermost_expansion() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        I SET 0
+        REPT 10
+            I SET I+1
+            IF I - 4
+                DB I
+            ELSE
+                EXITM
+            ENDIF
+        ENDM
+        HALF MACRO
+            NOP
+            EXITM
+            HLT
+        ENDM
+        HALF
+        DB 0FFH
+    ").unwrap();
+
+    assert_eq!(&memory[0..5], &[1, 2, 3, 0x00, 0xFF]);
+    assert_eq!(assembler.memory_pointer, 5);
+    assert!(assembler.if_stack.is_empty());
+}
+
+#[test]
+fn macros_nest_definitions_calls_and_repeats() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        OUTER MACRO
+        INNER MACRO V
+            MVI A, V
+        ENDM
+        ENDM
+        TABLE MACRO N
+            REPT N
+                DB N
+            ENDM
+            INNER N
+        ENDM
+        OUTER
+        INNER 7
+        TABLE 2
+    ").unwrap();
+
+    assert_eq!(&memory[0..6], &[0x3E, 0x07, 2, 2, 0x3E, 0x02]);
+}
+
+#[test]
+fn macro_directive_errors() {
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("NOP\nREPT 2\nNOP").unwrap_err();
+    assert_eq!(err.line_number, 2);
+    assert!(err.message.contains("REPT without matching ENDM"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("EXITM").unwrap_err();
+    assert!(err.message.contains("EXITM outside of a macro"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("NOP\nLOCAL X").unwrap_err();
+    assert_eq!(err.line_number, 2);
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("IRP X,1,2\nNOP\nENDM").unwrap_err();
+    assert!(err.message.contains("angle brackets"));
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("FOREVER MACRO\nFOREVER\nENDM\nNOP\nFOREVER").unwrap_err();
+    assert_eq!(err.line_number, 5);
+    assert!(err.message.contains("nested more than 64 deep"));
+}
+
+#[test]
+fn high_and_low_take_bytes_of_forward_labels() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        MVI H, HIGH(TABLE)
+        MVI L, LOW(TABLE)
+        DB HIGH TABLE+1, LOW 1234H SHR 4
+        ORG 1234H
+        TABLE: DB 1
+    ").unwrap();
+
+    assert_eq!(&memory[0..6], &[0x26, 0x12, 0x2E, 0x34, 0x13, 0x03]);
+}
+
+#[test]
+fn relational_operators_work_in_if_and_data() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        VERSION EQU 2
+        IF VERSION EQ 2
+            DB 1
+        ENDIF
+        IF VERSION >= 3
+            DB 2
+        ELSEIF VERSION <> 2
+            DB 3
+        ENDIF
+        DW 3 LT 5, 1 = 2, 1 + 1 = 2 AND 3 > 2, NOT 4 LE 4
+    ").unwrap();
+
+    assert_eq!(&memory[0..9], &[1, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+    assert_eq!(assembler.memory_pointer, 9);
+}
+
+#[test]
+fn strings_in_expressions_and_data() {
+    let mut assembler = Assembler::new();
+    let memory = assembler.assemble("
+        MVI A, '+'
+        DB 'A' + 80H, 'HI', 'it''s'
+        DW 'AB'
+        LXI H, 'O' OR 20H
+    ").unwrap();
+
+    assert_eq!(&memory[0..2], &[0x3E, b'+']);
+    assert_eq!(&memory[2..9], &[0xC1, b'H', b'I', b'i', b't', b'\'', b's']);
+    assert_eq!(&memory[9..11], &[0x42, 0x41]);
+    assert_eq!(&memory[11..14], &[0x21, 0x6F, 0x00]);
+
+    let mut assembler = Assembler::new();
+    let err = assembler.assemble("MVI A, 'ABC' + 1").unwrap_err();
+    assert!(err.message.contains("one or two ASCII characters"));
+
+    let mut assembler = Assembler::new();
+    assert!(assembler.assemble("DW 1 HIGH 2").is_err());
+}
@@ -1,5 +1,6 @@
 use std::error::Error;
 use std::fmt;
+use std::ops::Range;
 
 #[derive(Clone, Debug)]
 #[derive(PartialEq)]
@@ -10,6 +11,8 @@ pub enum TokenType{
 }
 #[derive(Debug, Clone)]
 pub struct AssemblyError{
+    // None for the text passed to assemble, otherwise the INCLUDE operand
+    pub file: Option<String>,
     pub line_number: usize,
     pub line_text: String,
     pub message: String
@@ -18,7 +21,45 @@ pub struct AssemblyError{
 impl Error for AssemblyError {}
 impl fmt::Display for AssemblyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error in line {} - {}:\n{}", self.line_number, self.line_text, self.message)
+        match &self.file {
+            Some(file) => write!(f, "Error in {} line {} - {}:\n{}", file, self.line_number, self.line_text, self.message),
+            None => write!(f, "Error in line {} - {}:\n{}", self.line_number, self.line_text, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    // None for the text passed to assemble, otherwise the INCLUDE operand
+    pub file: Option<String>,
+    // 1-based, 0 when the problem is only found at the end of the source
+    pub line_number: usize,
+    // character columns in the source line, end exclusive
+    pub columns: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        if self.line_number == 0 {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}:{}: {}: {}", self.line_number, self.columns.start + 1, severity, self.message)
+        }
     }
 }
 
@@ -46,12 +87,6 @@ impl fmt::Display for InvalidTokenError {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct InvalidTokenAtLineError {
-    pub line: usize,
-    pub source: InvalidTokenError,
-}
-
 #[derive(Debug, Clone)]
 pub struct OverflowError;
 
@@ -3,7 +3,8 @@ use std::slice::Iter;
 use regex::Regex;
 use crate::assembler::symbols::{MacroScope, SymbolScope};
 use super::{Assembler};
-use super::errors::{InvalidTokenAtLineError, InvalidTokenError, TokenType};
+use std::collections::HashSet;
+use super::errors::{InvalidTokenError, Severity, TokenType};
 
 #[derive(Debug, Clone)]
 enum CalculationToken {
@@ -11,6 +12,10 @@ enum CalculationToken {
     Op(Op),
     LParen,
     RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
     Symbol(String),
 }
 
@@ -27,21 +32,125 @@ enum Op {
     Shl,
     Shr,
     Not,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    High,
+    Low,
 }
 
 #[derive(Debug, Clone)]
 enum Expr {
     Value(i32),
     Symbol(String),
+    // only produced by debugger conditions
+    Register(String),
+    Memory { address: Box<Expr>, word: bool },
     Unary { op: Op, expr: Box<Expr> },
     Binary { op: Op, left: Box<Expr>, right: Box<Expr> },
 }
 
+const CONDITION_REGISTERS: [&str; 19] = [
+    "A", "B", "C", "D", "E", "H", "L", "M", "BC", "DE", "HL", "SP", "PC", "PSW",
+    "S", "Z", "AC", "P", "CY",
+];
+
+// register, flag and memory values a breakpoint condition reads at run time
+pub trait DebugContext {
+    fn register(&self, name: &str) -> Option<i32>;
+    fn read_memory(&self, address: u16) -> u8;
+}
+
+struct NoDebugContext;
+
+impl DebugContext for NoDebugContext {
+    fn register(&self, _name: &str) -> Option<i32> {
+        None
+    }
+
+    fn read_memory(&self, _address: u16) -> u8 {
+        0
+    }
+}
+
+// debugger condition, labels are resolved when it is parsed
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_met(&self, context: &dyn DebugContext) -> bool {
+        Self::eval(&self.expr, context) != 0
+    }
+
+    // value of an expression made only of numbers and labels
+    pub fn constant_value(&self) -> Option<i32> {
+        Self::is_constant(&self.expr).then(|| Self::eval(&self.expr, &NoDebugContext))
+    }
+
+    fn is_constant(expr: &Expr) -> bool {
+        match expr {
+            Expr::Value(_) => true,
+            Expr::Unary { expr, .. } => Self::is_constant(expr),
+            Expr::Binary { left, right, .. } => Self::is_constant(left) && Self::is_constant(right),
+            _ => false,
+        }
+    }
+
+    fn eval(expr: &Expr, context: &dyn DebugContext) -> i32 {
+        match expr {
+            Expr::Value(v) => *v,
+            Expr::Symbol(_) => 0,
+            Expr::Register(name) => context.register(name).unwrap_or(0),
+            Expr::Memory { address, word } => {
+                let address = Self::eval(address, context) as u16;
+                let low = context.read_memory(address) as i32;
+                if *word {
+                    low | (context.read_memory(address.wrapping_add(1)) as i32) << 8
+                } else {
+                    low
+                }
+            }
+            Expr::Unary { op, expr } => {
+                let v = Self::eval(expr, context);
+                match op {
+                    Op::Not => !v & 0xFFFF,
+                    Op::High => (v >> 8) & 0xFF,
+                    Op::Low => v & 0xFF,
+                    _ => (!v).wrapping_add(1) & 0xFFFF,
+                }
+            }
+            Expr::Binary { op, left, right } => {
+                let l = Self::eval(left, context);
+                let r = Self::eval(right, context);
+                if r == 0 && matches!(op, Op::Div | Op::Mod) {
+                    return 0;
+                }
+                Assembler::eval_bin(*op, l, r)
+            }
+        }
+    }
+}
+
 pub struct PendingExpr {
-    addr: usize,
+    pub(super) addr: usize,
     expr: Expr,
+    file: usize,
     line: usize,
     macro_scope: Option<MacroScope>,
+    // false for 8-bit operands, which only own one byte
+    pub(super) word: bool,
+    // source map range of the line, set once the line is recorded
+    pub(super) range: Option<usize>,
 }
 
 impl Assembler {
@@ -63,6 +172,7 @@ impl Assembler {
                 additional_info: Some("Unexpected token at end of expression".into()),
             });
         }
+        Self::collect_symbols(&ast, &mut self.referenced_symbols);
 
         match self.eval_expr(&ast, self.current_macro_scope.as_ref()) {
             Ok(v) => Ok(Some(v)),
@@ -71,8 +181,11 @@ impl Assembler {
                     self.pending_exprs.push(PendingExpr {
                         addr: self.memory_pointer + offset,
                         expr: ast,
+                        file: self.current_file,
                         line: self.current_line,
-                        macro_scope: self.current_macro_scope.clone()
+                        macro_scope: self.current_macro_scope.clone(),
+                        word: true,
+                        range: None,
                     });
                 } else {
                     return Err(InvalidTokenError {
@@ -86,24 +199,113 @@ impl Assembler {
         }
     }
 
+    // "A = 0FFH AND HL > BUFEND", "[COUNT] <> 0" or "[SP] = 1234H"
+    // [x] reads a byte, or a word when compared with a value above FFh, {x} always a little endian word
+    pub fn parse_condition(&self, condition: &str) -> Result<Condition, InvalidTokenError> {
+        let pattern = r"(?i)(\bHIGH\b|\bLOW\b|\bMOD\b|\bNOT\b|\bAND\b|\bOR\b|\bXOR\b|\bSHL\b|\bSHR\b|\bEQ\b|\bNE\b|\bLT\b|\bLE\b|\bGT\b|\bGE\b|<>|<=|>=|=|<|>|\+|-|\*|/|\(|\)|\[|\]|\{|\})";
+        let re = Regex::new(pattern).unwrap();
+        let tokens = self.tokenize_with(condition, &re)?;
+        let mut it = tokens.iter().peekable();
+
+        let ast = Self::parse_expr(&mut it, 0)?;
+
+        if it.peek().is_some() {
+            return Err(InvalidTokenError {
+                token: condition.into(),
+                token_type: TokenType::Operand,
+                additional_info: Some("Unexpected token at end of expression".into()),
+            });
+        }
+
+        Ok(Condition {
+            source: condition.trim().to_string(),
+            expr: self.resolve_condition_symbols(ast)?,
+        })
+    }
+
+    fn resolve_condition_symbols(&self, expr: Expr) -> Result<Expr, InvalidTokenError> {
+        Ok(match expr {
+            Expr::Symbol(name) => {
+                let name_upper = name.to_uppercase();
+                let label = self.eval_expr(&Expr::Symbol(name.clone()), None);
+                if CONDITION_REGISTERS.contains(&name_upper.as_str()) {
+                    if label.is_ok() {
+                        return Err(InvalidTokenError {
+                            token: name,
+                            token_type: TokenType::Label,
+                            additional_info: Some("Name is both a label and a register or flag".into()),
+                        });
+                    }
+                    Expr::Register(name_upper)
+                } else {
+                    let value = label.map_err(|e| InvalidTokenError {
+                        token: name,
+                        token_type: TokenType::Label,
+                        additional_info: Some(e),
+                    })?;
+                    Expr::Value(value)
+                }
+            }
+            Expr::Memory { address, word } => Expr::Memory {
+                address: Box::new(self.resolve_condition_symbols(*address)?),
+                word,
+            },
+            Expr::Unary { op, expr } => Expr::Unary {
+                op,
+                expr: Box::new(self.resolve_condition_symbols(*expr)?),
+            },
+            Expr::Binary { op, left, right } => {
+                let mut left = self.resolve_condition_symbols(*left)?;
+                let mut right = self.resolve_condition_symbols(*right)?;
+                if matches!(op, Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge) {
+                    left = Self::widen_memory_read(left, &right);
+                    right = Self::widen_memory_read(right, &left);
+                }
+                Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+            }
+            other => other,
+        })
+    }
+
+    // a byte read never equals 1234H, so [x] compared with a value above FFh reads a word
+    fn widen_memory_read(expr: Expr, other: &Expr) -> Expr {
+        match expr {
+            Expr::Memory { address, word: false }
+                if Condition::is_constant(other) && Condition::eval(other, &NoDebugContext) & 0xFFFF > 0xFF =>
+            {
+                Expr::Memory { address, word: true }
+            }
+            expr => expr,
+        }
+    }
+
     fn tokenize(&self, expr: &str) -> Result<Vec<CalculationToken>, InvalidTokenError> {
-        let pattern = r"(\bHERE\b|\$|\bMOD\b|\bNOT\b|\bAND\b|\bOR\b|\bXOR\b|\bSHL\b|\bSHR\b|\+|-|\*|/|\(|\))";
+        let pattern = r"(\bHERE\b|\$|\bHIGH\b|\bLOW\b|\bMOD\b|\bNOT\b|\bAND\b|\bOR\b|\bXOR\b|\bSHL\b|\bSHR\b|\bEQ\b|\bNE\b|\bLT\b|\bLE\b|\bGT\b|\bGE\b|<>|<=|>=|=|<|>|\+|-|\*|/|\(|\))";
         let re = Regex::new(pattern).unwrap();
+        self.tokenize_with(expr, &re)
+    }
 
+    fn tokenize_with(&self, expr: &str, re: &Regex) -> Result<Vec<CalculationToken>, InvalidTokenError> {
         let mut tokens = Vec::new();
         let mut last = 0;
+        let strings = Self::string_spans(expr);
 
-        for m in re.find_iter(expr) {
+        // operators inside 'strings' are just characters
+        for m in re.find_iter(expr).filter(|m| !strings.iter().any(|span| span.contains(&m.start()))) {
             if m.start() > last {
-                Self::push_part_as_token(&expr[last..m.start()], &mut tokens);
+                Self::push_part_as_token(&expr[last..m.start()], &mut tokens)?;
             }
 
-            let t = m.as_str();
-            tokens.push(match t {
+            let t = m.as_str().to_uppercase();
+            tokens.push(match t.as_str() {
                 "(" => CalculationToken::LParen,
                 ")" => CalculationToken::RParen,
+                "[" => CalculationToken::LBracket,
+                "]" => CalculationToken::RBracket,
+                "{" => CalculationToken::LBrace,
+                "}" => CalculationToken::RBrace,
                 "HERE" | "$" => CalculationToken::Num(self.memory_pointer as i32),
-                _ => CalculationToken::Op(match t {
+                _ => CalculationToken::Op(match t.as_str() {
                     "+" => Op::Add,
                     "-" => Op::Sub,
                     "*" => Op::Mul,
@@ -115,6 +317,14 @@ impl Assembler {
                     "SHL" => Op::Shl,
                     "SHR" => Op::Shr,
                     "NOT" => Op::Not,
+                    "HIGH" => Op::High,
+                    "LOW" => Op::Low,
+                    "=" | "EQ" => Op::Eq,
+                    "<>" | "NE" => Op::Ne,
+                    "<" | "LT" => Op::Lt,
+                    "<=" | "LE" => Op::Le,
+                    ">" | "GT" => Op::Gt,
+                    ">=" | "GE" => Op::Ge,
                     _ => unreachable!(),
                 }),
             });
@@ -123,41 +333,84 @@ impl Assembler {
         }
 
         if last < expr.len() {
-            Self::push_part_as_token(&expr[last..], &mut tokens);
+            Self::push_part_as_token(&expr[last..], &mut tokens)?;
         }
 
         Ok(tokens)
     }
 
+    // byte ranges of closed quoted strings, quotes included, '' inside a string is a quote
+    fn string_spans(expr: &str) -> Vec<std::ops::Range<usize>> {
+        let mut spans = Vec::new();
+        let mut start: Option<usize> = None;
+        let mut chars = expr.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            if c != '\'' {
+                continue;
+            }
+            match start {
+                None => start = Some(index),
+                Some(_) if chars.peek().is_some_and(|&(_, next)| next == '\'') => {
+                    chars.next();
+                }
+                Some(first) => {
+                    spans.push(first..index + 1);
+                    start = None;
+                }
+            }
+        }
+        spans
+    }
+
+    // text of an operand that is exactly one quoted string
+    pub(super) fn string_literal(operand: &str) -> Option<String> {
+        let operand = operand.trim();
+        match Self::string_spans(operand).as_slice() {
+            [span] if *span == (0..operand.len()) => {
+                Some(operand[1..operand.len() - 1].replace("''", "'"))
+            }
+            _ => None,
+        }
+    }
+
     fn push_part_as_token(
         part: &str,
         tokens: &mut Vec<CalculationToken>,
-    ) {
+    ) -> Result<(), InvalidTokenError> {
         let part = part.trim();
         if part.is_empty() {
-            return;
+            return Ok(());
         }
 
-        if part.starts_with('\'') && part.ends_with('\'') && part.len() == 2 {
-            tokens.push(CalculationToken::Num(0i32));
-        }
-        else if part.starts_with('\'') && part.ends_with('\'') && part.len() == 3 {
-            let c = part.chars().nth(1).unwrap();
-            tokens.push(CalculationToken::Num(c as i32));
+        if let Some(text) = Self::string_literal(part) {
+            // up to two characters, the first one in the high byte
+            if text.len() > 2 || !text.is_ascii() {
+                return Err(InvalidTokenError {
+                    token: part.into(),
+                    token_type: TokenType::Operand,
+                    additional_info: Some("Strings in expressions hold one or two ASCII characters".into()),
+                });
+            }
+            let value = text.bytes().fold(0i32, |value, byte| value << 8 | byte as i32);
+            tokens.push(CalculationToken::Num(value));
         } else if let Ok(v) = Self::parse_number_i32(part) {
             tokens.push(CalculationToken::Num(v));
         } else {
             tokens.push(CalculationToken::Symbol(part.to_string()));
         }
+        Ok(())
     }
 
+    // Intel order, loosest first
     fn precedence(op: Op) -> u8 {
         match op {
             Op::Or | Op::Xor        => 1,
             Op::And                => 2,
             Op::Not                => 3, // unary
-            Op::Add | Op::Sub      => 4,
-            Op::Mul | Op::Div | Op::Mod | Op::Shl | Op::Shr => 5,
+            Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge => 4,
+            Op::Add | Op::Sub      => 5,
+            Op::Mul | Op::Div | Op::Mod | Op::Shl | Op::Shr => 6,
+            Op::High | Op::Low     => 7, // unary
         }
     }
 
@@ -172,7 +425,7 @@ impl Assembler {
 
             Some(CalculationToken::Symbol(l)) => Expr::Symbol(l.clone()),
 
-            Some(CalculationToken::Op(op @ Op::Sub)) | Some(CalculationToken::Op(op @ Op::Not)) => {
+            Some(CalculationToken::Op(op @ (Op::Sub | Op::Not | Op::High | Op::Low))) => {
                 let prec = Self::precedence(*op);
                 let expr = Self::parse_expr(tokens, prec)?;
                 Expr::Unary {
@@ -193,6 +446,22 @@ impl Assembler {
                 }
             }
 
+            Some(open @ (CalculationToken::LBracket | CalculationToken::LBrace)) => {
+                let word = matches!(open, CalculationToken::LBrace);
+                let address = Self::parse_expr(tokens, 0)?;
+                match (tokens.next(), word) {
+                    (Some(CalculationToken::RBracket), false) | (Some(CalculationToken::RBrace), true) => Expr::Memory {
+                        address: Box::new(address),
+                        word,
+                    },
+                    _ => return Err(InvalidTokenError {
+                        token: "".into(),
+                        token_type: TokenType::Operand,
+                        additional_info: Some(if word { "Missing '}'" } else { "Missing ']'" }.into()),
+                    }),
+                }
+            }
+
             _ => return Err(InvalidTokenError {
                 token: "".into(),
                 token_type: TokenType::Operand,
@@ -200,7 +469,8 @@ impl Assembler {
             }),
         };
 
-        while let Some(CalculationToken::Op(op)) = tokens.peek() {
+        // a unary operator here is left over and reported by the caller
+        while let Some(CalculationToken::Op(op)) = tokens.peek() && !matches!(op, Op::Not | Op::High | Op::Low) {
             let prec = Self::precedence(*op);
             if prec < min_prec {
                 break;
@@ -232,11 +502,22 @@ impl Assembler {
             Op::Xor => a ^ b,
             Op::Shl => a.wrapping_shl((b & 0xF) as u32),
             Op::Shr => a.wrapping_shr((b & 0xF) as u32),
-            _ => unreachable!(), // NOT is unary
+            Op::Eq => Self::truth(a & 0xFFFF == b & 0xFFFF),
+            Op::Ne => Self::truth(a & 0xFFFF != b & 0xFFFF),
+            Op::Lt => Self::truth(a & 0xFFFF < b & 0xFFFF),
+            Op::Le => Self::truth(a & 0xFFFF <= b & 0xFFFF),
+            Op::Gt => Self::truth(a & 0xFFFF > b & 0xFFFF),
+            Op::Ge => Self::truth(a & 0xFFFF >= b & 0xFFFF),
+            _ => unreachable!(), // NOT, HIGH and LOW are unary
         };
         r & 0xFFFF
     }
 
+    // relational operators yield all ones for true, like Intel's assembler
+    fn truth(value: bool) -> i32 {
+        if value { 0xFFFF } else { 0 }
+    }
+
 
     fn eval_expr(&self, expr: &Expr, macro_scope: Option<&MacroScope>) -> Result<i32, String> {
         match expr {
@@ -273,10 +554,15 @@ impl Assembler {
                 Err(format!("Undefined symbol {}", l))
             }
 
+            Expr::Register(name) => Err(format!("Register {} is only known while debugging", name)),
+            Expr::Memory { .. } => Err("Memory can only be read while debugging".into()),
+
             Expr::Unary { op, expr } => {
                 let v = self.eval_expr(expr, macro_scope)?;
                 Ok(match op {
                     Op::Not => !v & 0xFFFF,
+                    Op::High => (v >> 8) & 0xFF,
+                    Op::Low => v & 0xFF,
                     Op::Sub => (!v).wrapping_add(1), // unary minus
                     _ => unreachable!(),
                 })
@@ -290,22 +576,48 @@ impl Assembler {
         }
     }
 
-    pub fn resolve_pending_exprs(&mut self) -> Result<(), InvalidTokenAtLineError> {
-        for p in &self.pending_exprs {
-            let v = self.eval_expr(&p.expr,  p.macro_scope.as_ref())
-                .map_err(|e| InvalidTokenAtLineError {
-                    line: p.line,
-                    source: InvalidTokenError {
-                        token: e,
-                        token_type: TokenType::Label,
-                        additional_info: None,
-                    },
-                })?;
+    fn collect_symbols(expr: &Expr, symbols: &mut HashSet<String>) {
+        match expr {
+            Expr::Symbol(name) => {
+                symbols.insert(name.to_uppercase());
+            }
+            Expr::Unary { expr, .. } => Self::collect_symbols(expr, symbols),
+            Expr::Binary { left, right, .. } => {
+                Self::collect_symbols(left, symbols);
+                Self::collect_symbols(right, symbols);
+            }
+            Expr::Memory { address, .. } => Self::collect_symbols(address, symbols),
+            Expr::Value(_) | Expr::Register(_) => {}
+        }
+    }
+
+    // every unresolved reference is reported, not only the first
+    pub fn resolve_pending_exprs(&mut self) {
+        let pending = std::mem::take(&mut self.pending_exprs);
+        for p in &pending {
+            let v = match self.eval_expr(&p.expr, p.macro_scope.as_ref()) {
+                Ok(v) => v,
+                Err(e) => {
+                    let error = InvalidTokenError { token: e, token_type: TokenType::Label, additional_info: None };
+                    self.report(Severity::Error, p.file, p.line, None, error.to_string());
+                    continue;
+                }
+            };
 
             let b = v.to_le_bytes();
-            self.memory[p.addr] = b[0];
-            self.memory[p.addr + 1] = b[1];
+            let bytes = if p.word { &b[..2] } else { &b[..1] };
+            self.memory[p.addr..p.addr + bytes.len()].copy_from_slice(bytes);
+            if let Some(range) = p.range {
+                self.source_map.fill(range, p.addr, bytes);
+            }
+            if !p.word && !(-128..=255).contains(&v) {
+                let error = InvalidTokenError {
+                    token: format!("{:04X}H", v & 0xFFFF),
+                    token_type: TokenType::Operand,
+                    additional_info: Some("Expression does not fit in 8 bits (-128..255)".into()),
+                };
+                self.report(Severity::Error, p.file, p.line, None, error.to_string());
+            }
         }
-        Ok(())
     }
 }
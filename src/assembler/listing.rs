@@ -0,0 +1,101 @@
+use std::fmt::Write;
+
+use super::Assembler;
+use super::source_map::{RangeKind, SourceRange};
+use super::symbols::SymbolKind;
+
+const BYTES_PER_ROW: usize = 4;
+
+impl Assembler {
+    // classic listing of the last assemble call: address, bytes, line number and source,
+    // macro expansions indented under their call, INCLUDEd files nested under their INCLUDE
+    // line and the symbol table at the end
+    pub fn listing(&self, source: &str) -> String {
+        let mut listing = String::new();
+        listing.push_str("ADDR  CODE         LINE   SOURCE\n");
+
+        let lines: Vec<&str> = source.lines().collect();
+        self.write_file(&mut listing, 0, &lines, "");
+
+        listing.push_str("\nSYMBOLS\n");
+        let mut symbols: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|(name, symbol)| {
+                let kind = match symbol.kind {
+                    SymbolKind::Label => "LABEL",
+                    SymbolKind::Equ => "EQU",
+                    SymbolKind::Set => "SET",
+                    SymbolKind::Macro => return None,
+                };
+                Some((name, kind, symbol.value))
+            })
+            .collect();
+        symbols.sort();
+        for (name, kind, value) in symbols {
+            let _ = writeln!(listing, "{:<16} {:<6} {:04X}", name, kind, value & 0xFFFF);
+        }
+        listing
+    }
+
+    fn write_file(&self, listing: &mut String, file: usize, lines: &[&str], indent: &str) {
+        let ranges = self.source_map.ranges();
+        for (index, text) in lines.iter().enumerate() {
+            let line_number = index + 1;
+            let text = format!("{}{}", indent, text);
+            let own = ranges
+                .iter()
+                .filter(|range| range.file == file && range.line == line_number && range.macro_expansion.is_none());
+            let mut first = true;
+            for range in own {
+                self.write_range(listing, range, line_number, "", if first { &text } else { "" });
+                first = false;
+            }
+            if first {
+                let _ = writeln!(listing, "{:18}{:>5}   {}", "", line_number, text);
+            }
+
+            let expanded = ranges
+                .iter()
+                .filter(|range| range.file == file && range.line == line_number && range.macro_expansion.is_some());
+            for range in expanded {
+                self.write_range(listing, range, line_number, "+", &format!("{}    {}", indent, range.text));
+            }
+
+            for included in self.source_map.files_included_at(file, line_number) {
+                let indent = format!("{}    ", indent);
+                let _ = writeln!(listing, "{:26}{}; {}", "", indent, self.source_map.files()[included]);
+                let lines: Vec<&str> = self.file_lines[included].iter().map(String::as_str).collect();
+                self.write_file(listing, included, &lines, &indent);
+            }
+        }
+    }
+
+    // bytes come from what the line emitted, not the final image a later ORG region may have overwritten
+    fn write_range(&self, listing: &mut String, range: &SourceRange, line_number: usize, marker: &str, text: &str) {
+        match range.kind {
+            RangeKind::Reserved => {
+                let _ = writeln!(listing, "{:04X}  {:12}{:>5}{:<3}{}", range.start, "", line_number, marker, text);
+                return;
+            }
+            RangeKind::Text => {
+                let _ = writeln!(listing, "{:18}{:>5}{:<3}{}", "", line_number, marker, text);
+                return;
+            }
+            RangeKind::Code | RangeKind::Data => {}
+        }
+        for (row, chunk) in range.bytes.chunks(BYTES_PER_ROW).enumerate() {
+            let row_start = range.start as usize + row * BYTES_PER_ROW;
+            let bytes = chunk
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if row == 0 {
+                let _ = writeln!(listing, "{:04X}  {:12}{:>5}{:<3}{}", row_start, bytes, line_number, marker, text);
+            } else {
+                let _ = writeln!(listing, "{:04X}  {}", row_start, bytes);
+            }
+        }
+    }
+}
@@ -1,15 +1,20 @@
 #[cfg(test)]
 mod assembler_tests;
 pub(crate) mod errors;
-mod expressions;
+pub(crate) mod expressions;
+pub mod source_map;
+mod listing;
 mod utils;
 mod symbols;
 
-use std::{collections::HashMap};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::iter::Peekable;
 use std::str::Chars;
-use errors::{AssemblyError, InvalidTokenError, OverflowError, TokenOrOverflowError, TokenType};
+use errors::{AssemblyError, Diagnostic, InvalidTokenError, OverflowError, Severity, TokenOrOverflowError, TokenType};
 use expressions::PendingExpr;
+use crate::hex::Segment;
+use source_map::{MacroExpansion, RangeKind, SourceMap, SourceRange};
 use symbols::{Macro, MacroScope, Symbol, SymbolKind, SymbolScope};
 
 /*
@@ -32,9 +37,69 @@ pub const INSTRUCTIONS: [&str; 78] = ["STC", "CMC", "INR", "DCR", "CMA", "DAA",
     , "XRI", "ORI", "CPI", "STA", "LDA", "SHLD", "LHLD", "PCHL", "JMP", "JC", "JNC", "JZ", "JNZ", "JP", "JM", "JPE", "JPO"
     , "CALL", "CC", "CNC", "CZ", "CNZ", "CP", "CM", "CPE", "CPO", "RET", "RC", "RNC", "RZ", "RNZ", "RM", "RP", "RPE", "RPO"
     , "RST", "EI", "DI", "IN", "OUT", "HLT"];
-pub const PSEUDO_INSTRUCTIONS: [&str; 8] = ["ORG", "EQU", "SET", "END", "IF", "ENDIF", "MACRO", "ENDM"];
+pub const PSEUDO_INSTRUCTIONS: [&str; 18] = ["ORG", "EQU", "SET", "END", "IF", "IFDEF", "IFNDEF", "ELSEIF", "ELSE", "ENDIF", "MACRO", "ENDM"
+    , "REPT", "IRP", "IRPC", "LOCAL", "EXITM", "INCLUDE"];
+// directives whose body runs up to a matching ENDM
+const BLOCK_DIRECTIVES: [&str; 4] = ["MACRO", "REPT", "IRP", "IRPC"];
+// macros calling macros, deeper than this is most likely endless recursion
+const MAX_EXPANSION_DEPTH: usize = 64;
 pub const DATA_STATEMENTS: [&str; 3] = ["DB", "DW", "DS"];
 
+struct IncludeFrame {
+    file: usize,
+    // canonical, to catch files including themselves
+    path: PathBuf,
+    lines: std::vec::IntoIter<String>,
+    line: usize,
+}
+
+enum BlockKind {
+    Macro { name: String, params: Vec<String> },
+    // REPT, IRP or IRPC, expanded as soon as its ENDM is read
+    Repeat { directive: String, passes: Vec<Vec<(String, String)>> },
+}
+
+// a MACRO, REPT, IRP or IRPC body being collected
+struct Definition {
+    kind: BlockKind,
+    // lines with the numbers they are reported at
+    body: Vec<(String, usize)>,
+    // nested blocks inside the body still waiting for their ENDM
+    depth: usize,
+    file: usize,
+    line: usize,
+    expansion: Option<u64>,
+}
+
+// a macro call or a REPT, IRP or IRPC block being expanded
+struct Expansion {
+    // "macro NAME" or the directive, for errors
+    name: String,
+    // fresh for every pass, IF blocks and LOCAL names belong to it
+    id: u64,
+    // where labels and EQUs go, REPT, IRP and IRPC keep the one they were used in
+    scope: Option<MacroScope>,
+    body: Vec<(String, usize)>,
+    // parameter values of the passes not started yet
+    passes: std::vec::IntoIter<Vec<(String, String)>>,
+    lines: std::vec::IntoIter<(String, usize)>,
+}
+
+// one IF/IFDEF/IFNDEF ... ENDIF block being assembled
+struct IfBlock {
+    // where it was opened, for errors about the block
+    file: usize,
+    line: usize,
+    // expansion it was opened in, ELSE and ENDIF have to come from the same one
+    macro_id: Option<u64>,
+    // whether the enclosing code is assembled at all
+    parent_active: bool,
+    // whether one of the branches so far was chosen
+    taken: bool,
+    active: bool,
+    seen_else: bool,
+}
+
 pub struct Assembler{
     memory: [u8; MEMORY_SIZE],
     memory_pointer: usize,
@@ -43,13 +108,26 @@ pub struct Assembler{
     pending_exprs: Vec<PendingExpr>,
     stopped: bool,
     current_line: usize,
-    if_stack: Vec<bool>,
-    in_macro_definition: bool,
-    current_macro_def_name: Option<String>,
+    if_stack: Vec<IfBlock>,
+    definition: Option<Definition>,
     current_macro_scope: Option<MacroScope>,
     next_macro_expansion_id: u64,
-    current_macro: Option<Macro>,
-    in_macro_expansion: bool,
+    // innermost last
+    expansions: Vec<Expansion>,
+    source_map: SourceMap,
+    start_address: Option<u16>,
+    diagnostics: Vec<Diagnostic>,
+    // lines of every assembled file, indexed like the source map files, for diagnostic columns
+    file_lines: Vec<Vec<String>>,
+    // INCLUDE paths of the main text are relative to it, the working directory when unset
+    source_path: Option<PathBuf>,
+    // INCLUDEd files being read, innermost last
+    include_stack: Vec<IncludeFrame>,
+    // source map file of the line being assembled
+    current_file: usize,
+    // addresses already emitted, to spot ORG regions running into each other
+    written: Vec<bool>,
+    referenced_symbols: HashSet<String>,
 }
 
 impl Assembler{
@@ -63,12 +141,19 @@ impl Assembler{
             stopped: false,
             current_line: 0,
             if_stack: Vec::new(),
-            in_macro_definition: false,
-            current_macro_def_name: None,
+            definition: None,
             current_macro_scope: None,
             next_macro_expansion_id: 0,
-            current_macro: None,
-            in_macro_expansion: false,
+            expansions: Vec::new(),
+            source_map: SourceMap::new(),
+            start_address: None,
+            diagnostics: Vec::new(),
+            file_lines: Vec::new(),
+            source_path: None,
+            include_stack: Vec::new(),
+            current_file: 0,
+            written: vec![false; MEMORY_SIZE],
+            referenced_symbols: HashSet::new(),
         }
     }
 
@@ -89,23 +174,21 @@ impl Assembler{
     }
 
     fn parse_operands (char_iter: &mut Peekable<Chars>) -> Vec<String>{
-        let mut is_inside_string = false;
+        // 'strings' and "file names" keep their case and commas
+        let mut quote: Option<char> = None;
         let mut field: String = String::new();
         let mut operands: Vec<String> = Vec::new();
         while let Some(c) = char_iter.next() {
             match c {
-                '\'' => {
+                '\'' | '"' if quote.is_none() || quote == Some(c) => {
                     field.push(c);
-                    is_inside_string = !is_inside_string;
+                    quote = if quote.is_none() { Some(c) } else { None };
                 }
-                ',' if is_inside_string => {
-                    field.push(c);
-                }
-                ',' if !is_inside_string => {
+                ',' if quote.is_none() => {
                     operands.push(field.trim().to_string());
                     field.clear();
                 }
-                _ if is_inside_string => {
+                _ if quote.is_some() => {
                     field.push(c);
                 }
                 _ => {
@@ -236,18 +319,25 @@ impl Assembler{
     }
 
     fn is_compiling(&self) -> bool {
-        self.if_stack.iter().all(|&v| v)
+        self.if_stack.last().is_none_or(|block| block.active)
     }
 
     fn handle_fields(&mut self, label: &Option<String>, instruction: &Option<String>, operands: &Option<Vec<String>>) -> Result<(), TokenOrOverflowError>{
         if let Some(instruction) = instruction {
-            if instruction == "IF" {
-                self.handle_if_instruction(operands)?;
-                return Ok(())
-            }
-            else if instruction == "ENDIF" {
-                self.handle_endif_instruction()?;
-                return Ok(())
+            match instruction.as_str() {
+                "IF" | "IFDEF" | "IFNDEF" => {
+                    self.handle_if_instruction(instruction, operands)?;
+                    return Ok(())
+                }
+                "ELSEIF" | "ELSE" => {
+                    self.handle_else_instruction(instruction, operands)?;
+                    return Ok(())
+                }
+                "ENDIF" => {
+                    self.handle_endif_instruction()?;
+                    return Ok(())
+                }
+                _ => {}
             }
         }
 
@@ -290,15 +380,16 @@ impl Assembler{
                 }
             }
 
+            // errors about the directive itself still have to reach the user
             match self.handle_pseudo_instruction(label, instruction, operands){
                 Ok(_) => { return Ok(()) },
-                Err(e) if e.token_type == TokenType::Instruction => {},
+                Err(e) if e.token_type == TokenType::Instruction && !PSEUDO_INSTRUCTIONS.contains(&instruction.as_str()) => {},
                 Err(e) => return Err(e.into())
             }
 
             match self.handle_macro_expansion(instruction, operands){
                 Ok(_) => { return Ok(()) },
-                Err(e) if e.token_type == TokenType::Instruction => {},
+                Err(e) if e.token_type == TokenType::Instruction && !self.macros.contains_key(instruction) => {},
                 Err(e) => return Err(e.into())
             }
 
@@ -317,68 +408,111 @@ impl Assembler{
         self.current_line = line_number;
         let line = line.trim();
         if line.is_empty() { return Ok(()) }
-        if !line.is_ascii() { return Err(AssemblyError { line_number, line_text: line.into(), message: "Non-ASCII characters found".into() })}
+        if !line.is_ascii() { return Err(self.line_error(line_number, line, None, "Non-ASCII characters found".into()))}
 
         let (label, instruction, operands) = Self::fetch_fields(self, &line);
 
-        if self.in_macro_definition {
-            if let Some(instruction) = instruction && instruction == "ENDM"{
-                self.handle_endm_instruction().map_err(|e| AssemblyError { line_number, line_text: line.into(), message: e.to_string() })?;
-            } else {
-                self.current_macro
-                    .as_mut()
-                    .unwrap()
-                    .body
-                    .push(line.to_string());
+        if let Some(definition) = self.definition.as_mut() {
+            match instruction.as_deref() {
+                Some("ENDM") if definition.depth == 0 => {
+                    if let Err(e) = self.handle_endm_instruction() {
+                        return Err(self.line_error(line_number, line, None, e.to_string()));
+                    }
+                    return Ok(());
+                }
+                Some("ENDM") => definition.depth -= 1,
+                Some(i) if BLOCK_DIRECTIVES.contains(&i) => definition.depth += 1,
+                _ => {}
             }
+            definition.body.push((line.to_string(), line_number));
             return Ok(());
         }
 
+        let start = self.memory_pointer;
+        // a macro call starts its expansion, the call itself still belongs to the enclosing scope
+        let scope = self.current_macro_scope.clone();
         match self.handle_fields(&label, &instruction, &operands) {
-            Ok(_) => {}
+            Ok(_) => self.record_source_range(start, line_number, line, instruction.as_deref(), scope),
             Err(TokenOrOverflowError::Overflow(_)) => {
-                return Err(AssemblyError { line_number, line_text: line.into(), message: "Overflow".into() })
+                // nothing after the end of memory can be assembled
+                self.stopped = true;
+                return Err(self.line_error(line_number, line, None, "Overflow".into()))
             }
             Err(TokenOrOverflowError::InvalidToken(e)) => {
-                return Err(AssemblyError { line_number, line_text: line.into(), message: e.to_string() })
+                return Err(self.line_error(line_number, line, Some(&e.token), e.to_string()))
             }
         }
 
         Ok(())
     }
 
+    fn line_error(&mut self, line_number: usize, line: &str, token: Option<&str>, message: String) -> AssemblyError {
+        self.report(Severity::Error, self.current_file, line_number, token, message.clone());
+        AssemblyError { file: self.file_name(self.current_file), line_number, line_text: line.into(), message }
+    }
+
+    // the span covers the token when it can be found on the line, otherwise the whole statement
+    pub(crate) fn report(&mut self, severity: Severity, file: usize, line_number: usize, token: Option<&str>, message: String) {
+        let text = line_number
+            .checked_sub(1)
+            .and_then(|index| self.file_lines.get(file)?.get(index))
+            .map(String::as_str)
+            .unwrap_or("");
+        let token_start = token
+            .filter(|token| !token.is_empty() && text.is_ascii())
+            .and_then(|token| text.to_ascii_uppercase().find(&token.to_ascii_uppercase()).map(|start| (start, token.len())));
+        let columns = match token_start {
+            Some((start, len)) => start..start + len,
+            None => {
+                let start = text.chars().take_while(|c| c.is_whitespace()).count();
+                start..text.trim_end().chars().count().max(start)
+            }
+        };
+        let file = self.file_name(file);
+        self.diagnostics.push(Diagnostic { file, line_number, columns, severity, message });
+    }
+
+    pub(crate) fn warn(&mut self, token: Option<&str>, message: String) {
+        self.report(Severity::Warning, self.current_file, self.current_line, token, message);
+    }
+
+    // None for the text passed to assemble
+    fn file_name(&self, file: usize) -> Option<String> {
+        (file != 0).then(|| self.source_map.files()[file].clone())
+    }
+
+    // where the text passed to assemble lives, so INCLUDE can resolve paths next to it
+    pub fn set_source_path(&mut self, path: impl Into<PathBuf>) {
+        self.source_path = Some(path.into());
+    }
+
+    // keeps going after a failing line, every problem ends up in diagnostics and the first error is returned
     pub fn assemble (&mut self, data: &str) -> Result<[u8; MEMORY_SIZE], AssemblyError> {
+        self.file_lines = vec![data.lines().map(String::from).collect()];
 
         let mut script_lines = data.lines();
-        let mut macro_lines: Option<std::vec::IntoIter<String>> = None;
         let mut script_line: usize = 0;
 
         while !self.stopped {
-            let next_line: Option<(String, usize)> = if self.in_macro_expansion {
-                if macro_lines.is_none() {
-                    if let Some(current_macro) = self.current_macro.as_ref() {
-                        macro_lines = Some(current_macro.body.clone().into_iter());
-                    } else {
-                        self.in_macro_expansion = false;
-                        self.current_macro_scope = None;
-                    }
+            let next_line: Option<(String, usize)> = if let Some(expansion) = self.expansions.last_mut() {
+                let next_line = expansion.lines.next();
+                if next_line.is_none() {
+                    self.finish_expansion_pass();
                 }
-
-                if let Some(lines) = macro_lines.as_mut() {
-                    if let Some(next_line) = lines.next() {
-                        Some((next_line, script_line))
-                    } else {
-                        macro_lines = None;
-                        self.in_macro_expansion = false;
-                        self.current_macro = None;
-                        self.current_macro_scope = None;
-                        None
-                    }
+                next_line
+            } else if let Some(frame) = self.include_stack.last_mut() {
+                if let Some(next_line) = frame.lines.next() {
+                    frame.line += 1;
+                    self.current_file = frame.file;
+                    Some((next_line, frame.line))
                 } else {
+                    self.close_open_blocks();
+                    self.include_stack.pop();
                     None
                 }
             } else if let Some(next_line) = script_lines.next() {
                 script_line += 1;
+                self.current_file = 0;
                 Some((next_line.to_string(), script_line))
             } else {
                 self.stopped = true;
@@ -386,34 +520,177 @@ impl Assembler{
             };
 
             if let Some((line, line_number)) = next_line {
-                self.handle_line(&line, line_number)?;
+                let _ = self.handle_line(&line, line_number);
             }
         }
 
-        if !self.if_stack.is_empty() {
-            return Err(AssemblyError {
-                line_number: 0,
-                line_text: "".to_string(),
-                message: "Unclosed IF block".into(),
-            });
+        self.current_file = 0;
+        self.close_open_blocks();
+
+        self.resolve_pending_exprs();
+        self.report_unused_labels();
+        // problems found at the end of the source go last, included files after the main text
+        self.diagnostics.sort_by(|a, b| {
+            (a.line_number == 0, &a.file, a.line_number, a.columns.start)
+                .cmp(&(b.line_number == 0, &b.file, b.line_number, b.columns.start))
+        });
+
+        match self.diagnostics.iter().find(|d| d.severity == Severity::Error) {
+            Some(error) => {
+                let file = match &error.file {
+                    Some(name) => self.source_map.files().iter().position(|file| file == name).unwrap_or(0),
+                    None => 0,
+                };
+                Err(AssemblyError {
+                    file: error.file.clone(),
+                    line_number: error.line_number,
+                    line_text: error
+                        .line_number
+                        .checked_sub(1)
+                        .and_then(|index| self.file_lines[file].get(index))
+                        .map(|line| line.trim().to_string())
+                        .unwrap_or_default(),
+                    message: error.message.clone(),
+                })
+            }
+            None => Ok(self.memory),
         }
+    }
 
-        if self.in_macro_definition {
-            return Err(AssemblyError {
-                line_number: 0,
-                line_text: "".to_string(),
-                message: "Unterminated MACRO definition".into(),
-            });
+    // errors and warnings of the last assemble call, ordered by line
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report_unused_labels(&mut self) {
+        // labels local to a macro are stored once per expansion as NAME@id
+        let mut unused: Vec<(usize, String)> = self
+            .symbols
+            .iter()
+            // shared headers define more than any one program uses
+            .filter(|(_, symbol)| symbol.kind == SymbolKind::Label && symbol.file == 0)
+            .map(|(key, symbol)| (symbol.line, key.split('@').next().unwrap_or(key).to_string()))
+            .filter(|(_, name)| !self.referenced_symbols.contains(name))
+            .collect();
+        unused.sort();
+        unused.dedup();
+        for (line, name) in unused {
+            self.report(Severity::Warning, 0, line, Some(&name), format!("Label {} is never used", name));
         }
+    }
 
-        self.resolve_pending_exprs()
-            .map_err(|e| AssemblyError {
-                line_number: e.line,
-                line_text: data.lines().nth(e.line).or_else(|| Some("")).unwrap_or_default().into(),
-                message: e.source.to_string()
-            })?;
+    // which source line produced which bytes, filled in by assemble
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
 
-        Ok(self.memory)
+    // operand of END, if one was given
+    pub fn start_address(&self) -> Option<u16> {
+        self.start_address
+    }
+
+    // bytes actually emitted by code and data statements, overlapping and adjacent ranges merged
+    pub fn segments(&self) -> Vec<Segment> {
+        let mut ranges: Vec<_> = self
+            .source_map
+            .ranges()
+            .iter()
+            .filter(|range| matches!(range.kind, RangeKind::Code | RangeKind::Data))
+            .map(|range| (range.start as usize, range.start as usize + range.len as usize))
+            .collect();
+        ranges.sort();
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+            .into_iter()
+            .map(|(start, end)| Segment { start: start as u16, data: self.memory[start..end].to_vec() })
+            .collect()
+    }
+
+    fn include_file(&mut self, operand: &str) -> Result<(), InvalidTokenError> {
+        let error = |info: String| InvalidTokenError { token: operand.into(), token_type: TokenType::Operand, additional_info: Some(info) };
+        if !self.expansions.is_empty() {
+            return Err(error("INCLUDE cannot be used inside a macro".into()));
+        }
+        let name = operand.trim().trim_matches(|c| c == '"' || c == '\'');
+        let base = match self.include_stack.last() {
+            Some(frame) => frame.path.parent().map(Path::to_path_buf),
+            None => self.source_path.as_deref().and_then(Path::parent).map(Path::to_path_buf),
+        };
+        let path = base.unwrap_or_default().join(name);
+        let path = path.canonicalize().map_err(|e| error(format!("Cannot open {}: {}", path.display(), e)))?;
+
+        let main = self.source_path.as_deref().and_then(|main| main.canonicalize().ok());
+        if main.as_ref() == Some(&path) || self.include_stack.iter().any(|frame| frame.path == path) {
+            return Err(error(format!("{} is already being included", path.display())));
+        }
+        let text = std::fs::read_to_string(&path).map_err(|e| error(format!("Cannot read {}: {}", path.display(), e)))?;
+
+        let file = self.source_map.add_file(name.to_string(), (self.current_file, self.current_line));
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        self.file_lines.push(lines.clone());
+        self.include_stack.push(IncludeFrame { file, path, lines: lines.into_iter(), line: 0 });
+        Ok(())
+    }
+
+    fn record_source_range(
+        &mut self,
+        start: usize,
+        line_number: usize,
+        text: &str,
+        instruction: Option<&str>,
+        scope: Option<MacroScope>,
+    ) {
+        // ORG and macro calls move or keep the pointer without emitting anything themselves,
+        // inside a macro expansion they are still listed
+        let kind = match instruction {
+            Some(i) if INSTRUCTIONS.contains(&i) => RangeKind::Code,
+            Some("DS") => RangeKind::Reserved,
+            Some(i) if DATA_STATEMENTS.contains(&i) => RangeKind::Data,
+            _ if scope.is_some() => RangeKind::Text,
+            _ => return,
+        };
+        let mut end = self.memory_pointer.max(start);
+        if let (Some(first), Some(last)) = (
+            (start..end).find(|&address| self.written[address]),
+            (start..end).rev().find(|&address| self.written[address]),
+        ) {
+            self.report(
+                Severity::Warning,
+                self.current_file,
+                line_number,
+                None,
+                format!("{:04X}H-{:04X}H overlaps an earlier ORG region", first, last),
+            );
+        }
+        if kind == RangeKind::Text {
+            end = start;
+        }
+        self.written[start..end].fill(true);
+        let index = self.source_map.push(SourceRange {
+            start: start as u16,
+            len: (end - start) as u16,
+            file: self.current_file,
+            line: line_number,
+            macro_expansion: scope.map(|scope| MacroExpansion { name: scope.name, id: scope.id }),
+            kind,
+            text: text.to_string(),
+            bytes: match kind {
+                RangeKind::Reserved => Vec::new(),
+                _ => self.memory[start..end].to_vec(),
+            },
+        });
+        // forward references of this line fill in its bytes once they resolve
+        let pending = self.pending_exprs.iter_mut().rev().take_while(|pending| pending.range.is_none());
+        for pending in pending.filter(|pending| (start..end).contains(&pending.addr)) {
+            pending.range = index;
+        }
     }
 
     fn save_values_to_memory(&mut self, values: Vec<u8>) -> Result<(), OverflowError>{
@@ -438,13 +715,13 @@ impl Assembler{
             "INR" => {
                 binary_values.push(0b00000100);
                 let operands = Self::assert_operand_amount(operands, 1)?;
-                let register = Self::parse_register(operands[0].as_str())?;
+                let register = self.parse_register(operands[0].as_str())?;
                 binary_values[0] |= register << 3;
             }
             "DCR" => {
                 binary_values.push(0b00000101);
                 let operands = Self::assert_operand_amount(operands, 1)?;
-                let register = Self::parse_register(operands[0].as_str())?;
+                let register = self.parse_register(operands[0].as_str())?;
                 binary_values[0] |= register << 3;
             }
             "CMA" => binary_values.push(0b00101111),
@@ -453,7 +730,7 @@ impl Assembler{
             "MOV" => {
                 binary_values.push(0b01000000);
                 let operands = Self::assert_operand_amount(operands, 2)?;
-                let (left_register, right_register) = (Self::parse_register(operands[0].as_str())?, Self::parse_register(operands[1].as_str())?);
+                let (left_register, right_register) = (self.parse_register(operands[0].as_str())?, self.parse_register(operands[1].as_str())?);
                 binary_values[0] |= (left_register << 3) | right_register;
             }
             "STAX" | "LDAX" => {
@@ -484,7 +761,7 @@ impl Assembler{
                     _ => unreachable!()
                 }
                 let operands = Self::assert_operand_amount(operands, 1)?;
-                let register = Self::parse_register(operands[0].as_str())?;
+                let register = self.parse_register(operands[0].as_str())?;
                 binary_values[0] |= register;
             }
             "RLC" => binary_values.push(0b00000111),
@@ -542,7 +819,7 @@ impl Assembler{
                 binary_values.push(0b00000110);
                 let operands = Self::assert_operand_amount(operands, 2)?;
                 let (register, operand) = (operands[0].as_str(), operands[1].as_str());
-                let register = Self::parse_register(register)?;
+                let register = self.parse_register(register)?;
                 binary_values[0] |= register << 3;
                 binary_values.push(self.parse_8bit_expr(&operand,1)?);
             }
@@ -627,7 +904,7 @@ impl Assembler{
             "RST" => {
                 binary_values.push(0b11000111);
                 let operands = Self::assert_operand_amount(operands, 1)?;
-                match Self::parse_8bit_number(operands[0].as_str()) {
+                match self.parse_8bit_number(operands[0].as_str()) {
                     Ok(x) => {
                         if x < 8 {
                             binary_values[0] |= x<<3;
@@ -667,7 +944,15 @@ impl Assembler{
                 self.memory_pointer = address as usize;
                 Ok(())
             }
+            "INCLUDE" => {
+                let operands = Self::assert_operand_amount(operands, 1)?;
+                self.include_file(&operands[0])
+            }
             "END" => {
+                if operands.as_ref().is_some_and(|operands| !operands.is_empty()) {
+                    let operands = Self::assert_operand_amount(operands, 1)?;
+                    self.start_address = Some(self.parse_positive_16bit_expr_immediately(operands[0].as_str())?);
+                }
                 self.stopped = true;
                 Ok(())
             }
@@ -713,19 +998,29 @@ impl Assembler{
             "MACRO" => {
                 Ok(self.handle_macro_instruction(label, operands)?)
             }
+            "ENDM" => self.handle_endm_instruction(),
+            "REPT" | "IRP" | "IRPC" => self.handle_repeat_instruction(instruction, operands),
+            "LOCAL" => self.handle_local_instruction(operands),
+            "EXITM" => {
+                if self.expansions.is_empty() {
+                    return Err(InvalidTokenError {
+                        token: instruction.into(),
+                        token_type: TokenType::Instruction,
+                        additional_info: Some("EXITM outside of a macro, REPT, IRP or IRPC".into()),
+                    });
+                }
+                // IF blocks around EXITM end with the expansion
+                let id = self.current_macro_id();
+                self.if_stack.retain(|block| block.macro_id != id);
+                self.expansions.last_mut().unwrap().passes = Vec::new().into_iter();
+                self.finish_expansion_pass();
+                Ok(())
+            }
             _ => Err( InvalidTokenError {token: instruction.into(), token_type:TokenType::Instruction, additional_info: Some("It is not a valid pseudo-instruction".into())})
         }
     }
 
     fn handle_macro_instruction(&mut self, label: &Option<String>, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
-        if self.in_macro_definition {
-            return Err(InvalidTokenError {
-                token: "MACRO".into(),
-                token_type: TokenType::Instruction,
-                additional_info: Some("Nested MACRO definitions are not allowed".into()),
-            });
-        }
-
         let name = self.assert_valid_symbol_name(label, SymbolKind::Macro, &SymbolScope::Global)?;
 
         let mut params = Vec::new();
@@ -739,56 +1034,294 @@ impl Assembler{
             }
         }
 
-        self.in_macro_definition = true;
-        self.current_macro_def_name = Some(name.clone());
-        self.current_macro = Some(Macro {
-            params,
+        self.start_definition(BlockKind::Macro { name, params });
+        Ok(())
+    }
+
+    fn start_definition(&mut self, kind: BlockKind) {
+        self.definition = Some(Definition {
+            kind,
             body: Vec::new(),
+            depth: 0,
+            file: self.current_file,
+            line: self.current_line,
+            expansion: self.current_macro_id(),
         });
+    }
 
+    // REPT n, IRP param,<list> or IRPC param,string
+    fn handle_repeat_instruction(&mut self, instruction: &str, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
+        let passes = match instruction {
+            "REPT" => {
+                let operands = Self::assert_operand_amount(operands, 1)?;
+                let count = self.parse_positive_16bit_expr_immediately(operands[0].as_str())?;
+                vec![Vec::new(); count as usize]
+            }
+            _ => {
+                let operands = operands.as_deref().unwrap_or_default();
+                let (param, values) = operands.split_first().ok_or(InvalidTokenError {
+                    token: instruction.into(),
+                    token_type: TokenType::Operand,
+                    additional_info: Some("Too few operands".into()),
+                })?;
+                self.validate_name(param).map_err(|_| InvalidTokenError {
+                    token: param.clone(),
+                    token_type: TokenType::Operand,
+                    additional_info: Some("Is not a valid parameter name".into()),
+                })?;
+                let values = if instruction == "IRP" {
+                    Self::parse_irp_list(&values.join(","))?
+                } else {
+                    let text = Self::assert_operand_amount(&Some(values.to_vec()), 1)?[0].clone();
+                    let text = match text.strip_prefix('\'').and_then(|text| text.strip_suffix('\'')) {
+                        Some(quoted) => quoted.to_string(),
+                        None => text,
+                    };
+                    text.chars().map(String::from).collect()
+                };
+                values.into_iter().map(|value| vec![(param.clone(), value)]).collect()
+            }
+        };
+
+        self.start_definition(BlockKind::Repeat { directive: instruction.to_string(), passes });
         Ok(())
     }
 
+    // <A,B,C>, an empty list gives no passes
+    fn parse_irp_list(list: &str) -> Result<Vec<String>, InvalidTokenError> {
+        let inner = list
+            .strip_prefix('<')
+            .and_then(|list| list.strip_suffix('>'))
+            .ok_or(InvalidTokenError {
+                token: list.into(),
+                token_type: TokenType::Operand,
+                additional_info: Some("IRP expects a list in angle brackets".into()),
+            })?;
+        if inner.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(inner.split(',').map(|value| value.trim().to_string()).collect())
+    }
+
+    // every LOCAL name gets a unique spelling for the rest of this pass
+    fn handle_local_instruction(&mut self, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
+        let names = operands.as_deref().unwrap_or_default();
+        if names.is_empty() {
+            return Err(InvalidTokenError { token: "LOCAL".into(), token_type: TokenType::Operand, additional_info: Some("Too few operands".into()) });
+        }
+        for name in names {
+            self.validate_name(name)?;
+        }
+        let Some(expansion) = self.expansions.last_mut() else {
+            return Err(InvalidTokenError {
+                token: "LOCAL".into(),
+                token_type: TokenType::Instruction,
+                additional_info: Some("LOCAL outside of a macro, REPT, IRP or IRPC".into()),
+            });
+        };
+
+        let renames: Vec<(String, String)> = names.iter().map(|name| (name.clone(), format!("{}??{}", name, expansion.id))).collect();
+        let lines: Vec<(String, usize)> = std::mem::take(&mut expansion.lines)
+            .map(|(line, number)| (Self::substitute_params(&line, &renames), number))
+            .collect();
+        expansion.lines = lines.into_iter();
+        Ok(())
+    }
+
+    fn substitute_params(line: &str, values: &[(String, String)]) -> String {
+        values
+            .iter()
+            .fold(line.to_string(), |line, (param, value)| Self::replace_param_token(&line, param, value))
+    }
+
     fn handle_endm_instruction(&mut self) -> Result<(), InvalidTokenError>{
-        if !self.in_macro_definition {
+        let Some(definition) = self.definition.take() else {
             return Err(InvalidTokenError {
                 token: "ENDM".into(),
                 token_type: TokenType::Instruction,
                 additional_info: Some("ENDM without matching MACRO".into()),
             });
+        };
+
+        match definition.kind {
+            BlockKind::Macro { name, params } => {
+                let body = definition.body.into_iter().map(|(line, _)| line).collect();
+                self.macros.insert(name, Macro { params, body });
+                Ok(())
+            }
+            BlockKind::Repeat { directive, passes } => {
+                let scope = self.current_macro_scope.clone();
+                self.start_expansion(directive, scope, definition.body, passes)
+            }
+        }
+    }
+
+    fn start_expansion(&mut self, name: String, scope: Option<MacroScope>, body: Vec<(String, usize)>, passes: Vec<Vec<(String, String)>>) -> Result<(), InvalidTokenError>{
+        if self.expansions.len() >= MAX_EXPANSION_DEPTH {
+            return Err(InvalidTokenError {
+                token: name,
+                token_type: TokenType::Instruction,
+                additional_info: Some(format!("Expansions nested more than {} deep", MAX_EXPANSION_DEPTH)),
+            });
         }
+        self.expansions.push(Expansion {
+            name,
+            id: 0,
+            scope,
+            body,
+            passes: passes.into_iter(),
+            lines: Vec::new().into_iter(),
+        });
+        self.start_expansion_pass();
+        Ok(())
+    }
 
-        let name = self.current_macro_def_name.take().unwrap();
-        let mac = self.current_macro.take().unwrap();
+    // the next pass of the innermost expansion, which is dropped once it has none left
+    fn start_expansion_pass(&mut self) {
+        let id = self.next_macro_expansion_id;
+        if let Some(expansion) = self.expansions.last_mut() {
+            match expansion.passes.next() {
+                Some(values) => {
+                    self.next_macro_expansion_id += 1;
+                    expansion.id = id;
+                    let lines: Vec<(String, usize)> = expansion
+                        .body
+                        .iter()
+                        .map(|(line, number)| (Self::substitute_params(line, &values), *number))
+                        .collect();
+                    expansion.lines = lines.into_iter();
+                }
+                None => {
+                    self.expansions.pop();
+                }
+            }
+        }
+        self.current_macro_scope = self.expansions.last().and_then(|expansion| expansion.scope.clone());
+    }
 
-        self.macros.insert(name, mac);
+    fn finish_expansion_pass(&mut self) {
+        self.close_open_blocks();
+        self.start_expansion_pass();
+    }
 
-        self.in_macro_definition = false;
+    fn current_macro_id(&self) -> Option<u64> {
+        self.expansions.last().map(|expansion| expansion.id)
+    }
 
-        Ok(())
+    // the innermost block, as long as it was opened in the file or macro expansion being read
+    fn open_if_block(&mut self, instruction: &str) -> Result<&mut IfBlock, InvalidTokenError> {
+        let (file, macro_id) = (self.current_file, self.current_macro_id());
+        match self.if_stack.last_mut() {
+            Some(block) if block.file == file && block.macro_id == macro_id => Ok(block),
+            Some(_) => Err(InvalidTokenError {
+                token: instruction.into(),
+                token_type: TokenType::Instruction,
+                additional_info: Some(format!("{} cannot close an IF opened outside this file or macro", instruction)),
+            }),
+            None => Err(InvalidTokenError {
+                token: instruction.into(),
+                token_type: TokenType::Instruction,
+                additional_info: Some(format!("{} without matching IF", instruction)),
+            }),
+        }
+    }
+
+    // reports and drops IF blocks and definitions left open by the file or expansion that just ended
+    fn close_open_blocks(&mut self) {
+        let (file, macro_id) = (self.current_file, self.current_macro_id());
+        let inside = self.expansions.last().map(|expansion| format!(" in {}", expansion.name)).unwrap_or_default();
+        while let Some(block) = self.if_stack.last() && block.file == file && block.macro_id == macro_id {
+            let line = block.line;
+            self.if_stack.pop();
+            self.report(Severity::Error, file, line, None, format!("IF without matching ENDIF{}", inside));
+        }
+        if let Some(definition) = self.definition.take_if(|definition| definition.file == file && definition.expansion == macro_id) {
+            let directive = match &definition.kind {
+                BlockKind::Macro { .. } => "MACRO",
+                BlockKind::Repeat { directive, .. } => directive,
+            };
+            let message = format!("{} without matching ENDM{}", directive, inside);
+            self.report(Severity::Error, file, definition.line, None, message);
+        }
     }
 
-    fn handle_if_instruction(&mut self, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
+    fn is_defined(&mut self, name: &str) -> bool {
+        let name = name.to_uppercase();
+        self.referenced_symbols.insert(name.clone());
+        let local = self
+            .current_macro_scope
+            .as_ref()
+            .map(|scope| self.symbol_key_for_scope(&name, &SymbolScope::Local(scope.clone())));
+        local.is_some_and(|key| self.symbols.contains_key(&key))
+            || self.symbols.contains_key(&name)
+            || self.macros.contains_key(&name)
+    }
+
+    fn evaluate_condition(&mut self, instruction: &str, operands: &Option<Vec<String>>) -> Result<bool, InvalidTokenError> {
         let operands = Self::assert_operand_amount(operands, 1)?;
+        if instruction != "IF" && instruction != "ELSEIF" {
+            self.validate_name(&operands[0].to_uppercase())?;
+            return Ok(self.is_defined(&operands[0]) == (instruction == "IFDEF"));
+        }
         let value = self.calculate_expression(&operands[0], 0, false)?
             .ok_or(InvalidTokenError {
                 token: operands[0].clone(),
                 token_type: TokenType::Operand,
-                additional_info: Some("IF does not allow forward referencing".into()),
+                additional_info: Some(format!("{} does not allow forward referencing", instruction)),
             })?;
+        Ok(value != 0)
+    }
 
-        self.if_stack.push(value != 0);
-        Ok(())
+    fn handle_if_instruction(&mut self, instruction: &str, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
+        // conditions inside skipped code are not evaluated, they may refer to symbols that never get defined
+        let parent_active = self.is_compiling();
+        let mut block = IfBlock {
+            file: self.current_file,
+            line: self.current_line,
+            macro_id: self.current_macro_id(),
+            parent_active,
+            taken: false,
+            active: false,
+            seen_else: false,
+        };
+        let condition = if parent_active { self.evaluate_condition(instruction, operands) } else { Ok(false) };
+        // the block is opened even when the condition is bad, so its ENDIF still matches
+        block.taken = *condition.as_ref().unwrap_or(&false);
+        block.active = block.taken;
+        self.if_stack.push(block);
+        condition.map(|_| ())
     }
 
-    fn handle_endif_instruction(&mut self) -> Result<(), InvalidTokenError>{
-        if self.if_stack.is_empty() {
+    fn handle_else_instruction(&mut self, instruction: &str, operands: &Option<Vec<String>>) -> Result<(), InvalidTokenError>{
+        let block = self.open_if_block(instruction)?;
+        if block.seen_else {
+            let line = block.line;
             return Err(InvalidTokenError {
-                token: "ENDIF".into(),
+                token: instruction.into(),
                 token_type: TokenType::Instruction,
-                additional_info: Some("ENDIF without matching IF".into()),
+                additional_info: Some(format!("{} after ELSE of the IF on line {}", instruction, line)),
             });
         }
+        let (parent_active, taken) = (block.parent_active, block.taken);
+
+        let condition = if !parent_active || taken {
+            Ok(false)
+        } else if instruction == "ELSE" {
+            Ok(true)
+        } else {
+            self.evaluate_condition(instruction, operands)
+        };
+        let active = *condition.as_ref().unwrap_or(&false);
+
+        let block = self.if_stack.last_mut().unwrap();
+        block.seen_else = instruction == "ELSE";
+        block.active = active;
+        block.taken |= active;
+        condition.map(|_| ())
+    }
+
+    fn handle_endif_instruction(&mut self) -> Result<(), InvalidTokenError>{
+        self.open_if_block("ENDIF")?;
         self.if_stack.pop();
         Ok(())
     }
@@ -809,8 +1342,9 @@ impl Assembler{
 
                 let mut offset = 0;
                 for operand in operands{
-                    if operand.len() > 3  && operand.starts_with("'") && operand.ends_with("'"){
-                        for char in operand.strip_prefix("'").unwrap().strip_suffix("'").unwrap().chars(){
+                    // a single character or a string inside an expression is a value
+                    if let Some(text) = Self::string_literal(operand) && text.len() > 1 {
+                        for char in text.chars(){
                             if char.is_ascii(){
                                 values.push(char as u8);
                                 offset += 1;
@@ -873,31 +1407,13 @@ impl Assembler{
                     });
                 }
 
-                let mut expanded_macro = mac.clone();
-
-                if params_len > 0 {
-                    let mut lines: Vec<String> = Vec::new();
-                    for origin_line in expanded_macro.body.iter() {
-                        let mut line = origin_line.clone();
-                        for i in 0..params_len {
-                            line = Self::replace_param_token(
-                                &line,
-                                expanded_macro.params[i].as_str(),
-                                operands.as_ref().unwrap()[i].as_str(),
-                            );
-                        }
-                        lines.push(line);
-                    }
-                    expanded_macro.body = lines;
-                }
-
-                self.in_macro_expansion = true;
-                self.current_macro_scope = Some(MacroScope {
+                let values = mac.params.iter().cloned().zip(operands.iter().flatten().cloned()).collect();
+                let body = mac.body.iter().map(|line| (line.clone(), self.current_line)).collect();
+                let scope = MacroScope {
                     name: instruction.to_string(),
                     id: self.next_macro_expansion_id,
-                });
-                self.next_macro_expansion_id += 1;
-                self.current_macro = Some(expanded_macro);
+                };
+                self.start_expansion(format!("macro {}", instruction), Some(scope), body, vec![values])?;
             }
             None => {
                 return Err( InvalidTokenError {token: instruction.into(), token_type:TokenType::Instruction, additional_info: Some("It is not a valid macro".into())}.into())
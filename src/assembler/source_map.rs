@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    Code,
+    // DB and DW
+    Data,
+    // DS, nothing is written to these bytes
+    Reserved,
+    // a label, comment or directive of a macro expansion, kept for the listing only
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroExpansion {
+    pub name: String,
+    // counts expansions in the order they were assembled
+    pub id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRange {
+    pub start: u16,
+    pub len: u16,
+    // index into SourceMap::files
+    pub file: usize,
+    // 1-based, lines coming from a macro body carry the line of the call
+    pub line: usize,
+    pub macro_expansion: Option<MacroExpansion>,
+    pub kind: RangeKind,
+    // the line as assembled, with macro parameters substituted
+    pub text: String,
+    // what the line emitted, later lines overlapping it do not change these
+    pub bytes: Vec<u8>,
+}
+
+impl SourceRange {
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.start && (address - self.start) < self.len
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    // file 0 is the text passed to assemble, the rest are INCLUDE operands
+    files: Vec<String>,
+    // (file, line) of the INCLUDE that brought each file in, None for file 0
+    included_from: Vec<Option<(usize, usize)>>,
+    // in the order they were emitted
+    ranges: Vec<SourceRange>,
+    // start address -> index of the last range emitted there
+    by_address: BTreeMap<u16, usize>,
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap {
+            files: vec![String::new()],
+            included_from: vec![None],
+            ranges: Vec::new(),
+            by_address: BTreeMap::new(),
+        }
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn ranges(&self) -> &[SourceRange] {
+        &self.ranges
+    }
+
+    // INCLUDEd files get the next index
+    pub(super) fn add_file(&mut self, name: String, included_from: (usize, usize)) -> usize {
+        self.files.push(name);
+        self.included_from.push(Some(included_from));
+        self.files.len() - 1
+    }
+
+    pub fn included_from(&self, file: usize) -> Option<(usize, usize)> {
+        self.included_from.get(file).copied().flatten()
+    }
+
+    // files INCLUDEd by that line, in the order they were read
+    pub fn files_included_at(&self, file: usize, line: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.files.len()).filter(move |&index| self.included_from(index) == Some((file, line)))
+    }
+
+    // the line of `outer` a line of an INCLUDEd file sits under, following nested INCLUDEs outwards
+    pub fn enclosing_line(&self, mut file: usize, mut line: usize, outer: usize) -> Option<usize> {
+        while file != outer {
+            (file, line) = self.included_from(file)?;
+        }
+        Some(line)
+    }
+
+    // index of the range, if it was kept
+    pub(super) fn push(&mut self, range: SourceRange) -> Option<usize> {
+        if range.len > 0 {
+            self.by_address.insert(range.start, self.ranges.len());
+        } else if range.kind != RangeKind::Text {
+            return None;
+        }
+        self.ranges.push(range);
+        Some(self.ranges.len() - 1)
+    }
+
+    // a forward reference resolved after its line was recorded
+    pub(super) fn fill(&mut self, index: usize, address: usize, bytes: &[u8]) {
+        let range = &mut self.ranges[index];
+        let offset = address - range.start as usize;
+        range.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn range_at(&self, address: u16) -> Option<&SourceRange> {
+        let (_, &index) = self.by_address.range(..=address).next_back()?;
+        let range = &self.ranges[index];
+        range.contains(address).then_some(range)
+    }
+
+    // (file, line) of the code or data containing the address
+    pub fn line_for_address(&self, address: u16) -> Option<(usize, usize)> {
+        self.range_at(address).map(|range| (range.file, range.line))
+    }
+
+    // first instruction on the line of that file or, for labels and comments, below it,
+    // an INCLUDE line stands for the code of the file it brings in
+    pub fn address_for_line(&self, file: usize, line: usize) -> Option<u16> {
+        self.ranges
+            .iter()
+            .filter(|range| range.kind == RangeKind::Code)
+            .filter_map(|range| Some((self.enclosing_line(range.file, range.line, file)?, range.start)))
+            .filter(|&(range_line, _)| range_line >= line)
+            .min_by_key(|&(range_line, _)| range_line)
+            .map(|(_, start)| start)
+    }
+}
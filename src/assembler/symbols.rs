@@ -14,6 +14,9 @@ pub struct Symbol {
     pub value: i32,
     pub kind: SymbolKind,
     pub symbol_scope: SymbolScope,
+    // where it was defined, for diagnostics
+    pub file: usize,
+    pub line: usize,
 }
 
 #[derive(PartialEq)]
@@ -66,6 +69,7 @@ impl Assembler {
             value: self.memory_pointer as i32,
             kind: SymbolKind::Label,
             symbol_scope: scope,
+            file: self.current_file, line: self.current_line,
         });
 
         Ok(())
@@ -126,25 +130,25 @@ impl Assembler {
                     if let Some(symbol) = self.symbols.get_mut(&local_key) {
                         symbol.value = value;
                     } else {
-                        self.symbols.insert(local_key, Symbol{value, kind: SymbolKind::Set, symbol_scope: SymbolScope::Local(macro_scope.clone())});
+                        self.symbols.insert(local_key, Symbol{value, kind: SymbolKind::Set, symbol_scope: SymbolScope::Local(macro_scope.clone()), file: self.current_file, line: self.current_line});
                     }
                 }
                 SymbolKind::Equ => {
                     let key = self.symbol_key_for_scope(&name, &SymbolScope::Local(macro_scope.clone()));
-                    self.symbols.insert(key, Symbol{value, kind: SymbolKind::Equ, symbol_scope: SymbolScope::Local(macro_scope.clone())});
+                    self.symbols.insert(key, Symbol{value, kind: SymbolKind::Equ, symbol_scope: SymbolScope::Local(macro_scope.clone()), file: self.current_file, line: self.current_line});
                 }
                 //should not be possible, we do nothing
                 SymbolKind::Macro => {panic!()}
                 SymbolKind::Label => {
                     let scope = SymbolScope::Local(macro_scope.clone());
                     let key = self.symbol_key_for_scope(&name, &scope);
-                    self.symbols.insert(key, Symbol{value, kind: SymbolKind::Set, symbol_scope: scope});
+                    self.symbols.insert(key, Symbol{value, kind: SymbolKind::Set, symbol_scope: scope, file: self.current_file, line: self.current_line});
                 }
             }
         } else {
             let scope = SymbolScope::Global;
             let key = self.symbol_key_for_scope(&name, &scope);
-            self.symbols.insert(key, Symbol{value, kind: symbol_kind, symbol_scope: scope });
+            self.symbols.insert(key, Symbol{value, kind: symbol_kind, symbol_scope: scope, file: self.current_file, line: self.current_line });
         }
     }
 
@@ -2,7 +2,7 @@ use super::Assembler;
 use super::errors::{InvalidTokenError, TokenType};
 
 impl Assembler {
-    pub fn parse_register(operand: &str) -> Result<u8, InvalidTokenError>{
+    pub fn parse_register(&mut self, operand: &str) -> Result<u8, InvalidTokenError>{
         let register_in_upper = operand.to_uppercase();
         let register = register_in_upper.as_str();
         match register {
@@ -15,7 +15,7 @@ impl Assembler {
             "M" => Ok(0b110),
             "A" => Ok(0b111),
             _ => {
-                match Self::parse_8bit_number(register){
+                match self.parse_8bit_number(register){
                     Ok(x) => {
                         if x < 8 {
                             Ok(x)
@@ -78,11 +78,16 @@ impl Assembler {
                     Err(InvalidTokenError {
                         token: expr.into(),
                         token_type: TokenType::Operand,
-                        additional_info: Some("Expression does not fit in signed 8 bits".into()),
+                        additional_info: Some("Expression does not fit in 8 bits (-128..255)".into()),
                     })
                 }
             }
-            None => Ok(0),
+            None => {
+                if let Some(pending) = self.pending_exprs.last_mut() {
+                    pending.word = false;
+                }
+                Ok(0)
+            }
         }
     }
 
@@ -103,11 +108,14 @@ impl Assembler {
         i32::from_str_radix(number, radix).map_err(|_| InvalidTokenError { token: value.into(), token_type: TokenType::Operand, additional_info: Some("Only numeric values within valid range with right suffixes are allowed".into())})
     }
 
-    pub fn parse_8bit_number(number: &str) -> Result<u8, InvalidTokenError>{
+    pub fn parse_8bit_number(&mut self, number: &str) -> Result<u8, InvalidTokenError>{
         match Self::parse_number_i32(number){
             Ok(x) => {
                 let v = x as i16;
                 if (i8::MIN as i16..= u8::MAX as i16).contains(&v) {
+                    if v as i32 != x {
+                        self.warn(Some(number), format!("{} is truncated to 8 bits ({:02X}H)", number, v as u8));
+                    }
                     Ok(v as u8)
                 } else {
                     Err(InvalidTokenError { token: number.into(), token_type: TokenType::Operand, additional_info: Some("Only 8-bit numeric values with right suffixes are allowed".into())})
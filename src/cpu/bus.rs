@@ -0,0 +1,179 @@
+use super::MEMORY_SIZE;
+use super::io_handler::IoHandler;
+use super::ppi::Ppi8255;
+use super::serial::SerialPort;
+use super::timer::Pit8253;
+
+const PIT_BASE: u8 = 0x88;
+const PIT_LAST: u8 = 0x8B;
+const PPI_BASE: u8 = 0xA0;
+const PPI_LAST: u8 = 0xA3;
+const SERIAL_BASE: u8 = 0xA4;
+const SERIAL_LAST: u8 = 0xA5;
+const RST7: u8 = 0xFF;
+
+pub trait Bus {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    // None stalls the IN instruction, it is retried on the next step
+    fn input(&mut self, port: u8) -> Option<u8>;
+    fn output(&mut self, port: u8, value: u8);
+
+    fn tick(&mut self, _cycles: u64) {}
+    // vector byte of a device interrupt raised since the last call
+    fn take_interrupt(&mut self) -> Option<u8> {
+        None
+    }
+    fn reset(&mut self) {}
+}
+
+pub struct RamBus {
+    pub(crate) memory: [u8; MEMORY_SIZE],
+}
+
+impl RamBus {
+    pub fn new(memory: [u8; MEMORY_SIZE]) -> Self {
+        RamBus { memory }
+    }
+}
+
+impl Bus for RamBus {
+    fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn input(&mut self, _port: u8) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
+}
+
+pub struct Mcs8Bus {
+    memory: [u8; MEMORY_SIZE],
+    io: IoHandler,
+    pit: Pit8253,
+    ppi: Ppi8255,
+    serial: SerialPort,
+    timer_interrupt: Option<(usize, u8)>,
+}
+
+impl Mcs8Bus {
+    pub fn new(memory: [u8; MEMORY_SIZE]) -> Self {
+        Mcs8Bus {
+            memory,
+            io: IoHandler::new(),
+            pit: Pit8253::new(),
+            ppi: Ppi8255::new(),
+            serial: SerialPort::new(),
+            timer_interrupt: Some((1, RST7)),
+        }
+    }
+
+    pub fn pit(&self) -> &Pit8253 {
+        &self.pit
+    }
+
+    pub fn pit_mut(&mut self) -> &mut Pit8253 {
+        &mut self.pit
+    }
+
+    pub fn ppi(&self) -> &Ppi8255 {
+        &self.ppi
+    }
+
+    pub fn ppi_mut(&mut self) -> &mut Ppi8255 {
+        &mut self.ppi
+    }
+
+    // second 8251, the console one lives in the IoHandler
+    pub fn serial(&self) -> &SerialPort {
+        &self.serial
+    }
+
+    pub fn serial_mut(&mut self) -> &mut SerialPort {
+        &mut self.serial
+    }
+
+    // (counter, vector) raised on the rising edge of that counter's OUT
+    pub fn set_timer_interrupt(&mut self, wiring: Option<(usize, u8)>) {
+        self.timer_interrupt = wiring;
+    }
+
+    pub fn interrupt_source_armed(&self) -> bool {
+        self.timer_interrupt.is_some_and(|(counter, _)| self.pit.is_armed(counter))
+    }
+
+    pub fn io(&self) -> &IoHandler {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut IoHandler {
+        &mut self.io
+    }
+}
+
+impl Bus for Mcs8Bus {
+    fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn input(&mut self, port: u8) -> Option<u8> {
+        if (PIT_BASE..=PIT_LAST).contains(&port) && !self.io.input_aborted() {
+            return Some(self.pit.read(port - PIT_BASE));
+        }
+        if (PPI_BASE..=PPI_LAST).contains(&port) && !self.io.input_aborted() {
+            return Some(self.ppi.read(port - PPI_BASE));
+        }
+        if (SERIAL_BASE..=SERIAL_LAST).contains(&port) && !self.io.input_aborted() {
+            return Some(self.serial.read(port - SERIAL_BASE));
+        }
+        let value = self.io.handle_input(port);
+        if self.io.input_aborted() {
+            self.io.mark_trace_suppress();
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if (PIT_BASE..=PIT_LAST).contains(&port) {
+            self.pit.write(port - PIT_BASE, value);
+            return;
+        }
+        if (PPI_BASE..=PPI_LAST).contains(&port) {
+            self.ppi.write(port - PPI_BASE, value);
+            return;
+        }
+        if (SERIAL_BASE..=SERIAL_LAST).contains(&port) {
+            self.serial.write(port - SERIAL_BASE, value);
+            return;
+        }
+        self.io.handle_output(port, value);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.pit.tick(cycles);
+        self.serial.tick();
+    }
+
+    fn take_interrupt(&mut self) -> Option<u8> {
+        let (counter, vector) = self.timer_interrupt?;
+        self.pit.take_rising_edge(counter).then_some(vector)
+    }
+
+    fn reset(&mut self) {
+        self.pit.reset();
+        self.ppi.reset();
+        self.serial.reset();
+    }
+}
@@ -37,7 +37,7 @@ fn mov_all_register_combinations() {
             let opcode = 0x40 | (dst << 3) | src;
 
             let mut cpu = Cpu::new();
-            cpu.memory[0] = opcode;
+            cpu.bus.memory[0] = opcode;
 
             set_reg(&mut cpu, src, 0xAB);
 
@@ -66,13 +66,13 @@ fn mov_r_from_m() {
         let opcode = 0x46 | (dst << 3);
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.h_reg = 0x12;
         cpu.l_reg = 0x34;
         let addr = 0x1234;
 
-        cpu.memory[addr] = 0xCD;
+        cpu.bus.memory[addr] = 0xCD;
 
         cpu.step();
 
@@ -96,7 +96,7 @@ fn mov_m_from_r() {
         let opcode = 0x70 | src;
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.h_reg = 0x20;
         cpu.l_reg = 0x10;
@@ -107,7 +107,7 @@ fn mov_m_from_r() {
         cpu.step();
 
         assert_eq!(
-            cpu.memory[addr],
+            cpu.bus.memory[addr],
             0xEF,
             "MOV M,r failed: src={}, opcode=0x{:02X}",
             src,
@@ -119,7 +119,7 @@ fn mov_m_from_r() {
 #[test]
 fn mov_m_h(){
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x74;
+    cpu.bus.memory[0] = 0x74;
 
     cpu.h_reg = 0x20;
     cpu.l_reg = 0x10;
@@ -128,7 +128,7 @@ fn mov_m_h(){
     cpu.step();
 
     assert_eq!(
-        cpu.memory[addr],
+        cpu.bus.memory[addr],
         cpu.h_reg,
         "MOV M,H failed, opcode=0x74",
     );
@@ -137,7 +137,7 @@ fn mov_m_h(){
 #[test]
 fn mov_m_l(){
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x75;
+    cpu.bus.memory[0] = 0x75;
 
     cpu.h_reg = 0x20;
     cpu.l_reg = 0x10;
@@ -146,7 +146,7 @@ fn mov_m_l(){
     cpu.step();
 
     assert_eq!(
-        cpu.memory[addr],
+        cpu.bus.memory[addr],
         cpu.l_reg,
         "MOV M,L failed, opcode=0x74",
     );
@@ -162,7 +162,7 @@ fn add_all_registers() {
         let opcode = 0x80 | src; // ADD r
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.a_reg = 0x10;
         set_reg(&mut cpu, src, 0x22);
@@ -182,7 +182,7 @@ fn add_all_registers() {
 #[test]
 fn add_a() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x87;
+    cpu.bus.memory[0] = 0x87;
 
     cpu.a_reg = 0x10;
 
@@ -198,13 +198,13 @@ fn add_a() {
 #[test]
 fn add_m() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x86; // ADD M
+    cpu.bus.memory[0] = 0x86; // ADD M
 
     cpu.a_reg = 0x40;
     cpu.h_reg = 0x12;
     cpu.l_reg = 0x34;
 
-    cpu.memory[0x1234] = 0x20;
+    cpu.bus.memory[0x1234] = 0x20;
 
     cpu.step();
 
@@ -214,7 +214,7 @@ fn add_m() {
 #[test]
 fn add_sets_zero_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x80; // ADD B
+    cpu.bus.memory[0] = 0x80; // ADD B
 
     cpu.a_reg = 0x00;
     cpu.b_reg = 0x00;
@@ -227,7 +227,7 @@ fn add_sets_zero_flag() {
 #[test]
 fn add_sets_sign_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x80; // ADD B
+    cpu.bus.memory[0] = 0x80; // ADD B
 
     cpu.a_reg = 0x40;
     cpu.b_reg = 0x40; // 0x80
@@ -240,7 +240,7 @@ fn add_sets_sign_flag() {
 #[test]
 fn add_sets_carry_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x80; // ADD B
+    cpu.bus.memory[0] = 0x80; // ADD B
 
     cpu.a_reg = 0xF0;
     cpu.b_reg = 0x30; // overflow
@@ -253,7 +253,7 @@ fn add_sets_carry_flag() {
 #[test]
 fn add_sets_auxiliary_carry_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x80; // ADD B
+    cpu.bus.memory[0] = 0x80; // ADD B
 
     cpu.a_reg = 0x0F;
     cpu.b_reg = 0x01;
@@ -269,7 +269,7 @@ fn add_sets_auxiliary_carry_flag() {
 #[test]
 fn add_sets_parity_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x80; // ADD B
+    cpu.bus.memory[0] = 0x80; // ADD B
 
     cpu.a_reg = 0x01;
     cpu.b_reg = 0x01; // result = 0x02 (1 bit set -> odd)
@@ -292,7 +292,7 @@ fn sub_all_registers() {
         let opcode = 0x90 | src; // SUB r
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.a_reg = 0x50;
         set_reg(&mut cpu, src, 0x10);
@@ -312,13 +312,13 @@ fn sub_all_registers() {
 #[test]
 fn sub_m() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x96; // SUB M
+    cpu.bus.memory[0] = 0x96; // SUB M
 
     cpu.a_reg = 0x30;
     cpu.h_reg = 0x12;
     cpu.l_reg = 0x34;
 
-    cpu.memory[0x1234] = 0x20;
+    cpu.bus.memory[0x1234] = 0x20;
 
     cpu.step();
 
@@ -328,7 +328,7 @@ fn sub_m() {
 #[test]
 fn sub_a_8080() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x97; // SUB A
+    cpu.bus.memory[0] = 0x97; // SUB A
 
     cpu.a_reg = 0x3C;
 
@@ -344,7 +344,7 @@ fn sub_a_8080() {
 #[test]
 fn sub_sets_zero_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x90; // SUB B
+    cpu.bus.memory[0] = 0x90; // SUB B
 
     cpu.a_reg = 0x20;
     cpu.b_reg = 0x20;
@@ -357,7 +357,7 @@ fn sub_sets_zero_flag() {
 #[test]
 fn sub_sets_sign_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x90; // SUB B
+    cpu.bus.memory[0] = 0x90; // SUB B
 
     cpu.a_reg = 0x10;
     cpu.b_reg = 0x20; // wynik ujemny
@@ -370,7 +370,7 @@ fn sub_sets_sign_flag() {
 #[test]
 fn sub_sets_carry_flag_on_borrow() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x90; // SUB B
+    cpu.bus.memory[0] = 0x90; // SUB B
 
     cpu.a_reg = 0x10;
     cpu.b_reg = 0x20;
@@ -383,7 +383,7 @@ fn sub_sets_carry_flag_on_borrow() {
 #[test]
 fn sub_sets_auxiliary_carry_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x90; // SUB B
+    cpu.bus.memory[0] = 0x90; // SUB B
 
     cpu.a_reg = 0x10;
     cpu.b_reg = 0x01; // borrow z bitu 4
@@ -399,7 +399,7 @@ fn sub_sets_auxiliary_carry_flag() {
 #[test]
 fn sub_sets_parity_flag() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x90; // SUB B
+    cpu.bus.memory[0] = 0x90; // SUB B
 
     cpu.a_reg = 0x05;
     cpu.b_reg = 0x01; // 0x04 → 1 bit → odd
@@ -422,7 +422,7 @@ fn ana_all_registers() {
         let opcode = 0xA0 | src; // ANA r
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.a_reg = 0b1100_1100;
         set_reg(&mut cpu, src, 0b1010_1010);
@@ -442,12 +442,12 @@ fn ana_all_registers() {
 #[test]
 fn ana_m() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xA6; // ANA M
+    cpu.bus.memory[0] = 0xA6; // ANA M
 
     cpu.a_reg = 0b1111_0000;
     cpu.h_reg = 0x12;
     cpu.l_reg = 0x34;
-    cpu.memory[0x1234] = 0b0011_0011;
+    cpu.bus.memory[0x1234] = 0b0011_0011;
 
     cpu.step();
 
@@ -457,7 +457,7 @@ fn ana_m() {
 #[test]
 fn ana_a() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xA7; // ANA A
+    cpu.bus.memory[0] = 0xA7; // ANA A
 
     cpu.a_reg = 0x5A;
 
@@ -469,7 +469,7 @@ fn ana_a() {
 #[test]
 fn ana_flags() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xA0; // ANA B
+    cpu.bus.memory[0] = 0xA0; // ANA B
 
     cpu.a_reg = 0xF0;
     cpu.b_reg = 0x0F;
@@ -493,7 +493,7 @@ fn ora_all_registers() {
         let opcode = 0xB0 | src; // ORA r
 
         let mut cpu = Cpu::new();
-        cpu.memory[0] = opcode;
+        cpu.bus.memory[0] = opcode;
 
         cpu.a_reg = 0b1100_0000;
         set_reg(&mut cpu, src, 0b0011_0011);
@@ -513,12 +513,12 @@ fn ora_all_registers() {
 #[test]
 fn ora_m() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xB6; // ORA M
+    cpu.bus.memory[0] = 0xB6; // ORA M
 
     cpu.a_reg = 0b1000_0000;
     cpu.h_reg = 0x12;
     cpu.l_reg = 0x34;
-    cpu.memory[0x1234] = 0b0000_1111;
+    cpu.bus.memory[0x1234] = 0b0000_1111;
 
     cpu.step();
 
@@ -528,7 +528,7 @@ fn ora_m() {
 #[test]
 fn ora_a() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xB7; // ORA A
+    cpu.bus.memory[0] = 0xB7; // ORA A
 
     cpu.a_reg = 0x3C;
 
@@ -540,7 +540,7 @@ fn ora_a() {
 #[test]
 fn ora_flags() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0xB0; // ORA B
+    cpu.bus.memory[0] = 0xB0; // ORA B
 
     cpu.a_reg = 0x00;
     cpu.b_reg = 0x00;
@@ -557,7 +557,7 @@ fn ora_flags() {
 #[test]
 fn sbb() {
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x9D;
+    cpu.bus.memory[0] = 0x9D;
     cpu.set_carry_flag(true);
     cpu.l_reg = 0x2;
     cpu.a_reg = 0x4;
@@ -569,7 +569,7 @@ fn sbb() {
 #[test]
 fn daa(){
     let mut cpu = Cpu::new();
-    cpu.memory[0] = 0x27;
+    cpu.bus.memory[0] = 0x27;
     cpu.a_reg = 0x9B;
     cpu.step();
     assert_eq!(cpu.a_reg, 0x1);
@@ -615,13 +615,13 @@ fn call_and_ret_restore_pc() {
     // 0003: 76         HLT
     // 0005: C9         RET
 
-    cpu.memory[0x0000] = 0xCD;
-    cpu.memory[0x0001] = 0x05;
-    cpu.memory[0x0002] = 0x00;
+    cpu.bus.memory[0x0000] = 0xCD;
+    cpu.bus.memory[0x0001] = 0x05;
+    cpu.bus.memory[0x0002] = 0x00;
 
-    cpu.memory[0x0003] = 0x76; // HLT
+    cpu.bus.memory[0x0003] = 0x76; // HLT
 
-    cpu.memory[0x0005] = 0xC9; // RET
+    cpu.bus.memory[0x0005] = 0xC9; // RET
 
     cpu.program_counter = 0x0000;
     cpu.stack_pointer = 0x2000;
@@ -640,7 +640,7 @@ fn rst_acts_like_call() {
     cpu.program_counter = 0x0100;
     cpu.stack_pointer = 0x3000;
 
-    cpu.memory[0x0100] = 0xC7; // RST 0
+    cpu.bus.memory[0x0100] = 0xC7; // RST 0
 
     cpu.step();
 
@@ -715,9 +715,9 @@ fn jz_taken_when_zero() {
 
     cpu.set_zero_flag(true);
 
-    cpu.memory[0] = 0xCA; // JZ
-    cpu.memory[1] = 0x34;
-    cpu.memory[2] = 0x12;
+    cpu.bus.memory[0] = 0xCA; // JZ
+    cpu.bus.memory[1] = 0x34;
+    cpu.bus.memory[2] = 0x12;
 
     cpu.step();
 
@@ -730,9 +730,9 @@ fn jz_not_taken_when_not_zero() {
 
     cpu.set_zero_flag(false);
 
-    cpu.memory[0] = 0xCA;
-    cpu.memory[1] = 0x34;
-    cpu.memory[2] = 0x12;
+    cpu.bus.memory[0] = 0xCA;
+    cpu.bus.memory[1] = 0x34;
+    cpu.bus.memory[2] = 0x12;
 
     cpu.step();
 
@@ -745,9 +745,9 @@ fn jnz_taken_when_not_zero() {
 
     cpu.set_zero_flag(false);
 
-    cpu.memory[0] = 0xC2; // JNZ
-    cpu.memory[1] = 0x78;
-    cpu.memory[2] = 0x56;
+    cpu.bus.memory[0] = 0xC2; // JNZ
+    cpu.bus.memory[1] = 0x78;
+    cpu.bus.memory[2] = 0x56;
 
     cpu.step();
 
@@ -760,9 +760,9 @@ fn jc_taken_when_carry() {
 
     cpu.set_carry_flag(true);
 
-    cpu.memory[0] = 0xDA; // JC
-    cpu.memory[1] = 0x00;
-    cpu.memory[2] = 0x20;
+    cpu.bus.memory[0] = 0xDA; // JC
+    cpu.bus.memory[1] = 0x00;
+    cpu.bus.memory[2] = 0x20;
 
     cpu.step();
 
@@ -775,10 +775,10 @@ fn loop_breaks_when_zero_set() {
 
     // 0000: CMP B
     // 0001: JNZ 0000
-    cpu.memory[0] = 0xB8; // CMP B
-    cpu.memory[1] = 0xC2; // JNZ
-    cpu.memory[2] = 0x00;
-    cpu.memory[3] = 0x00;
+    cpu.bus.memory[0] = 0xB8; // CMP B
+    cpu.bus.memory[1] = 0xC2; // JNZ
+    cpu.bus.memory[2] = 0x00;
+    cpu.bus.memory[3] = 0x00;
 
     cpu.a_reg = 0x10;
     cpu.b_reg = 0x10; // CMP => Z=1
@@ -789,6 +789,1173 @@ fn loop_breaks_when_zero_set() {
     assert_eq!(cpu.program_counter, 0x0004); // loop broken
 }
 
+#[test]
+fn interrupt_ignored_when_disabled() {
+    let mut cpu = Cpu::new();
+
+    cpu.bus.memory[0] = 0xF3; // DI
+    cpu.bus.memory[1] = 0x00; // NOP
+
+    cpu.step();
+    cpu.request_interrupt(0xFF);
+    cpu.step();
+
+    assert_eq!(cpu.program_counter, 0x0002);
+    assert!(cpu.has_pending_interrupt());
+}
+
+#[test]
+fn interrupt_pushes_pc_and_jumps_to_rst_vector() {
+    let mut cpu = Cpu::new();
+
+    cpu.bus.memory[0x0100] = 0x00; // NOP
+    cpu.program_counter = 0x0100;
+    cpu.stack_pointer = 0x0F00;
+
+    cpu.request_interrupt(0xFF); // RST 7
+    let cycles = cpu.step_with_cycles();
+
+    assert_eq!(cycles, 11);
+    assert_eq!(cpu.program_counter, 0x0038);
+    assert_eq!(cpu.stack_pointer, 0x0EFE);
+    assert_eq!(cpu.bus.memory[0x0EFE], 0x00);
+    assert_eq!(cpu.bus.memory[0x0EFF], 0x01);
+    assert!(!cpu.interrupts_enabled());
+    assert!(!cpu.has_pending_interrupt());
+}
+
+#[test]
+fn ei_delays_interrupt_by_one_instruction() {
+    let mut cpu = Cpu::new();
+
+    cpu.bus.memory[0] = 0xF3; // DI
+    cpu.bus.memory[1] = 0xFB; // EI
+    cpu.bus.memory[2] = 0x00; // NOP
+    cpu.bus.memory[3] = 0x00; // NOP
+
+    cpu.step(); // DI
+    cpu.request_interrupt(0xFF);
+    cpu.step(); // EI
+    cpu.step(); // NOP after EI still executes
+
+    assert_eq!(cpu.program_counter, 0x0003);
+
+    cpu.step(); // interrupt acknowledged
+
+    assert_eq!(cpu.program_counter, 0x0038);
+}
+
+#[test]
+fn interrupt_wakes_halted_cpu() {
+    let mut cpu = Cpu::new();
+
+    cpu.bus.memory[0] = 0x76; // HLT
+    cpu.step();
+    assert!(cpu.is_halted());
+
+    cpu.step();
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.program_counter, 0x0001);
+
+    cpu.request_interrupt(0xFF);
+    cpu.step();
+
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.program_counter, 0x0038);
+    assert_eq!(cpu.bus.memory[0x0FFD], 0x01); // return address is past HLT
+}
+
+#[test]
+fn halted_cpu_stays_halted_when_interrupts_disabled() {
+    let mut cpu = Cpu::new();
+
+    cpu.bus.memory[0] = 0xF3; // DI
+    cpu.bus.memory[1] = 0x76; // HLT
+
+    cpu.step();
+    cpu.step();
+    cpu.request_interrupt(0xFF);
+    cpu.step();
+
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.program_counter, 0x0002);
+}
+
+struct PortBus {
+    memory: [u8; MEMORY_SIZE],
+    input: Option<u8>,
+    outputs: Vec<(u8, u8)>,
+}
+
+impl Bus for PortBus {
+    fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn input(&mut self, _port: u8) -> Option<u8> {
+        self.input.take()
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.outputs.push((port, value));
+    }
+}
+
+#[test]
+fn in_and_out_go_through_bus() {
+    let mut memory = [0; MEMORY_SIZE];
+    memory[0] = 0xDB; // IN 84h
+    memory[1] = 0x84;
+    memory[2] = 0xD3; // OUT 85h
+    memory[3] = 0x85;
+    let mut cpu = Cpu::with_bus(PortBus { memory, input: None, outputs: Vec::new() });
+    cpu.a_reg = 0x11;
+
+    assert_eq!(cpu.step_with_cycles(), 0); // stalled, IN is retried
+    assert_eq!(cpu.program_counter, 0x0000);
+    assert_eq!(cpu.a_reg, 0x11);
+
+    cpu.bus_mut().input = Some(0x42);
+    assert_eq!(cpu.step_with_cycles(), 10);
+    assert_eq!(cpu.a_reg, 0x42);
+
+    cpu.step();
+    assert_eq!(cpu.bus().outputs, vec![(0x85, 0x42)]);
+}
+
+#[test]
+fn io_abort_is_per_simulation() {
+    let first = Mcs8Bus::new([0; MEMORY_SIZE]);
+    let second = Mcs8Bus::new([0; MEMORY_SIZE]);
+
+    first.io().control().abort_input_wait();
+
+    assert!(first.io().input_aborted());
+    assert!(!second.io().input_aborted());
+}
+
+#[test]
+fn usart_holds_data_until_transmitter_enabled() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+
+    assert_eq!(usart.write_data(b'A'), None);
+    assert_eq!(usart.read_status() & STATUS_TX_READY, 0);
+
+    assert_eq!(usart.write_control(0xCE), None); // mode: async x16, 8 bits, 2 stop
+    assert_eq!(usart.write_control(0x27), Some(b'A')); // command: TxEN, DTR, RxE, RTS
+    assert_eq!(
+        usart.read_status(),
+        STATUS_TX_READY | STATUS_TX_EMPTY | STATUS_DSR
+    );
+}
+
+#[test]
+fn usart_receive_sets_rx_ready_and_overrun() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+    usart.write_control(0xCE);
+
+    usart.receive(b'x');
+    assert!(!usart.rx_ready()); // receiver disabled
+
+    usart.write_control(0x04); // RxE
+    usart.receive(b'x');
+    usart.receive(b'y');
+    assert_eq!(usart.read_status() & (STATUS_RX_READY | STATUS_OVERRUN_ERROR), STATUS_RX_READY | STATUS_OVERRUN_ERROR);
+    assert_eq!(usart.read_data(), b'y');
+    assert_eq!(usart.read_status() & STATUS_RX_READY, 0);
+
+    usart.write_control(0x14); // RxE + error reset
+    assert_eq!(usart.read_status() & STATUS_OVERRUN_ERROR, 0);
+}
+
+#[test]
+fn usart_masks_characters_to_mode_length() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+    usart.write_control(0x4A); // 7 data bits
+    usart.write_control(0x01);
+
+    assert_eq!(usart.write_data(0xC1), Some(0x41));
+}
+
+#[test]
+fn usart_internal_reset_after_sync_mode_init() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+
+    // usual datasheet reset sequence: three zeros then internal reset
+    for value in [0x00, 0x00, 0x00, 0x40] {
+        assert_eq!(usart.write_control(value), None);
+    }
+    usart.write_control(0x4E); // async mode again
+    usart.write_control(0x01);
+
+    assert_eq!(usart.write_data(b'Z'), Some(b'Z'));
+}
+
+#[test]
+fn usart_checks_parity_and_stop_bits_from_mode_word() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+    usart.write_control(0x7A); // async x16, 7 bits, even parity, 1 stop
+    usart.write_control(0x04); // RxE
+
+    // 'A' has two ones, even parity sends a 0
+    usart.receive_frame(b'A', false, true);
+    assert_eq!(usart.read_status() & (STATUS_PARITY_ERROR | STATUS_FRAMING_ERROR), 0);
+    assert_eq!(usart.read_data(), b'A');
+
+    usart.receive_frame(b'A', true, true);
+    assert_eq!(usart.read_status() & STATUS_PARITY_ERROR, STATUS_PARITY_ERROR);
+    // the character is still assembled
+    assert_eq!(usart.read_data(), b'A');
+
+    usart.receive_frame(b'C', true, false);
+    assert_eq!(usart.read_status() & (STATUS_FRAMING_ERROR | STATUS_SYNC_DETECT), STATUS_FRAMING_ERROR);
+    usart.read_data();
+
+    usart.write_control(0x14); // RxE + error reset
+    usart.receive(b'C');
+    assert_eq!(usart.read_status() & (STATUS_PARITY_ERROR | STATUS_FRAMING_ERROR), 0);
+}
+
+#[test]
+fn usart_detects_break_until_line_marks_again() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+    usart.write_control(0x4E); // async x16, 8 bits, no parity, 1 stop
+    usart.write_control(0x04);
+
+    usart.receive_frame(0x00, false, false);
+    assert_eq!(usart.read_status() & STATUS_SYNC_DETECT, STATUS_SYNC_DETECT);
+    // BRKDET is not cleared by reading the status
+    assert_eq!(usart.read_status() & STATUS_SYNC_DETECT, STATUS_SYNC_DETECT);
+    usart.read_data();
+
+    usart.receive(b'x');
+    assert_eq!(usart.read_status() & STATUS_SYNC_DETECT, 0);
+}
+
+#[test]
+fn usart_sync_mode_hunts_for_sync_characters() {
+    use super::usart::*;
+    let mut usart = Usart8251::new();
+    usart.write_control(0x0C); // sync, 8 bits, two sync characters
+    usart.write_control(0x16);
+    usart.write_control(0x17);
+    usart.write_control(0x84); // EH + RxE
+
+    for value in [0x55, 0x16, 0x55, 0x16] {
+        usart.receive(value);
+    }
+    assert_eq!(usart.read_status() & (STATUS_SYNC_DETECT | STATUS_RX_READY), 0);
+
+    usart.receive(0x17);
+    assert_eq!(usart.read_status() & (STATUS_SYNC_DETECT | STATUS_RX_READY), STATUS_SYNC_DETECT);
+    // SYNDET goes away once read in sync mode
+    assert_eq!(usart.read_status() & STATUS_SYNC_DETECT, 0);
+
+    usart.receive(b'D');
+    assert!(usart.rx_ready());
+    assert_eq!(usart.read_data(), b'D');
+}
+
+#[test]
+fn console_program_writes_through_usart() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0x85, // MVI A,CEh / OUT 85h
+        0x3E, 0x27, 0xD3, 0x85, // MVI A,27h / OUT 85h
+        0x3E, 0x48, 0xD3, 0x84, // MVI A,'H' / OUT 84h
+        0xDB, 0x85, 0xE6, 0x01, // IN 85h / ANI 01h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (output_tx, output_rx) = std::sync::mpsc::channel();
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+    cpu.bus_mut().io_mut().set_output_sender(Some(output_tx));
+
+    cpu.run();
+
+    let output: String = output_rx
+        .try_iter()
+        .map(|event| match event {
+            io_handler::OutputEvent::Append(text) => text,
+            io_handler::OutputEvent::Redraw(text) => text,
+        })
+        .collect();
+    assert_eq!(output, "H");
+    assert_eq!(cpu.a_reg, 0x01);
+}
+
+#[test]
+fn console_waits_for_input_when_polling_rx_ready() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0x85, // MVI A,CEh / OUT 85h
+        0x3E, 0x27, 0xD3, 0x85, // MVI A,27h / OUT 85h
+        0xDB, 0x85, 0xE6, 0x02, // IN 85h / ANI 02h
+        0xCA, 0x08, 0x00, // JZ 0008h
+        0xDB, 0x84, // IN 84h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (input_tx, input_rx) = std::sync::mpsc::channel();
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+    cpu.bus_mut().io_mut().set_input_receiver(Some(input_rx));
+
+    while !cpu.bus().io().is_awaiting_input() {
+        cpu.step();
+    }
+    assert!(!cpu.bus_mut().io_mut().poll_input_ready());
+
+    input_tx.send(b'k').unwrap();
+    assert!(cpu.bus_mut().io_mut().poll_input_ready());
+    assert!(!cpu.bus().io().is_awaiting_input());
+
+    cpu.run();
+    assert_eq!(cpu.a_reg, b'k');
+}
+
+#[test]
+fn stopping_an_input_wait_reports_it_ended() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0x85, // MVI A,CEh / OUT 85h
+        0x3E, 0x27, 0xD3, 0x85, // MVI A,27h / OUT 85h
+        0xDB, 0x85, 0xE6, 0x02, // IN 85h / ANI 02h
+        0xCA, 0x08, 0x00, // JZ 0008h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (status_tx, status_rx) = std::sync::mpsc::channel();
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+    cpu.bus_mut().io_mut().set_input_status_sender(Some(status_tx));
+
+    while !cpu.bus().io().is_awaiting_input() {
+        cpu.step();
+    }
+    assert_eq!(status_rx.try_iter().collect::<Vec<_>>(), vec![true]);
+
+    cpu.bus().io().control().abort_input_wait();
+    assert!(!cpu.bus().io().is_awaiting_input());
+    assert_eq!(status_rx.try_iter().collect::<Vec<_>>(), vec![false]);
+    // nothing more to report when no wait was going on
+    cpu.bus().io().control().reset_io_state();
+    assert_eq!(status_rx.try_iter().count(), 0);
+}
+
+#[test]
+fn console_waits_for_input_behind_a_called_status_poll() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0x85, // MVI A,CEh / OUT 85h
+        0x3E, 0x27, 0xD3, 0x85, // MVI A,27h / OUT 85h
+        0xCD, 0x10, 0x00, // CALL 0010h
+        0xCA, 0x08, 0x00, // JZ 0008h
+        0x76, 0x00, // HLT / NOP
+        0xDB, 0x85, 0xE6, 0x02, // IN 85h / ANI 02h
+        0xC9, // RET
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+
+    // the return addresses pushed by CALL are not console activity
+    for _ in 0..200 {
+        cpu.step();
+    }
+    assert!(cpu.bus().io().is_awaiting_input());
+}
+
+#[test]
+fn pit_mode0_raises_output_on_terminal_count() {
+    use super::timer::Pit8253;
+    let mut pit = Pit8253::new();
+
+    pit.write(3, 0x30); // counter 0, LSB/MSB, mode 0, binary
+    assert!(!pit.output(0));
+    pit.write(0, 0x10);
+    pit.write(0, 0x00);
+
+    pit.tick(0x0F);
+    assert!(!pit.output(0));
+    assert_eq!(pit.read(0), 0x01);
+    assert_eq!(pit.read(0), 0x00);
+
+    pit.tick(1);
+    assert!(pit.output(0));
+    assert!(pit.take_rising_edge(0));
+    assert!(!pit.take_rising_edge(0));
+    assert!(!pit.is_armed(0));
+}
+
+#[test]
+fn pit_mode2_pulses_every_period() {
+    use super::timer::Pit8253;
+    let mut pit = Pit8253::new();
+
+    pit.write(3, 0x54); // counter 1, LSB only, mode 2
+    pit.write(1, 5);
+
+    pit.tick(4);
+    assert!(!pit.output(1));
+    pit.tick(1);
+    assert!(pit.output(1));
+    assert!(pit.take_rising_edge(1));
+
+    pit.tick(12);
+    assert!(pit.take_rising_edge(1));
+    assert_eq!(pit.read(1), 3);
+}
+
+#[test]
+fn pit_mode3_square_wave() {
+    use super::timer::Pit8253;
+    let mut pit = Pit8253::new();
+
+    pit.write(3, 0x96); // counter 2, LSB only, mode 3
+    pit.write(2, 5);
+
+    let levels: Vec<bool> = (0..5)
+        .map(|_| {
+            let level = pit.output(2);
+            pit.tick(1);
+            level
+        })
+        .collect();
+    assert_eq!(levels, vec![true, true, true, false, false]);
+    assert!(pit.take_rising_edge(2));
+}
+
+#[test]
+fn pit_bcd_count_and_latch() {
+    use super::timer::Pit8253;
+    let mut pit = Pit8253::new();
+
+    pit.write(3, 0x31); // counter 0, LSB/MSB, mode 0, BCD
+    pit.write(0, 0x00);
+    pit.write(0, 0x10); // 1000 decimal
+
+    pit.tick(1);
+    pit.write(3, 0x00); // latch counter 0
+    pit.tick(500);
+    assert_eq!(pit.read(0), 0x99);
+    assert_eq!(pit.read(0), 0x09);
+    assert_eq!(pit.read(0), 0x99);
+    assert_eq!(pit.read(0), 0x04);
+}
+
+#[test]
+fn pit_mode1_waits_for_gate_trigger() {
+    use super::timer::Pit8253;
+    let mut pit = Pit8253::new();
+
+    pit.write(3, 0x12); // counter 0, LSB only, mode 1
+    pit.write(0, 3);
+    pit.tick(10);
+    assert!(pit.output(0));
+    assert!(!pit.take_rising_edge(0));
+
+    pit.set_gate(0, false);
+    pit.set_gate(0, true);
+    assert!(!pit.output(0));
+    pit.tick(3);
+    assert!(pit.output(0));
+    assert!(pit.take_rising_edge(0));
+}
+
+#[test]
+fn pit_counter1_wakes_halted_cpu_with_rst7() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0x70, 0xD3, 0x8B, // MVI A,70h / OUT 8Bh
+        0x3E, 0x64, 0xD3, 0x89, // MVI A,100 / OUT 89h
+        0x3E, 0x00, 0xD3, 0x89, // MVI A,0 / OUT 89h
+        0xFB, // EI
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    memory[0x38] = 0x76; // HLT in the RST7 handler
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+
+    cpu.run();
+    assert_eq!(cpu.program_counter, 0x000E);
+
+    while cpu.is_halted() && cpu.program_counter == 0x000E {
+        cpu.step();
+    }
+
+    assert_eq!(cpu.program_counter, 0x0038);
+    assert!(!cpu.interrupts_enabled());
+    assert_eq!(cpu.bus.read(0x0FFD), 0x0E);
+}
+
+#[test]
+fn ppi_mode0_drives_outputs_and_reads_pins() {
+    use super::ppi::{Ppi8255, PpiInput, PORT_A, PORT_B, PORT_C};
+    let mut ppi = Ppi8255::new();
+
+    ppi.write(3, 0x91); // mode 0, A in, B out, C upper out, C lower in
+    ppi.apply_input(PpiInput::Pins(PORT_A, 0x5A));
+    ppi.apply_input(PpiInput::Pins(PORT_C, 0xA3));
+    ppi.write(1, 0x81);
+    ppi.write(2, 0x70);
+
+    assert_eq!(ppi.read(0), 0x5A);
+    assert_eq!(ppi.read(1), 0x81);
+    assert_eq!(ppi.read(2), 0x73);
+
+    let state = ppi.state();
+    assert_eq!(state.input_mask, [0xFF, 0x00, 0x0F]);
+    assert_eq!(state.pins[PORT_B], 0x81);
+    assert_eq!(state.pins[PORT_C], 0x73);
+}
+
+#[test]
+fn ppi_port_c_bit_set_reset() {
+    use super::ppi::{Ppi8255, PORT_C};
+    let mut ppi = Ppi8255::new();
+
+    ppi.write(3, 0x80); // everything output
+    ppi.write(3, 0x0F); // set PC7
+    ppi.write(3, 0x03); // set PC1
+    assert_eq!(ppi.read(2), 0x82);
+    ppi.write(3, 0x0E); // reset PC7
+    assert_eq!(ppi.state().pins[PORT_C], 0x02);
+
+    // a new mode word clears the output latches
+    ppi.write(3, 0x80);
+    assert_eq!(ppi.read(2), 0x00);
+}
+
+#[test]
+fn ppi_mode1_strobed_input_handshake() {
+    use super::ppi::{Ppi8255, PpiInput, PORT_A};
+    let mut ppi = Ppi8255::new();
+
+    ppi.write(3, 0xB0); // group A mode 1 input
+    ppi.write(3, 0x09); // INTE A via PC4
+    assert_eq!(ppi.read(2) & 0x38, 0x10);
+
+    ppi.apply_input(PpiInput::Pins(PORT_A, 0x42));
+    ppi.apply_input(PpiInput::Strobe(PORT_A));
+    ppi.apply_input(PpiInput::Pins(PORT_A, 0x00));
+    assert_eq!(ppi.read(2) & 0x38, 0x38); // IBF, INTE, INTR
+
+    assert_eq!(ppi.read(0), 0x42);
+    assert_eq!(ppi.read(2) & 0x38, 0x10);
+}
+
+#[test]
+fn ppi_mode1_output_handshake() {
+    use super::ppi::{Ppi8255, PpiInput, PORT_B};
+    let mut ppi = Ppi8255::new();
+
+    ppi.write(3, 0x84); // group B mode 1 output
+    ppi.write(3, 0x05); // INTE B via PC2
+    assert_eq!(ppi.read(2) & 0x07, 0x06); // OBF inactive high
+
+    ppi.write(1, 0x99);
+    assert_eq!(ppi.read(2) & 0x07, 0x04); // OBF active low
+    assert_eq!(ppi.state().pins[PORT_B], 0x99);
+
+    ppi.apply_input(PpiInput::Acknowledge(PORT_B));
+    assert_eq!(ppi.read(2) & 0x07, 0x07); // OBF released, INTR raised
+}
+
+#[test]
+fn ppi_is_mapped_at_a0h() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0x90, 0xD3, 0xA3, // MVI A,90h / OUT A3h
+        0x3E, 0x3C, 0xD3, 0xA1, // MVI A,3Ch / OUT A1h
+        0xDB, 0xA0, // IN A0h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+    cpu.bus_mut().ppi_mut().apply_input(super::ppi::PpiInput::Pins(0, 0xC3));
+
+    cpu.run();
+    assert_eq!(cpu.a_reg, 0xC3);
+    assert_eq!(cpu.bus().ppi().state().pins[1], 0x3C);
+}
+
+struct QueueLink {
+    incoming: std::collections::VecDeque<u8>,
+    sent: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+}
+
+impl super::serial::SerialLink for QueueLink {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.incoming.pop_front()
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        self.sent.lock().unwrap().push(value);
+    }
+
+    fn description(&self) -> String {
+        "queue".to_string()
+    }
+}
+
+#[test]
+fn usart1_echoes_through_serial_link() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0xA5, // MVI A,CEh / OUT A5h
+        0x3E, 0x27, 0xD3, 0xA5, // MVI A,27h / OUT A5h
+        0xDB, 0xA5, // wait: IN A5h
+        0xE6, 0x02, // ANI 02h
+        0xCA, 0x08, 0x00, // JZ wait
+        0xDB, 0xA4, // IN A4h
+        0xD3, 0xA4, // OUT A4h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let link = QueueLink { incoming: [b'U'].into(), sent: sent.clone() };
+    let mut bus = Mcs8Bus::new(memory);
+    bus.serial_mut().set_link(Some(Box::new(link)));
+    let mut cpu = Cpu::with_bus(bus);
+
+    cpu.run();
+    assert_eq!(cpu.a_reg, b'U');
+    assert_eq!(*sent.lock().unwrap(), vec![b'U']);
+    assert_eq!(cpu.bus().serial().link_description().as_deref(), Some("queue"));
+}
+
+// takes one character at a time and passes it on only once the test lets it drain
+struct BackedUpLink {
+    queued: Option<u8>,
+    draining: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    sent: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+}
+
+impl super::serial::SerialLink for BackedUpLink {
+    fn read_byte(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        assert!(self.queued.replace(value).is_none(), "written while not clear to send");
+    }
+
+    fn description(&self) -> String {
+        "backed up".to_string()
+    }
+
+    fn poll(&mut self) {
+        if self.draining.load(std::sync::atomic::Ordering::SeqCst) {
+            self.sent.lock().unwrap().extend(self.queued.take());
+        }
+    }
+
+    fn clear_to_send(&self) -> bool {
+        self.queued.is_none()
+    }
+}
+
+#[test]
+fn serial_port_holds_tx_ready_while_the_link_is_backed_up() {
+    use super::usart::{STATUS_TX_EMPTY, STATUS_TX_READY};
+    let draining = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut port = super::serial::SerialPort::new();
+    port.set_link(Some(Box::new(BackedUpLink { queued: None, draining: draining.clone(), sent: sent.clone() })));
+    port.write(1, 0x4E); // mode: async x16, 8 bits, 1 stop
+    port.write(1, 0x05); // command: TxEN, RxE
+
+    // A goes out to the link, B waits in the USART until the link catches up
+    port.write(0, b'A');
+    assert_ne!(port.read(1) & STATUS_TX_READY, 0);
+    port.write(0, b'B');
+    assert_eq!(port.read(1) & (STATUS_TX_READY | STATUS_TX_EMPTY), 0);
+    // a program ignoring TxRDY overwrites the held character instead of queueing more
+    port.write(0, b'C');
+    port.tick();
+    assert_eq!(port.read(1) & STATUS_TX_READY, 0);
+
+    draining.store(true, std::sync::atomic::Ordering::SeqCst);
+    port.tick();
+    port.tick();
+    assert_eq!(port.read(1) & (STATUS_TX_READY | STATUS_TX_EMPTY), STATUS_TX_READY | STATUS_TX_EMPTY);
+    assert_eq!(*sent.lock().unwrap(), b"AC".to_vec());
+}
+
+#[test]
+fn serial_file_link_reads_input_and_writes_output() {
+    let dir = std::env::temp_dir().join(format!("mcs8sim_serial_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.bin");
+    let output = dir.join("out.bin");
+    std::fs::write(&input, b"AB").unwrap();
+
+    let mut link = super::serial::open_files(&input, &output).unwrap();
+    assert_eq!(link.read_byte(), Some(b'A'));
+    assert_eq!(link.read_byte(), Some(b'B'));
+    assert_eq!(link.read_byte(), None);
+    link.write_byte(b'Z');
+    assert_eq!(std::fs::read(&output).unwrap(), b"Z");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn serial_tcp_link_accepts_a_client() {
+    use std::io::{Read, Write};
+
+    let mut link = super::serial::open_tcp(0).unwrap();
+    let port: u16 = link.description().rsplit(':').next().unwrap().parse().unwrap();
+    let mut client = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    client.write_all(b"x").unwrap();
+
+    let mut received = None;
+    for _ in 0..200 {
+        received = link.read_byte();
+        if received.is_some() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert_eq!(received, Some(b'x'));
+
+    link.write_byte(b'y');
+    let mut byte = [0u8; 1];
+    client.read_exact(&mut byte).unwrap();
+    assert_eq!(byte[0], b'y');
+}
+
+#[test]
+fn serial_tcp_link_queues_output_the_client_is_not_reading_yet() {
+    use std::io::Read;
+
+    let mut link = super::serial::open_tcp(0).unwrap();
+    let port: u16 = link.description().rsplit(':').next().unwrap().parse().unwrap();
+    let mut client = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+    // more than the socket buffers hold while nobody reads
+    let data: Vec<u8> = (0..8 * 1024 * 1024).map(|i: u32| (i % 251) as u8).collect();
+    let expected = data.len();
+    let (written_tx, written_rx) = std::sync::mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        written_rx.recv().unwrap();
+        let mut received = vec![0u8; expected];
+        client.read_exact(&mut received).unwrap();
+        received
+    });
+
+    // the link only accepts the client on first use
+    assert_eq!(link.read_byte(), None);
+    for value in &data {
+        link.write_byte(*value);
+    }
+    written_tx.send(()).unwrap();
+    // what the socket could not take goes out on later polls
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+    while !reader.is_finished() && std::time::Instant::now() < deadline {
+        link.poll();
+    }
+    let received = reader.join().unwrap();
+    assert!(received == data);
+}
+
+fn wait_for_breakpoint(
+    events: &std::sync::mpsc::Receiver<simulation_controller::SimulationEvent>,
+) -> (u16, CpuState) {
+    use simulation_controller::SimulationEvent;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut hit = None;
+    while std::time::Instant::now() < deadline {
+        match events.recv_timeout(std::time::Duration::from_millis(100)) {
+            Ok(SimulationEvent::BreakpointHit(address)) => hit = Some(address),
+            Ok(SimulationEvent::CpuState(state)) if hit.is_some() => {
+                return (hit.unwrap(), state);
+            }
+            _ => {}
+        }
+    }
+    panic!("no breakpoint hit");
+}
+
+#[test]
+fn run_stops_before_breakpoint_and_resumes_past_it() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3C, // loop: INR A
+        0x00, // NOP
+        0x00, // NOP
+        0xC3, 0x00, 0x00, // JMP loop
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    controller.set_breakpoints(vec![0x0002]);
+    controller.run();
+    let (address, state) = wait_for_breakpoint(&event_rx);
+    assert_eq!(address, 0x0002);
+    assert_eq!(state.program_counter, 0x0002);
+    assert_eq!(state.a, 1);
+
+    controller.run();
+    let (_, state) = wait_for_breakpoint(&event_rx);
+    assert_eq!(state.program_counter, 0x0002);
+    assert_eq!(state.a, 2);
+
+    controller.toggle_breakpoint(0x0002);
+    controller.toggle_breakpoint(0x0003);
+    controller.run();
+    let (address, _) = wait_for_breakpoint(&event_rx);
+    assert_eq!(address, 0x0003);
+    controller.stop();
+}
+
+#[test]
+fn run_keeps_stepping_while_the_console_waits_for_input() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0xCE, 0xD3, 0x85, // MVI A,CEh / OUT 85h
+        0x3E, 0x27, 0xD3, 0x85, // MVI A,27h / OUT 85h
+        0x11, 0x00, 0x00, // LXI D,0
+        0xDB, 0x85, 0xE6, 0x02, // loop: IN 85h / ANI 02h
+        0xC2, 0x1A, 0x00, // JNZ 001Ah
+        0x13, 0x7A, // INX D / MOV A,D
+        0xFE, 0x40, // CPI 40h
+        0xC2, 0x0B, 0x00, // JNZ loop
+        0x76, 0x76, // HLT / HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    // the count runs on, over several batches, past the polls that mark the console as waiting
+    controller.set_breakpoints(vec![0x0019]);
+    controller.run();
+    let (address, state) = wait_for_breakpoint(&event_rx);
+    assert_eq!(address, 0x0019);
+    assert_eq!((state.d, state.e), (0x40, 0x00));
+    controller.stop();
+}
+
+#[test]
+fn watchpoint_reports_write_with_old_and_new_value() {
+    use super::watch::{WatchHit, WatchKind, Watchpoint};
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x21, 0x34, 0x12, // LXI H,1234h
+        0x22, 0x00, 0x08, // SHLD 0800h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    memory[0x0801] = 0xAA;
+    let mut cpu = Cpu::with_memory(memory);
+    cpu.set_watchpoints(vec![Watchpoint::new(0x0801, 0x0801, WatchKind::Write)]);
+
+    cpu.step();
+    assert_eq!(cpu.take_watch_hit(), None);
+    cpu.step();
+    assert_eq!(
+        cpu.take_watch_hit(),
+        Some(WatchHit { pc: 0x0003, address: 0x0801, kind: WatchKind::Write, old_value: 0xAA, new_value: 0x12 })
+    );
+}
+
+#[test]
+fn watchpoint_ignores_operand_fetches_and_filters_kind() {
+    use super::watch::{WatchKind, Watchpoint};
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x21, 0x00, 0x09, // LXI H,0900h
+        0x7E, // MOV A,M
+        0x77, // MOV M,A
+        0xC5, // PUSH B
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    memory[0x0900] = 0x5A;
+    let mut cpu = Cpu::with_memory(memory);
+    cpu.set_watchpoints(vec![
+        Watchpoint::new(0x0000, 0x0006, WatchKind::Access),
+        Watchpoint::new(0x0900, 0x0900, WatchKind::Read),
+        Watchpoint::new(0x0FFE, 0x0FFD, WatchKind::Write),
+    ]);
+
+    cpu.step();
+    assert_eq!(cpu.take_watch_hit(), None);
+    cpu.step();
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.pc, hit.address, hit.kind, hit.new_value), (0x0003, 0x0900, WatchKind::Read, 0x5A));
+    cpu.step();
+    assert_eq!(cpu.take_watch_hit(), None);
+    cpu.step();
+    let hit = cpu.take_watch_hit().unwrap();
+    assert_eq!((hit.pc, hit.address, hit.kind), (0x0005, 0x0FFE, WatchKind::Write));
+}
+
+#[test]
+fn run_stops_after_watched_write() {
+    use super::watch::{WatchKind, Watchpoint};
+    use simulation_controller::SimulationEvent;
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3C, // loop: INR A
+        0x32, 0x00, 0x08, // STA 0800h
+        0xC3, 0x00, 0x00, // JMP loop
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    controller.set_watchpoints(vec![Watchpoint::new(0x0800, 0x0800, WatchKind::Write)]);
+    controller.run();
+    let hit = loop {
+        match event_rx.recv_timeout(std::time::Duration::from_secs(5)) {
+            Ok(SimulationEvent::WatchpointHit(hit)) => break hit,
+            Ok(_) => {}
+            Err(_) => panic!("no watchpoint hit"),
+        }
+    };
+    assert_eq!((hit.pc, hit.old_value, hit.new_value), (0x0001, 0x00, 0x01));
+    controller.stop();
+}
+
+#[test]
+fn port_accesses_are_logged_and_trigger_port_breakpoints() {
+    use super::watch::{PortAccess, PortBreakpoint, WatchKind};
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3E, 0x80, // MVI A,80h
+        0xD3, 0xA3, // OUT A3h
+        0x3E, 0x55, // MVI A,55h
+        0xD3, 0xA0, // OUT A0h
+        0xDB, 0xA0, // IN A0h
+        0x76, // HLT
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::with_bus(Mcs8Bus::new(memory));
+    cpu.set_port_breakpoints(vec![PortBreakpoint { port: 0xA0, kind: WatchKind::Read }]);
+
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.take_port_hit(), None);
+    assert!(cpu.take_io_log().is_empty());
+
+    cpu.set_io_logging(true);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.take_port_hit(), None);
+    cpu.step();
+    let hit = cpu.take_port_hit().unwrap();
+    assert_eq!((hit.pc, hit.kind, hit.port, hit.value), (0x0008, WatchKind::Read, 0xA0, 0x55));
+
+    let log = cpu.take_io_log();
+    assert_eq!(log.len(), 2);
+    assert_eq!(
+        log[0],
+        PortAccess { cycle: log[0].cycle, pc: 0x0006, kind: WatchKind::Write, port: 0xA0, value: 0x55 }
+    );
+    assert_eq!(log[1], hit);
+    assert!(log[0].cycle < log[1].cycle);
+    assert!(cpu.take_io_log().is_empty());
+}
+
+#[test]
+fn run_stops_on_port_breakpoint() {
+    use super::watch::{PortBreakpoint, WatchKind};
+    use simulation_controller::SimulationEvent;
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3C, // loop: INR A
+        0xD3, 0xA0, // OUT A0h
+        0xC3, 0x00, 0x00, // JMP loop
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    controller.set_port_breakpoints(vec![PortBreakpoint { port: 0xA0, kind: WatchKind::Write }]);
+    controller.run();
+    let access = loop {
+        match event_rx.recv_timeout(std::time::Duration::from_secs(5)) {
+            Ok(SimulationEvent::PortBreakpointHit(access)) => break access,
+            Ok(_) => {}
+            Err(_) => panic!("no port breakpoint hit"),
+        }
+    };
+    assert_eq!((access.pc, access.port, access.value), (0x0001, 0xA0, 0x01));
+    controller.stop();
+}
+
+#[test]
+fn conditional_breakpoint_stops_only_when_condition_is_met() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x3C, // loop: INR A
+        0x00, // NOP
+        0xC3, 0x00, 0x00, // JMP loop
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+    let assembler = crate::assembler::Assembler::new();
+
+    let condition = assembler.parse_condition("A = 10H OR A = 30H").unwrap();
+    controller.set_breakpoint_condition(0x0001, Some(condition));
+    controller.run();
+    let (address, state) = wait_for_breakpoint(&event_rx);
+    assert_eq!((address, state.a), (0x0001, 0x10));
+
+    controller.run();
+    let (_, state) = wait_for_breakpoint(&event_rx);
+    assert_eq!(state.a, 0x30);
+    controller.stop();
+}
+
+fn wait_for_target(
+    events: &std::sync::mpsc::Receiver<simulation_controller::SimulationEvent>,
+) -> (u16, CpuState) {
+    use simulation_controller::SimulationEvent;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut reached = None;
+    while std::time::Instant::now() < deadline {
+        match events.recv_timeout(std::time::Duration::from_millis(100)) {
+            Ok(SimulationEvent::TargetReached(address)) => reached = Some(address),
+            Ok(SimulationEvent::CpuState(state)) if reached.is_some() => {
+                return (reached.unwrap(), state);
+            }
+            _ => {}
+        }
+    }
+    panic!("run target not reached");
+}
+
+#[test]
+fn step_over_step_out_and_run_to_stop_at_their_targets() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x31, 0x00, 0x08, // LXI SP,0800h
+        0xCD, 0x10, 0x00, // CALL 0010h
+        0x04, // INR B
+        0xC3, 0x07, 0x00, // JMP $
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    memory[0x0010..0x0015].copy_from_slice(&[
+        0x3C, // INR A
+        0xCD, 0x20, 0x00, // CALL 0020h
+        0xC9, // RET
+    ]);
+    memory[0x0020..0x0022].copy_from_slice(&[
+        0x0C, // INR C
+        0xC9, // RET
+    ]);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    controller.step();
+    controller.step_over();
+    let (address, state) = wait_for_target(&event_rx);
+    assert_eq!(address, 0x0006);
+    assert_eq!((state.a, state.c, state.stack_pointer), (1, 1, 0x0800));
+
+    controller.reset();
+    controller.run_to(0x0020);
+    let (address, state) = wait_for_target(&event_rx);
+    assert_eq!((address, state.a, state.c), (0x0020, 1, 0));
+
+    controller.step_out();
+    let (address, state) = wait_for_target(&event_rx);
+    assert_eq!((address, state.c, state.stack_pointer), (0x0014, 1, 0x07FE));
+
+    controller.step_out();
+    let (address, state) = wait_for_target(&event_rx);
+    assert_eq!((address, state.b, state.stack_pointer), (0x0006, 0, 0x0800));
+    controller.stop();
+}
+
+#[test]
+fn step_out_after_a_push_returns_to_the_caller() {
+    let mut memory = [0; MEMORY_SIZE];
+    let program = [
+        0x31, 0x00, 0x08, // LXI SP,0800h
+        0xCD, 0x10, 0x00, // CALL 0010h
+        0x04, // INR B
+        0xC3, 0x07, 0x00, // JMP $
+    ];
+    memory[..program.len()].copy_from_slice(&program);
+    memory[0x0010..0x0016].copy_from_slice(&[
+        0xC5, // PUSH B
+        0xC1, // POP B
+        0xCD, 0x20, 0x00, // CALL 0020h
+        0xC9, // RET
+    ]);
+    memory[0x0020..0x0022].copy_from_slice(&[
+        0x0C, // INR C
+        0xC9, // RET
+    ]);
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let controller = simulation_controller::SimulationController::new(
+        Cpu::with_bus(Mcs8Bus::new(memory)),
+        None,
+        event_tx,
+        false,
+        None,
+    );
+
+    controller.run_to(0x0011);
+    let (_, state) = wait_for_target(&event_rx);
+    assert_eq!(state.stack_pointer, 0x07FC);
+
+    // the nested RET leaves the stack above where Step Out was pressed but returns into the routine
+    controller.step_out();
+    let (address, state) = wait_for_target(&event_rx);
+    assert_eq!((address, state.c, state.stack_pointer), (0x0006, 1, 0x0800));
+    controller.stop();
+}
+
 // #[test]
 // fn cpu_runs_mcs8_bios() {
 //     use std::fs;
@@ -798,7 +1965,7 @@ fn loop_breaks_when_zero_set() {
 //     let bios = fs::read("src/bios.bin")
 //         .expect("cannot read bios.bin");
 //
-//     cpu.memory[0x0000..bios.len()].copy_from_slice(&bios);
+//     cpu.bus.memory[0x0000..bios.len()].copy_from_slice(&bios);
 //     cpu.program_counter = 0x0000;
 //
 //     let mut steps: u64 = 0;
@@ -1,355 +1,275 @@
-use std::cell::RefCell;
 use std::io::{self, Write};
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use crate::encoding;
+use super::usart::{STATUS_RX_READY, Usart8251};
 
 const TERM_COLS: usize = 80;
 const TERM_ROWS: usize = 40;
 const TAB_WIDTH: usize = 4;
 
+
+// consecutive RxRDY polls with no other console activity before the console shows as waiting
+const AWAIT_INPUT_POLLS: u32 = 16;
+
 #[derive(Debug, Clone)]
 pub enum OutputEvent {
     Append(String),
     Redraw(String),
 }
 
-thread_local! {
-    static OUTPUT_SENDER: RefCell<Option<Sender<OutputEvent>>> = RefCell::new(None);
-    static INPUT_RECEIVER: RefCell<Option<Receiver<u8>>> = RefCell::new(None);
-    static INPUT_STATUS_SENDER: RefCell<Option<Sender<bool>>> = RefCell::new(None);
-    static TERMINAL_STATE: RefCell<TerminalState> = RefCell::new(TerminalState::new());
+#[derive(Debug, Default)]
+pub struct IoControl {
+    reset_pending: AtomicBool,
+    input_aborted: AtomicBool,
+    input_awaiting: AtomicBool,
+    // shared so Stop and Reset from the GUI thread can end the waiting indicator too
+    input_status_sender: Mutex<Option<Sender<bool>>>,
 }
 
-static PORT0XA4: AtomicU8 = AtomicU8::new(b'3');
-static PORT0XA0: AtomicU8 = AtomicU8::new(b'4');
-static PORT0X88: AtomicU8 = AtomicU8::new(b'5');
-
-const ABORT_NONE: u8 = 0;
-const ABORT_STOP: u8 = 1;
-const ABORT_RESET: u8 = 2;
-static INPUT_ABORT: AtomicU8 = AtomicU8::new(ABORT_NONE);
-static IO_RESET_PENDING: AtomicBool = AtomicBool::new(false);
-static INPUT_ABORTED: AtomicBool = AtomicBool::new(false);
-static INPUT_RETRY: AtomicBool = AtomicBool::new(false);
-static INPUT_AWAITING: AtomicBool = AtomicBool::new(false);
-static TRACE_SUPPRESS: AtomicBool = AtomicBool::new(false);
-
-#[derive(Debug, Clone, Copy)]
-struct Usart0State {
-    pending_command_27: bool,
-    input_mode: bool,
-    status: u8,
-    input_ready: bool,
-    input_data: u8,
-}
+impl IoControl {
+    pub fn abort_input_wait(&self) {
+        self.input_aborted.store(true, Ordering::SeqCst);
+        self.stop_awaiting_input();
+    }
 
-impl Usart0State {
-    fn new() -> Self {
-        Self {
-            pending_command_27: false,
-            input_mode: false,
-            status: 0,
-            input_ready: false,
-            input_data: 0,
+    pub fn reset_io_state(&self) {
+        self.reset_pending.store(true, Ordering::SeqCst);
+        self.input_aborted.store(true, Ordering::SeqCst);
+        self.stop_awaiting_input();
+    }
+
+    fn stop_awaiting_input(&self) {
+        if self.input_awaiting.swap(false, Ordering::SeqCst) {
+            self.send_input_status(false);
         }
     }
-}
 
-thread_local! {
-    static USART0_STATE: RefCell<Usart0State> = RefCell::new(Usart0State::new());
-}
+    fn send_input_status(&self, waiting: bool) {
+        if let Some(sender) = self.input_status_sender.lock().unwrap().as_ref() {
+            let _ = sender.send(waiting);
+        }
+    }
 
-pub fn set_output_sender(sender: Option<Sender<OutputEvent>>) {
-    OUTPUT_SENDER.with(|cell| {
-        *cell.borrow_mut() = sender;
-    });
-}
+    pub fn input_aborted(&self) -> bool {
+        self.input_aborted.load(Ordering::SeqCst)
+    }
 
-pub fn set_input_receiver(receiver: Option<Receiver<u8>>) {
-    INPUT_RECEIVER.with(|cell| {
-        *cell.borrow_mut() = receiver;
-    });
-}
+    pub fn clear_input_aborted(&self) -> bool {
+        self.input_aborted.swap(false, Ordering::SeqCst)
+    }
 
-pub fn set_input_status_sender(sender: Option<Sender<bool>>) {
-    INPUT_STATUS_SENDER.with(|cell| {
-        *cell.borrow_mut() = sender;
-    });
-}
+    pub fn is_awaiting_input(&self) -> bool {
+        self.input_awaiting.load(Ordering::SeqCst)
+    }
 
-pub fn init_for_new_sim() {
-    INPUT_ABORT.store(ABORT_NONE, Ordering::SeqCst);
-    IO_RESET_PENDING.store(false, Ordering::SeqCst);
-    INPUT_ABORTED.store(false, Ordering::SeqCst);
-    INPUT_RETRY.store(false, Ordering::SeqCst);
-    INPUT_AWAITING.store(false, Ordering::SeqCst);
-    TRACE_SUPPRESS.store(false, Ordering::SeqCst);
-    USART0_STATE.with(|cell| {
-        *cell.borrow_mut() = Usart0State::new();
-    });
-    send_input_status(false);
+    fn clear(&self) {
+        self.reset_pending.store(false, Ordering::SeqCst);
+        self.input_aborted.store(false, Ordering::SeqCst);
+        self.input_awaiting.store(false, Ordering::SeqCst);
+    }
 }
 
-pub fn abort_input_wait() {
-    INPUT_ABORT.store(ABORT_STOP, Ordering::SeqCst);
-    INPUT_ABORTED.store(true, Ordering::SeqCst);
-    INPUT_RETRY.store(false, Ordering::SeqCst);
-    INPUT_AWAITING.store(false, Ordering::SeqCst);
-    send_input_status(false);
+pub struct IoHandler {
+    control: Arc<IoControl>,
+    output_sender: Option<Sender<OutputEvent>>,
+    input_receiver: Option<Receiver<u8>>,
+    terminal: TerminalState,
+    console: Usart8251,
+    idle_polls: u32,
+    trace_suppress: bool,
 }
 
-pub fn reset_io_state() {
-    INPUT_ABORT.store(ABORT_RESET, Ordering::SeqCst);
-    IO_RESET_PENDING.store(true, Ordering::SeqCst);
-    INPUT_ABORTED.store(true, Ordering::SeqCst);
-    INPUT_RETRY.store(false, Ordering::SeqCst);
-    INPUT_AWAITING.store(false, Ordering::SeqCst);
+impl Default for IoHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub fn handle_output(device: u8, value: u8) {
-    let _ = apply_pending_reset();
-    match device {
-        0x84 => {
-            USART0_STATE.with(|cell| {
-                let mut state = cell.borrow_mut();
-                if state.pending_command_27 {
-                    state.pending_command_27 = false;
-                    state.input_mode = false;
-                    state.status = 1;
-                }
-            });
-            let event = TERMINAL_STATE.with(|cell| cell.borrow_mut().process_byte(value));
-            if let Some(event) = event {
-                emit_output_event(event);
-            }
-
-            // PORT0X84.store(value, Ordering::Relaxed);
-        }
-        0x85 => {
-            if value == 0x27 {
-                USART0_STATE.with(|cell| {
-                    let mut state = cell.borrow_mut();
-                    state.pending_command_27 = true;
-                    state.input_ready = false;
-                    state.input_mode = false;
-                    state.status = 0;
-                });
-                INPUT_RETRY.store(false, Ordering::SeqCst);
-                INPUT_AWAITING.store(false, Ordering::SeqCst);
-            }
+impl IoHandler {
+    pub fn new() -> Self {
+        Self {
+            control: Arc::new(IoControl::default()),
+            output_sender: None,
+            input_receiver: None,
+            terminal: TerminalState::new(),
+            console: Usart8251::new(),
+            idle_polls: 0,
+            trace_suppress: false,
         }
-        _ => {}
     }
-}
 
-pub fn handle_input(device: u8) -> u8 {
-    if apply_pending_reset() {
-        INPUT_ABORTED.store(true, Ordering::SeqCst);
-        return 0x01;
-    }
-    match device {
-        0x85 => {
-            let (status, needs_input) = USART0_STATE.with(|cell| {
-                let state = cell.borrow();
-                if state.input_ready {
-                    return (2, false);
-                }
-                if state.input_mode {
-                    return (2, false);
-                }
-                if state.pending_command_27 {
-                    return (2, true);
-                }
-                (state.status, false)
-            });
-
-            if needs_input {
-                USART0_STATE.with(|cell| {
-                    let mut state = cell.borrow_mut();
-                    state.pending_command_27 = false;
-                    state.input_mode = true;
-                    state.status = 2;
-                });
-                return 2;
-            }
+    pub fn control(&self) -> Arc<IoControl> {
+        Arc::clone(&self.control)
+    }
 
-            status
-        },
-        0x84 => {
-            let mut immediate = None;
-            let mut needs_input = false;
-            USART0_STATE.with(|cell| {
-                let mut state = cell.borrow_mut();
-                if state.input_ready {
-                    immediate = Some(state.input_data);
-                    state.input_ready = false;
-                    state.input_mode = false;
-                    state.status = 0;
-                    return;
-                }
-                if state.pending_command_27 {
-                    state.pending_command_27 = false;
-                    state.input_mode = true;
-                    needs_input = true;
-                }
-            });
+    pub fn set_output_sender(&mut self, sender: Option<Sender<OutputEvent>>) {
+        self.output_sender = sender;
+    }
 
-            if let Some(value) = immediate {
-                INPUT_RETRY.store(false, Ordering::SeqCst);
-                INPUT_AWAITING.store(false, Ordering::SeqCst);
-                send_input_status(false);
-                return value;
-            }
+    pub fn set_input_receiver(&mut self, receiver: Option<Receiver<u8>>) {
+        self.input_receiver = receiver;
+    }
 
-            if INPUT_AWAITING.load(Ordering::SeqCst) {
-                INPUT_RETRY.store(true, Ordering::SeqCst);
-                return 0x01;
-            }
+    pub fn set_input_status_sender(&mut self, sender: Option<Sender<bool>>) {
+        *self.control.input_status_sender.lock().unwrap() = sender;
+    }
 
-            if needs_input || USART0_STATE.with(|cell| cell.borrow().input_mode) {
-                INPUT_RETRY.store(true, Ordering::SeqCst);
-                INPUT_AWAITING.store(true, Ordering::SeqCst);
-                send_input_status(true);
-                return 0x01;
-            }
+    pub fn init_for_new_sim(&mut self) {
+        self.control.clear();
+        self.trace_suppress = false;
+        self.console.reset();
+        self.idle_polls = 0;
+        self.send_input_status(false);
+    }
 
-            0x01
-        },
-        0xA4 => {
-            handle_output(0x84, PORT0XA4.load(Ordering::Relaxed));
-            PORT0XA4.load(Ordering::Relaxed) },
-        0xA0 => {
-            handle_output(0x84, PORT0XA0.load(Ordering::Relaxed));
-            PORT0XA0.load(Ordering::Relaxed)
-        },
-        0x88 => {
-            handle_output(0x84, PORT0X88.load(Ordering::Relaxed));
-            PORT0X88.load(Ordering::Relaxed)
-        },
-        _ => 0x01,
+    pub fn handle_output(&mut self, device: u8, value: u8) {
+        let _ = self.apply_pending_reset();
+        self.note_console_activity();
+        match device {
+            0x84 => {
+                if let Some(byte) = self.console.write_data(value) {
+                    self.transmit_console_byte(byte);
+                }
+            }
+            0x85 => {
+                if let Some(byte) = self.console.write_control(value) {
+                    self.transmit_console_byte(byte);
+                }
+            }
+            _ => {}
+        }
     }
-}
 
-fn send_input_status(waiting: bool) {
-    INPUT_STATUS_SENDER.with(|status_cell| {
-        if let Some(sender) = status_cell.borrow().as_ref() {
-            let _ = sender.send(waiting);
+    pub fn handle_input(&mut self, device: u8) -> u8 {
+        if self.apply_pending_reset() {
+            self.control.input_aborted.store(true, Ordering::SeqCst);
+            return 0x01;
         }
-    });
-}
+        match device {
+            0x85 => {
+                self.fill_console_receiver();
+                let status = self.console.read_status();
+                if status & STATUS_RX_READY == 0 && self.console.receiver_enabled() {
+                    self.idle_polls = self.idle_polls.saturating_add(1);
+                    if self.idle_polls == AWAIT_INPUT_POLLS {
+                        self.control.input_awaiting.store(true, Ordering::SeqCst);
+                        self.send_input_status(true);
+                    }
+                } else {
+                    self.note_console_activity();
+                }
+                status
+            },
+            0x84 => {
+                self.note_console_activity();
+                self.fill_console_receiver();
+                self.console.read_data()
+            },
+            _ => {
+                self.note_console_activity();
+                0x01
+            }
+        }
+    }
 
-fn drain_input_queue(rx: &Receiver<u8>) {
-    while rx.try_recv().is_ok() {}
-}
+    fn note_console_activity(&mut self) {
+        self.idle_polls = 0;
+        self.control.stop_awaiting_input();
+    }
 
-fn apply_pending_reset() -> bool {
-    if !IO_RESET_PENDING.swap(false, Ordering::SeqCst) {
-        return false;
+    fn transmit_console_byte(&mut self, value: u8) {
+        if let Some(event) = self.terminal.process_byte(value) {
+            self.emit_output_event(event);
+        }
     }
-    USART0_STATE.with(|cell| {
-        *cell.borrow_mut() = Usart0State::new();
-    });
-    INPUT_RECEIVER.with(|cell| {
-        let mut receiver = cell.borrow_mut();
-        let Some(rx) = receiver.as_mut() else {
-            return;
-        };
-        drain_input_queue(rx);
-    });
-    INPUT_RETRY.store(false, Ordering::SeqCst);
-    INPUT_AWAITING.store(false, Ordering::SeqCst);
-    send_input_status(false);
-    true
-}
 
-pub fn input_aborted() -> bool {
-    INPUT_ABORTED.load(Ordering::SeqCst)
-}
+    fn fill_console_receiver(&mut self) -> bool {
+        if !self.console.can_receive() {
+            return false;
+        }
+        let Some(value) = self.input_receiver.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return false;
+        };
+        self.console.receive(value);
+        self.echo_input_byte(value);
+        true
+    }
 
-pub fn clear_input_aborted() -> bool {
-    INPUT_ABORTED.swap(false, Ordering::SeqCst)
-}
+    fn send_input_status(&self, waiting: bool) {
+        self.control.send_input_status(waiting);
+    }
 
-pub fn take_input_retry() -> bool {
-    let retry = INPUT_RETRY.swap(false, Ordering::SeqCst);
-    if retry {
-        TRACE_SUPPRESS.store(true, Ordering::SeqCst);
+    fn apply_pending_reset(&mut self) -> bool {
+        if !self.control.reset_pending.swap(false, Ordering::SeqCst) {
+            return false;
+        }
+        self.console.reset();
+        self.idle_polls = 0;
+        if let Some(rx) = self.input_receiver.as_ref() {
+            drain_input_queue(rx);
+        }
+        self.control.input_awaiting.store(false, Ordering::SeqCst);
+        self.send_input_status(false);
+        true
     }
-    retry
-}
 
-pub fn is_awaiting_input() -> bool {
-    INPUT_AWAITING.load(Ordering::SeqCst)
-}
+    pub fn input_aborted(&self) -> bool {
+        self.control.input_aborted()
+    }
 
-pub fn mark_trace_suppress() {
-    TRACE_SUPPRESS.store(true, Ordering::SeqCst);
-}
+    pub fn is_awaiting_input(&self) -> bool {
+        self.control.is_awaiting_input()
+    }
 
-pub fn take_trace_suppress() -> bool {
-    TRACE_SUPPRESS.swap(false, Ordering::SeqCst)
-}
+    pub fn mark_trace_suppress(&mut self) {
+        self.trace_suppress = true;
+    }
 
-pub fn poll_input_ready() -> bool {
-    if !INPUT_AWAITING.load(Ordering::SeqCst) {
-        return false;
+    pub fn take_trace_suppress(&mut self) -> bool {
+        std::mem::take(&mut self.trace_suppress)
     }
-    INPUT_RECEIVER.with(|cell| {
-        let mut receiver = cell.borrow_mut();
-        let Some(rx) = receiver.as_mut() else {
+
+    pub fn poll_input_ready(&mut self) -> bool {
+        if !self.control.is_awaiting_input() || !self.fill_console_receiver() {
             return false;
-        };
-        match rx.try_recv() {
-            Ok(value) => {
-                USART0_STATE.with(|cell| {
-                    let mut state = cell.borrow_mut();
-                    state.input_ready = true;
-                    state.input_data = value;
-                    state.status = 2;
-                });
-                echo_input_byte(value);
-                drain_input_queue(rx);
-                INPUT_AWAITING.store(false, Ordering::SeqCst);
-                INPUT_RETRY.store(false, Ordering::SeqCst);
-                send_input_status(false);
-                true
-            }
-            Err(_) => false,
         }
-    })
-}
+        self.note_console_activity();
+        true
+    }
 
-fn emit_output_event(event: OutputEvent) {
-    let sent = OUTPUT_SENDER.with(|cell| {
-        if let Some(sender) = cell.borrow().as_ref() {
-            sender.send(event.clone()).is_ok()
-        } else {
-            false
+    fn emit_output_event(&self, event: OutputEvent) {
+        let sent = self
+            .output_sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(event.clone()).is_ok());
+
+        if !sent {
+            let output = match event {
+                OutputEvent::Append(text) => text,
+                OutputEvent::Redraw(text) => text,
+            };
+            print!("{output}");
+            let _ = io::stdout().flush();
         }
-    });
-
-    if !sent {
-        let output = match event {
-            OutputEvent::Append(text) => text,
-            OutputEvent::Redraw(text) => text,
-        };
-        print!("{output}");
-        let _ = io::stdout().flush();
     }
-}
 
-fn echo_input_byte(value: u8) {
-    if !(0x20..=0x7e).contains(&value) {
-        return;
-    }
+    fn echo_input_byte(&mut self, value: u8) {
+        if !(0x20..=0x7e).contains(&value) {
+            return;
+        }
 
-    let event = TERMINAL_STATE.with(|cell| cell.borrow_mut().process_byte(value));
-    if let Some(event) = event {
-        emit_output_event(event);
+        if let Some(event) = self.terminal.process_byte(value) {
+            self.emit_output_event(event);
+        }
     }
 }
 
+fn drain_input_queue(rx: &Receiver<u8>) {
+    while rx.try_recv().is_ok() {}
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum EscapeState {
     None,
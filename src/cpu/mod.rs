@@ -1,11 +1,21 @@
 use deassembler::deassemble;
+pub mod bus;
 pub mod io_handler;
+pub mod ppi;
+pub mod serial;
 #[cfg(test)]
 mod emulation_tests;
 pub mod simulation_controller;
+pub mod timer;
+pub mod usart;
+pub mod watch;
 pub mod deassembler;
 
 const MEMORY_SIZE: usize = (u16::MAX as usize) + 1;
+const HALT_IDLE_CYCLES: u64 = 4;
+
+pub use bus::{Bus, Mcs8Bus, RamBus};
+use watch::{PortAccess, PortBreakpoint, WatchHit, WatchKind, Watchpoint};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct CpuState {
@@ -26,7 +36,7 @@ pub struct InstructionTrace {
     pub address: u16,
     pub text: String,
 }
-pub struct Cpu{
+pub struct Cpu<B: Bus = RamBus>{
     a_reg: u8,
     flags: u8,
     b_reg: u8,
@@ -37,20 +47,42 @@ pub struct Cpu{
     l_reg: u8,
     stack_pointer: u16,
     program_counter: u16,
-    memory: [u8; MEMORY_SIZE],
+    bus: B,
     interrupts_enabled: bool,
+    interrupt_delay: bool,
+    pending_interrupt: Option<u8>,
     halted: bool,
     cycle_counter: u64,
-
+    watchpoints: Vec<Watchpoint>,
+    instruction_address: u16,
+    watch_hit: Option<WatchHit>,
+    port_breakpoints: Vec<PortBreakpoint>,
+    port_hit: Option<PortAccess>,
+    io_logging: bool,
+    io_log: Vec<PortAccess>,
 }
 
 impl Cpu{
     pub fn new() -> Self{
-        Cpu{a_reg:0, flags:0b00000010, b_reg:0, c_reg:0, d_reg:0, e_reg:0, h_reg:0, l_reg:0, stack_pointer:0x0FFF, program_counter:0, memory: [0; MEMORY_SIZE], interrupts_enabled:true, halted:false, cycle_counter:0}
+        Cpu::with_bus(RamBus::new([0; MEMORY_SIZE]))
     }
 
     pub fn with_memory(memory: [u8; MEMORY_SIZE]) -> Self{
-        Cpu{a_reg:0, flags:0b00000010, b_reg:0, c_reg:0, d_reg:0, e_reg:0, h_reg:0, l_reg:0, stack_pointer:0x0FFF, program_counter:0, memory, interrupts_enabled:true, halted:false, cycle_counter:0}
+        Cpu::with_bus(RamBus::new(memory))
+    }
+}
+
+impl<B: Bus> Cpu<B>{
+    pub fn with_bus(bus: B) -> Self{
+        Cpu{a_reg:0, flags:0b00000010, b_reg:0, c_reg:0, d_reg:0, e_reg:0, h_reg:0, l_reg:0, stack_pointer:0x0FFF, program_counter:0, bus, interrupts_enabled:true, interrupt_delay:false, pending_interrupt:None, halted:false, cycle_counter:0, watchpoints:Vec::new(), instruction_address:0, watch_hit:None, port_breakpoints:Vec::new(), port_hit:None, io_logging:false, io_log:Vec::new()}
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
     }
 
     pub fn run(&mut self){
@@ -74,34 +106,85 @@ impl Cpu{
         self.stack_pointer = 0x0FFF;
 
         self.interrupts_enabled = false;
+        self.interrupt_delay = false;
+        self.pending_interrupt = None;
         self.halted = false;
 
         self.cycle_counter = 0;
+        self.bus.reset();
     }
 
     pub fn is_halted(&self) -> bool {
         self.halted
     }
 
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    pub fn has_pending_interrupt(&self) -> bool {
+        self.pending_interrupt.is_some()
+    }
+
+    // vector is executed in place of the next opcode (normally RST n), latched until acknowledged
+    pub fn request_interrupt(&mut self, vector: u8) {
+        self.pending_interrupt = Some(vector);
+    }
+
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+        self.watch_hit = None;
+    }
+
+    // first watched access made by the last executed instructions
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    pub fn set_port_breakpoints(&mut self, breakpoints: Vec<PortBreakpoint>) {
+        self.port_breakpoints = breakpoints;
+        self.port_hit = None;
+    }
+
+    pub fn take_port_hit(&mut self) -> Option<PortAccess> {
+        self.port_hit.take()
+    }
+
+    pub fn set_io_logging(&mut self, enabled: bool) {
+        self.io_logging = enabled;
+        self.io_log.clear();
+    }
+
+    // completed IN/OUT accesses since the last call, only recorded while logging is on
+    pub fn take_io_log(&mut self) -> Vec<PortAccess> {
+        std::mem::take(&mut self.io_log)
+    }
+
     pub fn step(&mut self) {
         let _ = self.step_with_cycles();
     }
 
     pub fn step_with_cycles(&mut self) -> u64 {
-        let opcode = self.fetch_opcode();
-        let cycles = self.execute(opcode);
-        self.cycle_counter += cycles;
+        let cycles = match self.next_opcode() {
+            Some(opcode) => self.execute(opcode),
+            None => HALT_IDLE_CYCLES,
+        };
+        self.advance(cycles);
         cycles
     }
 
     pub fn step_with_trace(&mut self) -> (u64, InstructionTrace) {
         let address = self.program_counter;
-        let opcode = self.fetch_opcode();
-        let lo = self.memory[self.program_counter as usize];
-        let hi = self.memory[self.program_counter.wrapping_add(1) as usize];
+        let Some(opcode) = self.next_opcode() else {
+            self.advance(HALT_IDLE_CYCLES);
+            let trace = InstructionTrace { address: address.wrapping_sub(1), text: deassemble(0x76, 0, 0) };
+            return (HALT_IDLE_CYCLES, trace);
+        };
+        let lo = self.read_byte(self.program_counter);
+        let hi = self.read_byte(self.program_counter.wrapping_add(1));
         let text = deassemble(opcode, lo, hi);
         let cycles = self.execute(opcode);
-        self.cycle_counter += cycles;
+        self.advance(cycles);
         (cycles, InstructionTrace { address, text })
     }
 
@@ -121,23 +204,104 @@ impl Cpu{
     }
 
     pub fn memory_snapshot(&self) -> Vec<u8> {
-        self.memory.to_vec()
+        (0..MEMORY_SIZE).map(|address| self.read_byte(address as u16)).collect()
     }
 
     pub fn step_with_deassembler(&mut self) -> String {
-        if self.halted {
+        let Some(opcode) = self.next_opcode() else {
+            self.advance(HALT_IDLE_CYCLES);
             return "".to_string();
+        };
+        let code = deassemble(opcode, self.read_byte(self.program_counter), self.read_byte(self.program_counter.wrapping_add(1)));
+        let cycles = self.execute(opcode);
+        self.advance(cycles);
+        code
+    }
+
+    fn next_opcode(&mut self) -> Option<u8> {
+        self.instruction_address = self.program_counter;
+        if let Some(vector) = self.acknowledge_interrupt() {
+            return Some(vector);
         }
+        if self.halted {
+            return None;
+        }
+        Some(self.fetch_opcode())
+    }
 
-        let opcode = self.fetch_opcode();
-        let code = deassemble(opcode, self.memory[self.program_counter as usize], self.memory[(self.program_counter.wrapping_add(1)) as usize]);
-        let cycles = self.execute(opcode);
+    fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        if self.interrupt_delay {
+            self.interrupt_delay = false;
+            return None;
+        }
+        if !self.interrupts_enabled {
+            return None;
+        }
+        let vector = self.pending_interrupt.take()?;
+        self.interrupts_enabled = false;
+        self.halted = false;
+        Some(vector)
+    }
+
+    fn advance(&mut self, cycles: u64) {
         self.cycle_counter += cycles;
-        code
+        self.bus.tick(cycles);
+        if let Some(vector) = self.bus.take_interrupt() {
+            self.request_interrupt(vector);
+        }
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.watchpoints.is_empty() {
+            let old_value = self.bus.read(address);
+            self.note_access(address, WatchKind::Write, old_value, value);
+        }
+        self.bus.write(address, value);
+    }
+
+    // operand fetches go through read_byte, only data reads are watched
+    fn read_data(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(address);
+        if !self.watchpoints.is_empty() {
+            self.note_access(address, WatchKind::Read, value, value);
+        }
+        value
+    }
+
+    fn note_port_access(&mut self, kind: WatchKind, port: u8, value: u8) {
+        if !self.io_logging && self.port_breakpoints.is_empty() {
+            return;
+        }
+        let access = PortAccess { cycle: self.cycle_counter, pc: self.instruction_address, kind, port, value };
+        if self.io_logging {
+            self.io_log.push(access);
+        }
+        if self.port_hit.is_none() && self.port_breakpoints.iter().any(|bp| bp.triggers(port, kind)) {
+            self.port_hit = Some(access);
+        }
+    }
+
+    fn note_access(&mut self, address: u16, kind: WatchKind, old_value: u8, new_value: u8) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        if self.watchpoints.iter().any(|watch| watch.triggers(address, kind)) {
+            self.watch_hit = Some(WatchHit {
+                pc: self.instruction_address,
+                address,
+                kind,
+                old_value,
+                new_value,
+            });
+        }
     }
 
     fn fetch_opcode(&mut self) -> u8 {
-        let opcode = self.memory[self.program_counter as usize];
+        let opcode = self.read_byte(self.program_counter);
         self.program_counter = self.program_counter.wrapping_add(1);
         opcode
     }
@@ -156,7 +320,7 @@ impl Cpu{
             }
             0x02 => {
                 //STAX B
-                self.memory[((self.b_reg as usize) << 8) | (self.c_reg as usize)] = self.a_reg;
+                self.write_byte(self.get_bc(), self.a_reg);
                 7
             }
             0x03 => {
@@ -207,7 +371,7 @@ impl Cpu{
             }
             0x0A => {
                 //LDAX B
-                self.a_reg = self.memory[self.get_bc() as usize];
+                self.a_reg = self.read_data(self.get_bc());
                 7
             }
             0x0B => {
@@ -259,7 +423,7 @@ impl Cpu{
             }
             0x12 => {
                 //STAX D
-                self.memory[((self.d_reg as usize) << 8) | (self.e_reg as usize)] = self.a_reg;
+                self.write_byte(self.get_de(), self.a_reg);
                 7
             }
             0x13 => {
@@ -311,7 +475,7 @@ impl Cpu{
             }
             0x1A => {
                 //LDAX D
-                self.a_reg = self.memory[self.get_de() as usize];
+                self.a_reg = self.read_data(self.get_de());
                 7
             }
             0x1B => {
@@ -365,9 +529,9 @@ impl Cpu{
             0x22 => {
                 //SHLD a16
                 let mut address =self.read_u16_from_memory();
-                self.memory[address as usize] = self.l_reg;
+                self.write_byte(address, self.l_reg);
                 address = address.wrapping_add(1);
-                self.memory[address as usize] = self.h_reg;
+                self.write_byte(address, self.h_reg);
                 16
             }
             0x23 => {
@@ -439,9 +603,9 @@ impl Cpu{
             0x2A => {
                 //LHLD a16
                 let mut addr = self.read_u16_from_memory();
-                let lo = self.memory[addr as usize];
+                let lo = self.read_data(addr);
                 addr = addr.wrapping_add(1);
-                let hi = self.memory[addr as usize];
+                let hi = self.read_data(addr);
                 self.l_reg = lo;
                 self.h_reg = hi;
                 16
@@ -494,7 +658,7 @@ impl Cpu{
             0x32 => {
                 //STA a16
                 let address = self.read_u16_from_memory();
-                self.memory[address as usize] = self.a_reg;
+                self.write_byte(address, self.a_reg);
                 13
             }
             0x33 => {
@@ -504,10 +668,10 @@ impl Cpu{
             }
             0x34 => {
                 // INR M
-                let addr = self.get_address_from_m_as_usize();
-                let old = self.memory[addr];
+                let addr = self.get_address_from_m();
+                let old = self.read_data(addr);
                 let result = old.wrapping_add(1);
-                self.memory[addr] = result;
+                self.write_byte(addr, result);
 
                 self.check_value_and_set_zero_flag(result);
                 self.check_value_and_set_sign_flag(result);
@@ -517,10 +681,10 @@ impl Cpu{
             }
             0x35 => {
                 //DCR M
-                let addr = self.get_address_from_m_as_usize();
-                let old = self.memory[addr];
+                let addr = self.get_address_from_m();
+                let old = self.read_data(addr);
                 let result = old.wrapping_sub(1);
-                self.memory[addr] = result;
+                self.write_byte(addr, result);
 
                 self.check_value_and_set_zero_flag(result);
                 self.check_value_and_set_sign_flag(result);
@@ -530,8 +694,9 @@ impl Cpu{
             }
             0x36 => {
                 //MVI M,d8
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.read_u8_from_memory();
+                let addr = self.get_address_from_m();
+                let value = self.read_u8_from_memory();
+                self.write_byte(addr, value);
                 10
             }
             0x37 => {
@@ -547,7 +712,7 @@ impl Cpu{
             0x3A => {
                 //LDA a16
                 let addr = self.read_u16_from_memory();
-                self.a_reg = self.memory[addr as usize];
+                self.a_reg = self.read_data(addr);
                 13
             }
             0x3B => {
@@ -620,7 +785,7 @@ impl Cpu{
             }
             0x46 => {
                 //MOV B,M
-                self.b_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.b_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x47 => {
@@ -659,7 +824,7 @@ impl Cpu{
             }
             0x4E => {
                 //MOV C,M
-                self.c_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.c_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x4F => {
@@ -698,7 +863,7 @@ impl Cpu{
             }
             0x56 => {
                 //MOV D,M
-                self.d_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.d_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x57 => {
@@ -737,7 +902,7 @@ impl Cpu{
             }
             0x5E => {
                 //MOV E,M
-                self.e_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.e_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x5F => {
@@ -776,7 +941,7 @@ impl Cpu{
             }
             0x66 => {
                 //MOV H,M
-                self.h_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.h_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x67 => {
@@ -815,7 +980,7 @@ impl Cpu{
             }
             0x6E => {
                 //MOV L,M
-                self.l_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.l_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x6F => {
@@ -825,38 +990,38 @@ impl Cpu{
             }
             0x70 => {
                 // MOV M,B
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.b_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.b_reg);
                 7
             }
             0x71 => {
                 //MOV M,C
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.c_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.c_reg);
                 7
             }
             0x72 => {
                 //MOV M,D
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.d_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.d_reg);
                 7
             }
             0x73 => {
                 //MOV M,E
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.e_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.e_reg);
                 7
             }
             0x74 => {
                 //MOV M,H
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.h_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.h_reg);
                 7
             }
             0x75 => {
                 //MOV M,L
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.l_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.l_reg);
                 7
             }
             0x76 => {
@@ -866,8 +1031,8 @@ impl Cpu{
             }
             0x77 => {
                 //MOV M,A
-                let addr = self.get_address_from_m_as_usize();
-                self.memory[addr] = self.a_reg;
+                let addr = self.get_address_from_m();
+                self.write_byte(addr, self.a_reg);
                 7
             }
             0x78 => {
@@ -902,7 +1067,7 @@ impl Cpu{
             }
             0x7E => {
                 //MOV A,M
-                self.a_reg = self.memory[self.get_address_from_m_as_usize()];
+                self.a_reg = self.read_data(self.get_address_from_m());
                 7
             }
             0x7F => {
@@ -941,8 +1106,9 @@ impl Cpu{
             }
             0x86 => {
                 //ADD M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_u8_addition(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_u8_addition(value);
                 7
             }
             0x87 => {
@@ -982,8 +1148,9 @@ impl Cpu{
             }
             0x8E => {
                 //ADC M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_u8_addition_with_carry(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_u8_addition_with_carry(value);
                 7
             }
             0x8F => {
@@ -1023,8 +1190,9 @@ impl Cpu{
             }
             0x96 => {
                 //SUB M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_u8_subtraction(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_u8_subtraction(value);
                 7
             }
             0x97 => {
@@ -1063,8 +1231,9 @@ impl Cpu{
             }
             0x9E => {
                 //SBB M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_u8_subtraction_with_borrow(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_u8_subtraction_with_borrow(value);
                 7
             }
             0x9F => {
@@ -1104,8 +1273,9 @@ impl Cpu{
             }
             0xA6 => {
                 //ANA M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_and_operation(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_and_operation(value);
                 7
             }
             0xA7 => {
@@ -1145,8 +1315,9 @@ impl Cpu{
             }
             0xAE => {
                 //XRA M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_xra_operation(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_xra_operation(value);
                 7
             }
             0xAF => {
@@ -1186,8 +1357,9 @@ impl Cpu{
             }
             0xB6 => {
                 //ORA M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_or_operation(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_or_operation(value);
                 7
             }
             0xB7 => {
@@ -1227,8 +1399,9 @@ impl Cpu{
             }
             0xBE => {
                 //CMP M
-                let addr = self.get_address_from_m_as_usize();
-                self.perform_compare_operation(self.memory[addr]);
+                let addr = self.get_address_from_m();
+                let value = self.read_data(addr);
+                self.perform_compare_operation(value);
                 7
             }
             0xBF => {
@@ -1379,7 +1552,8 @@ impl Cpu{
             0xD3 => {
                 //OUT d8
                 let device = self.read_u8_from_memory();
-                io_handler::handle_output(device, self.a_reg);
+                self.bus.output(device, self.a_reg);
+                self.note_port_access(WatchKind::Write, device, self.a_reg);
                 10
 
             }
@@ -1437,16 +1611,16 @@ impl Cpu{
             0xDB => {
                 //IN d8
                 let device = self.read_u8_from_memory();
-                let prev_a = self.a_reg;
-                let value = io_handler::handle_input(device);
-                if io_handler::take_input_retry() || io_handler::input_aborted() {
-                    io_handler::mark_trace_suppress();
-                    self.a_reg = prev_a;
-                    self.program_counter = self.program_counter.wrapping_sub(2);
-                    0
-                } else {
-                    self.a_reg = value;
-                    10
+                match self.bus.input(device) {
+                    Some(value) => {
+                        self.a_reg = value;
+                        self.note_port_access(WatchKind::Read, device, value);
+                        10
+                    }
+                    None => {
+                        self.program_counter = self.program_counter.wrapping_sub(2);
+                        0
+                    }
                 }
             }
             0xDC => {
@@ -1504,11 +1678,11 @@ impl Cpu{
             }
             0xE3 => {
                 //XTHL
-                let mut temp = self.memory[self.stack_pointer as usize];
-                self.memory[self.stack_pointer as usize] = self.l_reg;
+                let mut temp = self.read_data(self.stack_pointer);
+                self.write_byte(self.stack_pointer, self.l_reg);
                 self.l_reg = temp;
-                temp = self.memory[(self.stack_pointer as usize).wrapping_add(1)];
-                self.memory[(self.stack_pointer as usize).wrapping_add(1)] = self.h_reg;
+                temp = self.read_data(self.stack_pointer.wrapping_add(1));
+                self.write_byte(self.stack_pointer.wrapping_add(1), self.h_reg);
                 self.h_reg = temp;
                 18
 
@@ -1689,6 +1863,7 @@ impl Cpu{
             0xFB => {
                 //EI
                 self.interrupts_enabled = true;
+                self.interrupt_delay = true;
                 4
             }
             0xFC => {
@@ -1728,10 +1903,6 @@ impl Cpu{
         ((self.h_reg as u16) << 8) | (self.l_reg as u16)
     }
 
-    fn get_address_from_m_as_usize(&self) -> usize{
-        self.get_address_from_m() as usize
-    }
-
     fn set_carry_flag(&mut self, value: bool) {
         if value {
             self.flags |= 0b0000_0001;
@@ -1824,9 +1995,9 @@ impl Cpu{
     }
 
     fn pop_stack_u16(&mut self) -> u16{
-        let lo = self.memory[self.stack_pointer as usize];
+        let lo = self.read_data(self.stack_pointer);
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        let hi = self.memory[self.stack_pointer as usize];
+        let hi = self.read_data(self.stack_pointer);
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
         ((hi as u16) << 8 )| lo as u16
     }
@@ -1835,21 +2006,21 @@ impl Cpu{
         let hi = (value >> 8) as u8;
         let lo = value as u8;
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-        self.memory[self.stack_pointer as usize] = hi;
+        self.write_byte(self.stack_pointer, hi);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-        self.memory[self.stack_pointer as usize] = lo;
+        self.write_byte(self.stack_pointer, lo);
     }
 
     fn read_u16_from_memory(&mut self) -> u16{
-        let lo = self.memory[self.program_counter as usize];
+        let lo = self.read_byte(self.program_counter);
         self.program_counter = self.program_counter.wrapping_add(1);
-        let hi = self.memory[self.program_counter as usize];
+        let hi = self.read_byte(self.program_counter);
         self.program_counter = self.program_counter.wrapping_add(1);
         (hi as u16) << 8 | lo as u16
     }
 
     fn read_u8_from_memory(&mut self) -> u8{
-        let value = self.memory[self.program_counter as usize];
+        let value = self.read_byte(self.program_counter);
         self.program_counter = self.program_counter.wrapping_add(1);
         value
     }
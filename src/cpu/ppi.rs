@@ -0,0 +1,308 @@
+pub const PORT_A: usize = 0;
+pub const PORT_B: usize = 1;
+pub const PORT_C: usize = 2;
+
+// mode word
+const MODE_SET: u8 = 0x80;
+const PORT_A_INPUT: u8 = 0x10;
+const PORT_C_UPPER_INPUT: u8 = 0x08;
+const GROUP_B_MODE: u8 = 0x04;
+const PORT_B_INPUT: u8 = 0x02;
+const PORT_C_LOWER_INPUT: u8 = 0x01;
+
+// port C handshake lines
+const INTR_B: u8 = 0x01;
+const IBF_OBF_B: u8 = 0x02;
+const STB_ACK_B: u8 = 0x04;
+const INTR_A: u8 = 0x08;
+const STB_A: u8 = 0x10;
+const IBF_A: u8 = 0x20;
+const ACK_A: u8 = 0x40;
+const OBF_A: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpiInput {
+    Pins(usize, u8),
+    Strobe(usize),
+    Acknowledge(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PpiState {
+    pub control: u8,
+    pub pins: [u8; 3],
+    pub inputs: [u8; 3],
+    pub input_mask: [u8; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+struct Handshake {
+    input_buffer_full: bool,
+    output_buffer_full: bool,
+    interrupt: bool,
+    input_interrupt_enable: bool,
+    output_interrupt_enable: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ppi8255 {
+    control: u8,
+    latches: [u8; 3],
+    inputs: [u8; 3],
+    strobed: [u8; 2],
+    group_a: Handshake,
+    group_b: Handshake,
+}
+
+impl Default for Ppi8255 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppi8255 {
+    pub fn new() -> Self {
+        // after RESET every port is an input in mode 0
+        Self {
+            control: MODE_SET | PORT_A_INPUT | PORT_C_UPPER_INPUT | PORT_B_INPUT | PORT_C_LOWER_INPUT,
+            latches: [0; 3],
+            inputs: [0xFF; 3],
+            strobed: [0; 2],
+            group_a: Handshake::default(),
+            group_b: Handshake::default(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let inputs = self.inputs;
+        *self = Self { inputs, ..Self::new() };
+    }
+
+    // port is the offset from the base address: 0-2 ports A/B/C, 3 control word
+    pub fn write(&mut self, port: u8, value: u8) {
+        match port & 0x03 {
+            0 => {
+                self.latches[PORT_A] = value;
+                if self.group_a_output() {
+                    self.group_a.output_buffer_full = true;
+                    self.group_a.interrupt = false;
+                }
+            }
+            1 => {
+                self.latches[PORT_B] = value;
+                if self.group_b_mode() == 1 && !self.port_b_input() {
+                    self.group_b.output_buffer_full = true;
+                    self.group_b.interrupt = false;
+                }
+            }
+            2 => self.latches[PORT_C] = value,
+            _ if value & MODE_SET != 0 => {
+                self.control = value;
+                self.latches = [0; 3];
+                self.group_a = Handshake::default();
+                self.group_b = Handshake::default();
+            }
+            _ => self.set_reset_bit((value >> 1) & 0x07, value & 0x01 != 0),
+        }
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        match port & 0x03 {
+            0 => {
+                if self.group_a_mode() == 0 {
+                    return if self.port_a_input() { self.inputs[PORT_A] } else { self.latches[PORT_A] };
+                }
+                if self.group_a_input() {
+                    self.group_a.input_buffer_full = false;
+                    self.group_a.interrupt = false;
+                    return self.strobed[PORT_A];
+                }
+                self.latches[PORT_A]
+            }
+            1 => {
+                if self.group_b_mode() == 0 {
+                    return if self.port_b_input() { self.inputs[PORT_B] } else { self.latches[PORT_B] };
+                }
+                if self.port_b_input() {
+                    self.group_b.input_buffer_full = false;
+                    self.group_b.interrupt = false;
+                    return self.strobed[PORT_B];
+                }
+                self.latches[PORT_B]
+            }
+            2 => self.read_port_c(),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn apply_input(&mut self, input: PpiInput) {
+        match input {
+            PpiInput::Pins(port, value) => self.inputs[port] = value,
+            PpiInput::Strobe(PORT_A) if self.group_a_input() => {
+                self.strobed[PORT_A] = self.inputs[PORT_A];
+                self.group_a.input_buffer_full = true;
+                self.group_a.interrupt |= self.group_a.input_interrupt_enable;
+            }
+            PpiInput::Strobe(PORT_B) if self.group_b_mode() == 1 && self.port_b_input() => {
+                self.strobed[PORT_B] = self.inputs[PORT_B];
+                self.group_b.input_buffer_full = true;
+                self.group_b.interrupt |= self.group_b.input_interrupt_enable;
+            }
+            PpiInput::Acknowledge(PORT_A) if self.group_a_output() => {
+                self.group_a.output_buffer_full = false;
+                self.group_a.interrupt |= self.group_a.output_interrupt_enable;
+            }
+            PpiInput::Acknowledge(PORT_B) if self.group_b_mode() == 1 && !self.port_b_input() => {
+                self.group_b.output_buffer_full = false;
+                self.group_b.interrupt |= self.group_b.output_interrupt_enable;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn state(&self) -> PpiState {
+        let input_mask = [
+            if self.port_a_input() || self.group_a_mode() == 2 { 0xFF } else { 0x00 },
+            if self.port_b_input() { 0xFF } else { 0x00 },
+            self.port_c_input_mask() & !self.handshake_mask(),
+        ];
+        let mut pins = [0; 3];
+        for port in [PORT_A, PORT_B] {
+            pins[port] = (self.inputs[port] & input_mask[port]) | (self.latches[port] & !input_mask[port]);
+        }
+        pins[PORT_C] = self.port_c_pins();
+        PpiState {
+            control: self.control,
+            pins,
+            inputs: self.inputs,
+            input_mask,
+        }
+    }
+
+    fn set_reset_bit(&mut self, bit: u8, set: bool) {
+        let mask = 1u8 << bit;
+        if self.group_a_mode() != 0 {
+            if mask == STB_A {
+                self.group_a.input_interrupt_enable = set;
+            } else if mask == ACK_A {
+                self.group_a.output_interrupt_enable = set;
+            }
+        }
+        if self.group_b_mode() == 1 && mask == STB_ACK_B {
+            self.group_b.input_interrupt_enable = set;
+            self.group_b.output_interrupt_enable = set;
+        }
+        if set {
+            self.latches[PORT_C] |= mask;
+        } else {
+            self.latches[PORT_C] &= !mask;
+        }
+    }
+
+    fn read_port_c(&self) -> u8 {
+        let input_mask = self.port_c_input_mask();
+        let mut value = (self.inputs[PORT_C] & input_mask) | (self.latches[PORT_C] & !input_mask);
+        let handshake = self.handshake_mask();
+        value &= !handshake;
+        value | self.handshake_status()
+    }
+
+    fn port_c_pins(&self) -> u8 {
+        let input_mask = self.port_c_input_mask() & !self.handshake_mask();
+        let general = (self.inputs[PORT_C] & input_mask)
+            | (self.latches[PORT_C] & !input_mask & !self.handshake_mask());
+        general | self.handshake_status()
+    }
+
+    // status word bits as seen on port C reads in modes 1 and 2
+    fn handshake_status(&self) -> u8 {
+        let mut status = 0;
+        match self.group_a_mode() {
+            0 => {}
+            mode => {
+                if self.group_a.interrupt {
+                    status |= INTR_A;
+                }
+                if mode == 2 || self.port_a_input() {
+                    if self.group_a.input_buffer_full {
+                        status |= IBF_A;
+                    }
+                    if self.group_a.input_interrupt_enable {
+                        status |= STB_A;
+                    }
+                }
+                if mode == 2 || !self.port_a_input() {
+                    // OBF is active low
+                    if !self.group_a.output_buffer_full {
+                        status |= OBF_A;
+                    }
+                    if self.group_a.output_interrupt_enable {
+                        status |= ACK_A;
+                    }
+                }
+            }
+        }
+        if self.group_b_mode() == 1 {
+            if self.group_b.interrupt {
+                status |= INTR_B;
+            }
+            if self.group_b.input_interrupt_enable {
+                status |= STB_ACK_B;
+            }
+            let buffer_flag = if self.port_b_input() {
+                self.group_b.input_buffer_full
+            } else {
+                !self.group_b.output_buffer_full
+            };
+            if buffer_flag {
+                status |= IBF_OBF_B;
+            }
+        }
+        status
+    }
+
+    fn handshake_mask(&self) -> u8 {
+        let group_a = match self.group_a_mode() {
+            0 => 0,
+            2 => INTR_A | STB_A | IBF_A | ACK_A | OBF_A,
+            _ if self.port_a_input() => INTR_A | STB_A | IBF_A,
+            _ => INTR_A | ACK_A | OBF_A,
+        };
+        let group_b = if self.group_b_mode() == 1 { INTR_B | IBF_OBF_B | STB_ACK_B } else { 0 };
+        group_a | group_b
+    }
+
+    fn port_c_input_mask(&self) -> u8 {
+        let upper = if self.control & PORT_C_UPPER_INPUT != 0 { 0xF0 } else { 0x00 };
+        let lower = if self.control & PORT_C_LOWER_INPUT != 0 { 0x0F } else { 0x00 };
+        upper | lower
+    }
+
+    fn group_a_mode(&self) -> u8 {
+        match (self.control >> 5) & 0x03 {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        }
+    }
+
+    fn group_b_mode(&self) -> u8 {
+        if self.control & GROUP_B_MODE != 0 { 1 } else { 0 }
+    }
+
+    fn port_a_input(&self) -> bool {
+        self.control & PORT_A_INPUT != 0
+    }
+
+    fn port_b_input(&self) -> bool {
+        self.control & PORT_B_INPUT != 0
+    }
+
+    fn group_a_input(&self) -> bool {
+        self.group_a_mode() == 2 || (self.group_a_mode() == 1 && self.port_a_input())
+    }
+
+    fn group_a_output(&self) -> bool {
+        self.group_a_mode() == 2 || (self.group_a_mode() == 1 && !self.port_a_input())
+    }
+}
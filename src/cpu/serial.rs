@@ -0,0 +1,314 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::Path;
+
+use super::usart::Usart8251;
+
+pub trait SerialLink: Send {
+    // never blocks, None when nothing has arrived
+    fn read_byte(&mut self) -> Option<u8>;
+    fn write_byte(&mut self, value: u8);
+    fn description(&self) -> String;
+    // called on every bus tick, links that queue output send what they can
+    fn poll(&mut self) {}
+    // false while queued output is still waiting, the USART holds back its next character meanwhile
+    fn clear_to_send(&self) -> bool {
+        true
+    }
+}
+
+// hands a nonblocking endpoint as much of the queue as it takes now, the rest waits for the next poll
+fn flush_pending(writer: &mut impl Write, pending: &mut VecDeque<u8>) -> io::Result<()> {
+    while !pending.is_empty() {
+        let (front, _) = pending.as_slices();
+        match writer.write(front) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(written) => {
+                pending.drain(..written);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+pub struct SerialPort {
+    usart: Usart8251,
+    link: Option<Box<dyn SerialLink>>,
+}
+
+impl Default for SerialPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerialPort {
+    pub fn new() -> Self {
+        Self { usart: Usart8251::new(), link: None }
+    }
+
+    pub fn set_link(&mut self, link: Option<Box<dyn SerialLink>>) {
+        self.link = link;
+    }
+
+    pub fn link_description(&self) -> Option<String> {
+        self.link.as_ref().map(|link| link.description())
+    }
+
+    pub fn reset(&mut self) {
+        self.usart.reset();
+    }
+
+    pub fn tick(&mut self) {
+        if let Some(link) = self.link.as_mut() {
+            link.poll();
+        }
+        self.update_clear_to_send();
+    }
+
+    // a backed up link leaves TxRDY clear, so a program polling it waits as on a slow line
+    fn update_clear_to_send(&mut self) {
+        let Some(link) = self.link.as_mut() else { return };
+        if let Some(value) = self.usart.set_cts(link.clear_to_send()) {
+            link.write_byte(value);
+            self.usart.set_cts(link.clear_to_send());
+        }
+    }
+
+    // port is the offset from the base address: 0 data, 1 control/status
+    pub fn read(&mut self, port: u8) -> u8 {
+        if port & 0x01 == 0 {
+            return self.usart.read_data();
+        }
+        if self.usart.can_receive()
+            && let Some(value) = self.link.as_mut().and_then(|link| link.read_byte())
+        {
+            self.usart.receive(value);
+        }
+        self.usart.read_status()
+    }
+
+    pub fn write(&mut self, port: u8, value: u8) {
+        let sent = if port & 0x01 == 0 {
+            self.usart.write_data(value)
+        } else {
+            self.usart.write_control(value)
+        };
+        if let (Some(value), Some(link)) = (sent, self.link.as_mut()) {
+            link.write_byte(value);
+        }
+        self.update_clear_to_send();
+    }
+}
+
+pub struct FileLink {
+    input: Option<File>,
+    output: Option<File>,
+    description: String,
+}
+
+// either path may be empty to leave that direction unconnected
+pub fn open_files(input: &Path, output: &Path) -> io::Result<Box<dyn SerialLink>> {
+    let input_file = if input.as_os_str().is_empty() { None } else { Some(File::open(input)?) };
+    let output_file = if output.as_os_str().is_empty() { None } else { Some(File::create(output)?) };
+    Ok(Box::new(FileLink {
+        input: input_file,
+        output: output_file,
+        description: format!("{} -> {}", input.display(), output.display()),
+    }))
+}
+
+impl SerialLink for FileLink {
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = [0u8; 1];
+        match self.input.as_mut()?.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        if let Some(output) = self.output.as_mut() {
+            let _ = output.write_all(&[value]).and_then(|_| output.flush());
+        }
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+}
+
+pub struct TcpLink {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    port: u16,
+    // bytes the client's socket could not take yet
+    pending: VecDeque<u8>,
+}
+
+pub fn open_tcp(port: u16) -> io::Result<Box<dyn SerialLink>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    Ok(Box::new(TcpLink { listener, stream: None, port, pending: VecDeque::new() }))
+}
+
+impl TcpLink {
+    fn connection(&mut self) -> Option<&mut TcpStream> {
+        if self.stream.is_none()
+            && let Ok((stream, _)) = self.listener.accept()
+            && stream.set_nonblocking(true).is_ok()
+        {
+            let _ = stream.set_nodelay(true);
+            self.stream = Some(stream);
+        }
+        self.stream.as_mut()
+    }
+
+    fn flush(&mut self) {
+        if let Some(stream) = self.stream.as_mut()
+            && flush_pending(stream, &mut self.pending).is_err()
+        {
+            self.disconnect();
+        }
+    }
+
+    // output queued for a client that went away is dropped with it
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.pending.clear();
+    }
+}
+
+impl SerialLink for TcpLink {
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = [0u8; 1];
+        match self.connection()?.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => None,
+            _ => {
+                // peer closed, wait for the next client
+                self.disconnect();
+                None
+            }
+        }
+    }
+
+    // without a client the byte goes nowhere, like a serial line with nothing attached
+    fn write_byte(&mut self, value: u8) {
+        if self.connection().is_some() {
+            // behind a backlog the socket was full on the last try, the next poll retries
+            let idle = self.pending.is_empty();
+            self.pending.push_back(value);
+            if idle {
+                self.flush();
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("tcp 127.0.0.1:{}", self.port)
+    }
+
+    fn poll(&mut self) {
+        if !self.pending.is_empty() {
+            self.flush();
+        }
+    }
+
+    fn clear_to_send(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(unix)]
+pub struct PtyLink {
+    master: File,
+    // held open so the line settings survive while no terminal program is attached
+    _slave: File,
+    slave_path: String,
+    // bytes the pseudo-terminal buffer could not take yet
+    pending: VecDeque<u8>,
+}
+
+#[cfg(unix)]
+pub fn open_pty() -> io::Result<Box<dyn SerialLink>> {
+    use std::ffi::CStr;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    if master < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(master) };
+    let fd = master.as_raw_fd();
+    if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // ptsname uses a static buffer, this only runs on the GUI thread
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let slave_path = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+    let slave = std::fs::OpenOptions::new().read(true).write(true).open(&slave_path)?;
+
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+            libc::cfmakeraw(&mut termios);
+            libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+        }
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(Box::new(PtyLink { master, _slave: slave, slave_path, pending: VecDeque::new() }))
+}
+
+#[cfg(not(unix))]
+pub fn open_pty() -> io::Result<Box<dyn SerialLink>> {
+    Err(io::Error::new(ErrorKind::Unsupported, "pseudo-terminals need a Unix host"))
+}
+
+#[cfg(unix)]
+impl SerialLink for PtyLink {
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = [0u8; 1];
+        match self.master.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn write_byte(&mut self, value: u8) {
+        let idle = self.pending.is_empty();
+        self.pending.push_back(value);
+        if idle {
+            self.poll();
+        }
+    }
+
+    fn description(&self) -> String {
+        self.slave_path.clone()
+    }
+
+    fn poll(&mut self) {
+        // a broken pseudo-terminal will not take the rest either
+        if flush_pending(&mut self.master, &mut self.pending).is_err() {
+            self.pending.clear();
+        }
+    }
+
+    // with no terminal program attached the master stops taking output and the program waits
+    fn clear_to_send(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -1,16 +1,36 @@
+use std::collections::HashMap;
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use super::io_handler::{self, OutputEvent};
-use super::{Cpu, CpuState, InstructionTrace};
+use std::sync::Arc;
+
+use crate::assembler::expressions::Condition;
+
+use super::io_handler::{IoControl, OutputEvent};
+use super::ppi::{PpiInput, PpiState};
+use super::watch::{PortAccess, PortBreakpoint, WatchHit, Watchpoint};
+use super::bus::Bus;
+use super::{Cpu, CpuState, InstructionTrace, Mcs8Bus};
 
 pub enum SimCommand {
     Run,
     Step,
+    // CALL, Cc and RST run to the return address, anything else is a Step
+    StepOver,
+    StepOut,
+    RunTo(u16),
     Stop,
     Reset,
     SetCyclesLimit(Option<u64>),
+    Ppi(PpiInput),
+    SetBreakpoints(Vec<u16>),
+    ToggleBreakpoint(u16),
+    // adds the breakpoint if needed, None makes it unconditional
+    SetBreakpointCondition(u16, Option<Condition>),
+    SetWatchpoints(Vec<Watchpoint>),
+    SetPortBreakpoints(Vec<PortBreakpoint>),
+    SetIoLogging(bool),
 }
 
 #[derive(Debug, Clone)]
@@ -23,10 +43,18 @@ pub enum SimulationEvent {
     MemorySnapshot(Vec<u8>),
     Trace(InstructionTrace),
     TraceBatch(Vec<InstructionTrace>),
+    PpiState(PpiState),
+    BreakpointHit(u16),
+    // a Step Over, Step Out or Run To finished at this address
+    TargetReached(u16),
+    WatchpointHit(WatchHit),
+    PortBreakpointHit(PortAccess),
+    IoLog(Vec<PortAccess>),
 }
 
 pub struct SimulationController {
     tx: Sender<SimCommand>,
+    io_control: Arc<IoControl>,
 }
 
 const RUN_BATCH_STEPS: usize = 20_000;
@@ -35,138 +63,112 @@ const MAX_CYCLES_LIMIT: u64 = 6_000_000;
 
 impl SimulationController {
     pub fn new(
-        mut cpu: Cpu,
+        mut cpu: Cpu<Mcs8Bus>,
         input_receiver: Option<Receiver<u8>>,
         event_sender: Sender<SimulationEvent>,
         publish_debug_events: bool,
         cycles_limit: Option<u64>,
     ) -> Self {
         let (tx, rx): (Sender<SimCommand>, Receiver<SimCommand>) = channel();
+        let io_control = cpu.bus().io().control();
+        let control = Arc::clone(&io_control);
 
         thread::spawn(move || {
             let (output_tx, output_rx) = channel::<OutputEvent>();
             let (input_status_tx, input_status_rx) = channel::<bool>();
 
-            io_handler::set_output_sender(Some(output_tx));
+            let io = cpu.bus_mut().io_mut();
+            io.set_output_sender(Some(output_tx));
             if let Some(receiver) = input_receiver {
-                io_handler::set_input_receiver(Some(receiver));
+                io.set_input_receiver(Some(receiver));
             }
-            io_handler::set_input_status_sender(Some(input_status_tx));
-            io_handler::init_for_new_sim();
+            io.set_input_status_sender(Some(input_status_tx));
+            io.init_for_new_sim();
             publish_snapshot(&cpu, &event_sender, publish_debug_events);
             emit(&event_sender, SimulationEvent::Halted(cpu.is_halted()));
+            let mut last_ppi = cpu.bus().ppi().state();
+            emit(&event_sender, SimulationEvent::PpiState(last_ppi));
             flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
 
-            let mut running = false;
-            let mut cycles_limit = cycles_limit.map(|v| v.min(MAX_CYCLES_LIMIT));
+            let mut state = LoopState {
+                running: false,
+                cycles_limit: cycles_limit.map(|v| v.min(MAX_CYCLES_LIMIT)),
+                cycles_since_report: 0,
+                last_report: Instant::now(),
+            };
+            let events = EventChannels {
+                output_rx: &output_rx,
+                input_status_rx: &input_status_rx,
+                sender: &event_sender,
+                publish_debug_events,
+            };
             let mut last_halted = cpu.is_halted();
-            let mut cycles_since_report: u64 = 0;
-            let mut last_report = Instant::now();
             let mut last_state_report = Instant::now();
+            let mut debug = DebugState::default();
 
             loop {
-                if running {
-                    if io_handler::is_awaiting_input() {
-                        let _ = io_handler::poll_input_ready();
-                        flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
-                        if let Ok(cmd) = rx.try_recv() {
-                            match cmd {
-                                SimCommand::Run => running = true,
-                                SimCommand::Step => {
-                                    let _ = io_handler::poll_input_ready();
-                                    flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
-                                    let mut traces = Vec::new();
-                                    step_once(
-                                        &mut cpu,
-                                        true,
-                                        &mut cycles_since_report,
-                                        &mut traces,
-                                    );
-                                    emit_traces(&event_sender, traces);
-                                }
-                                SimCommand::Stop => {
-                                    let _ = io_handler::clear_input_aborted();
-                                    running = false;
-                                }
-                                SimCommand::Reset => {
-                                    let _ = io_handler::clear_input_aborted();
-                                    reset_cpu(
-                                        &mut cpu,
-                                        &event_sender,
-                                        publish_debug_events,
-                                        &mut cycles_since_report,
-                                        &mut last_report,
-                                    );
-                                }
-                                SimCommand::SetCyclesLimit(limit) => {
-                                    cycles_limit = limit.map(|v| v.min(MAX_CYCLES_LIMIT));
-                                }
-                            }
-                        }
-                        thread::sleep(Duration::from_millis(1));
-                        continue;
-                    }
-
-                    if cpu.is_halted() {
-                        running = false;
+                if state.running {
+                    if is_stopped(&cpu) {
+                        state.running = false;
                     } else {
                         let batch_start = Instant::now();
                         let mut steps = 0usize;
                         let mut batch_cycles = 0u64;
                         let mut batch_traces = Vec::new();
-                        let max_cycles = cycles_limit
+                        let max_cycles = state
+                            .cycles_limit
                             .map(|limit| (limit as f64 * LIMIT_SLEEP_WINDOW_SECS).ceil() as u64)
                             .unwrap_or(u64::MAX)
                             .max(1);
 
-                        while steps < RUN_BATCH_STEPS && !cpu.is_halted() && batch_cycles < max_cycles
+                        while steps < RUN_BATCH_STEPS && !is_stopped(&cpu) && batch_cycles < max_cycles
                         {
+                            if debug.should_break(&cpu) {
+                                state.running = false;
+                                emit(&event_sender, SimulationEvent::BreakpointHit(cpu.program_counter));
+                                emit(&event_sender, SimulationEvent::CpuState(cpu.snapshot()));
+                                publish_snapshot(&cpu, &event_sender, publish_debug_events);
+                                break;
+                            }
                             batch_cycles += step_once(
                                 &mut cpu,
                                 publish_debug_events,
-                                &mut cycles_since_report,
+                                &mut state.cycles_since_report,
                                 &mut batch_traces,
                             );
                             steps += 1;
 
-                            if io_handler::clear_input_aborted() {
-                                running = false;
+                            if report_stop_hits(&mut cpu, &event_sender) {
+                                state.running = false;
+                                debug.clear_target();
+                                publish_snapshot(&cpu, &event_sender, publish_debug_events);
+                                break;
+                            }
+
+                            if debug.reached_target(&cpu) {
+                                state.running = false;
+                                emit(&event_sender, SimulationEvent::TargetReached(cpu.program_counter));
+                                emit(&event_sender, SimulationEvent::CpuState(cpu.snapshot()));
+                                publish_snapshot(&cpu, &event_sender, publish_debug_events);
+                                break;
+                            }
+
+                            if control.clear_input_aborted() {
+                                state.running = false;
                                 break;
                             }
 
                             flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
 
                             if let Ok(cmd) = rx.try_recv() {
-                                match cmd {
-                                    SimCommand::Run => running = true,
-                                    SimCommand::Step => {
-                                        batch_cycles += step_once(
-                                            &mut cpu,
-                                            publish_debug_events,
-                                            &mut cycles_since_report,
-                                            &mut batch_traces,
-                                        );
-                                    }
-                                    SimCommand::Stop => {
-                                        let _ = io_handler::clear_input_aborted();
-                                        running = false;
-                                        break;
-                                    }
-                                    SimCommand::Reset => {
-                                        let _ = io_handler::clear_input_aborted();
-                                        reset_cpu(
-                                            &mut cpu,
-                                            &event_sender,
-                                            publish_debug_events,
-                                            &mut cycles_since_report,
-                                            &mut last_report,
-                                        );
+                                match handle_command(cmd, &mut cpu, &mut debug, &control, &mut state, &events, &mut batch_traces) {
+                                    LoopFlow::Stepped(cycles) => batch_cycles += cycles,
+                                    LoopFlow::Stopped => break,
+                                    LoopFlow::Reset => {
                                         batch_cycles = 0;
                                         break;
                                     }
-                                    SimCommand::SetCyclesLimit(limit) => {
-                                        cycles_limit = limit.map(|v| v.min(MAX_CYCLES_LIMIT));
-                                    }
+                                    LoopFlow::Continue => {}
                                 }
                             }
                         }
@@ -175,7 +177,7 @@ impl SimulationController {
                             emit(&event_sender, SimulationEvent::TraceBatch(batch_traces));
                         }
 
-                        if let Some(limit) = cycles_limit {
+                        if let Some(limit) = state.cycles_limit {
                             let expected = (batch_cycles as f64) / (limit as f64);
                             let actual = batch_start.elapsed().as_secs_f64();
                             if expected > actual {
@@ -185,52 +187,25 @@ impl SimulationController {
                     }
 
                     publish_halted(&cpu, &event_sender, &mut last_halted);
+                    publish_ppi(&cpu, &event_sender, &mut last_ppi);
+                    publish_io_log(&mut cpu, &event_sender);
                     flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
 
                     if let Ok(cmd) = rx.try_recv() {
-                        match cmd {
-                            SimCommand::Run => running = true,
-                            SimCommand::Step => {
-                                let _ = io_handler::poll_input_ready();
-                                flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
-                                let mut traces = Vec::new();
-                                step_once(
-                                    &mut cpu,
-                                    publish_debug_events,
-                                    &mut cycles_since_report,
-                                    &mut traces,
-                                );
-                                emit_traces(&event_sender, traces);
-                            }
-                            SimCommand::Stop => {
-                                let _ = io_handler::clear_input_aborted();
-                                running = false;
-                            }
-                            SimCommand::Reset => {
-                                let _ = io_handler::clear_input_aborted();
-                                reset_cpu(
-                                    &mut cpu,
-                                    &event_sender,
-                                    publish_debug_events,
-                                    &mut cycles_since_report,
-                                    &mut last_report,
-                                );
-                            }
-                            SimCommand::SetCyclesLimit(limit) => {
-                                cycles_limit = limit.map(|v| v.min(MAX_CYCLES_LIMIT));
-                            }
-                        }
+                        let mut traces = Vec::new();
+                        handle_command(cmd, &mut cpu, &mut debug, &control, &mut state, &events, &mut traces);
+                        emit_traces(&event_sender, traces);
                     }
 
-                    let elapsed = last_report.elapsed();
+                    let elapsed = state.last_report.elapsed();
                     if elapsed >= Duration::from_millis(500) {
-                        let cps = (cycles_since_report as f64) / elapsed.as_secs_f64();
+                        let cps = (state.cycles_since_report as f64) / elapsed.as_secs_f64();
                         emit(
                             &event_sender,
                             SimulationEvent::CyclesPerSecond(cps.round() as u64),
                         );
-                        cycles_since_report = 0;
-                        last_report = Instant::now();
+                        state.cycles_since_report = 0;
+                        state.last_report = Instant::now();
                     }
 
                     if publish_debug_events
@@ -245,66 +220,48 @@ impl SimulationController {
                     continue;
                 }
 
-                match rx.recv() {
-                    Ok(cmd) => match cmd {
-                        SimCommand::Run => running = true,
-                        SimCommand::Step => {
-                            let _ = io_handler::poll_input_ready();
-                            flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
-                            let mut traces = Vec::new();
-                            step_once(
-                                &mut cpu,
-                                publish_debug_events,
-                                &mut cycles_since_report,
-                                &mut traces,
-                            );
-                            emit_traces(&event_sender, traces);
-                            let input_aborted = io_handler::clear_input_aborted();
-                            let elapsed = last_report.elapsed();
-                            let cps = if elapsed.as_secs_f64() > 0.0 {
-                                (cycles_since_report as f64) / elapsed.as_secs_f64()
-                            } else {
-                                0.0
-                            };
-                            emit(
-                                &event_sender,
-                                SimulationEvent::CyclesPerSecond(cps.round() as u64),
-                            );
-                            publish_snapshot(&cpu, &event_sender, publish_debug_events);
-                            cycles_since_report = 0;
-                            last_report = Instant::now();
-                            last_state_report = Instant::now();
-                            publish_halted(&cpu, &event_sender, &mut last_halted);
-                            flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
-                            if input_aborted {
-                                running = false;
-                            }
-                        }
-                        SimCommand::Stop => {
-                            let _ = io_handler::clear_input_aborted();
-                            running = false;
-                        }
-                        SimCommand::Reset => {
-                            let _ = io_handler::clear_input_aborted();
-                            reset_cpu(
-                                &mut cpu,
-                                &event_sender,
-                                publish_debug_events,
-                                &mut cycles_since_report,
-                                &mut last_report,
-                            );
-                            last_state_report = Instant::now();
-                            publish_halted(&cpu, &event_sender, &mut last_halted);
-                        }
-                        SimCommand::SetCyclesLimit(limit) => {
-                            cycles_limit = limit.map(|v| v.min(MAX_CYCLES_LIMIT));
+                let Ok(cmd) = rx.recv() else { break };
+                let mut traces = Vec::new();
+                let flow = handle_command(cmd, &mut cpu, &mut debug, &control, &mut state, &events, &mut traces);
+                emit_traces(&event_sender, traces);
+                // a paused simulation reports everything a single step or reset changed
+                match flow {
+                    LoopFlow::Stepped(_) => {
+                        let input_aborted = control.clear_input_aborted();
+                        let elapsed = state.last_report.elapsed();
+                        let cps = if elapsed.as_secs_f64() > 0.0 {
+                            (state.cycles_since_report as f64) / elapsed.as_secs_f64()
+                        } else {
+                            0.0
+                        };
+                        emit(
+                            &event_sender,
+                            SimulationEvent::CyclesPerSecond(cps.round() as u64),
+                        );
+                        publish_snapshot(&cpu, &event_sender, publish_debug_events);
+                        state.cycles_since_report = 0;
+                        state.last_report = Instant::now();
+                        last_state_report = Instant::now();
+                        publish_halted(&cpu, &event_sender, &mut last_halted);
+                        flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
+                        if input_aborted {
+                            state.running = false;
                         }
-                    },
-                    Err(_) => break,
+                    }
+                    LoopFlow::Reset => {
+                        last_state_report = Instant::now();
+                        publish_halted(&cpu, &event_sender, &mut last_halted);
+                        flush_runtime_events(&output_rx, &input_status_rx, &event_sender);
+                    }
+                    // a wait ended by Stop is reported from here
+                    LoopFlow::Stopped => flush_runtime_events(&output_rx, &input_status_rx, &event_sender),
+                    LoopFlow::Continue => {}
                 }
+                publish_ppi(&cpu, &event_sender, &mut last_ppi);
+                publish_io_log(&mut cpu, &event_sender);
             }
         });
-        Self { tx }
+        Self { tx, io_control }
     }
 
     pub fn run(&self) {
@@ -316,12 +273,12 @@ impl SimulationController {
     }
 
     pub fn stop(&self) {
-        io_handler::abort_input_wait();
+        self.io_control.abort_input_wait();
         let _ = self.tx.send(SimCommand::Stop);
     }
 
     pub fn reset(&self) {
-        io_handler::reset_io_state();
+        self.io_control.reset_io_state();
         let _ = self.tx.send(SimCommand::Stop);
         let _ = self.tx.send(SimCommand::Reset);
     }
@@ -329,6 +286,258 @@ impl SimulationController {
     pub fn set_cycles_limit(&self, limit: Option<u64>) {
         let _ = self.tx.send(SimCommand::SetCyclesLimit(limit));
     }
+
+    pub fn step_over(&self) {
+        let _ = self.tx.send(SimCommand::StepOver);
+    }
+
+    pub fn step_out(&self) {
+        let _ = self.tx.send(SimCommand::StepOut);
+    }
+
+    pub fn run_to(&self, address: u16) {
+        let _ = self.tx.send(SimCommand::RunTo(address));
+    }
+
+    pub fn set_breakpoints(&self, addresses: Vec<u16>) {
+        let _ = self.tx.send(SimCommand::SetBreakpoints(addresses));
+    }
+
+    pub fn toggle_breakpoint(&self, address: u16) {
+        let _ = self.tx.send(SimCommand::ToggleBreakpoint(address));
+    }
+
+    pub fn set_breakpoint_condition(&self, address: u16, condition: Option<Condition>) {
+        let _ = self.tx.send(SimCommand::SetBreakpointCondition(address, condition));
+    }
+
+    pub fn set_watchpoints(&self, watchpoints: Vec<Watchpoint>) {
+        let _ = self.tx.send(SimCommand::SetWatchpoints(watchpoints));
+    }
+
+    pub fn set_port_breakpoints(&self, breakpoints: Vec<PortBreakpoint>) {
+        let _ = self.tx.send(SimCommand::SetPortBreakpoints(breakpoints));
+    }
+
+    pub fn set_io_logging(&self, enabled: bool) {
+        let _ = self.tx.send(SimCommand::SetIoLogging(enabled));
+    }
+
+    pub fn ppi_input(&self, input: PpiInput) {
+        let _ = self.tx.send(SimCommand::Ppi(input));
+    }
+}
+
+// loop variables a command can change
+struct LoopState {
+    running: bool,
+    cycles_limit: Option<u64>,
+    cycles_since_report: u64,
+    last_report: Instant,
+}
+
+struct EventChannels<'a> {
+    output_rx: &'a Receiver<OutputEvent>,
+    input_status_rx: &'a Receiver<bool>,
+    sender: &'a Sender<SimulationEvent>,
+    publish_debug_events: bool,
+}
+
+// what the loop that received a command still has to do about it
+enum LoopFlow {
+    Continue,
+    // a single step ran this many cycles, its trace is in the traces passed in
+    Stepped(u64),
+    Stopped,
+    Reset,
+}
+
+// the one place commands are applied, whether the simulation is running, paused or waiting for input
+fn handle_command(
+    cmd: SimCommand,
+    cpu: &mut Cpu<Mcs8Bus>,
+    debug: &mut DebugState,
+    control: &IoControl,
+    state: &mut LoopState,
+    events: &EventChannels,
+    traces: &mut Vec<InstructionTrace>,
+) -> LoopFlow {
+    match debug.prepare(cmd, cpu) {
+        SimCommand::Run | SimCommand::StepOver | SimCommand::StepOut | SimCommand::RunTo(_) => {
+            if !state.running {
+                debug.resume_at(cpu.program_counter);
+            }
+            state.running = true;
+        }
+        SimCommand::Step => {
+            let _ = cpu.bus_mut().io_mut().poll_input_ready();
+            flush_runtime_events(events.output_rx, events.input_status_rx, events.sender);
+            let cycles = step_once(cpu, events.publish_debug_events, &mut state.cycles_since_report, traces);
+            report_stop_hits(cpu, events.sender);
+            return LoopFlow::Stepped(cycles);
+        }
+        SimCommand::Stop => {
+            let _ = control.clear_input_aborted();
+            state.running = false;
+            return LoopFlow::Stopped;
+        }
+        SimCommand::Reset => {
+            let _ = control.clear_input_aborted();
+            reset_cpu(
+                cpu,
+                events.sender,
+                events.publish_debug_events,
+                &mut state.cycles_since_report,
+                &mut state.last_report,
+            );
+            return LoopFlow::Reset;
+        }
+        SimCommand::SetCyclesLimit(limit) => {
+            state.cycles_limit = limit.map(|v| v.min(MAX_CYCLES_LIMIT));
+        }
+        SimCommand::Ppi(input) => cpu.bus_mut().ppi_mut().apply_input(input),
+        SimCommand::SetBreakpoints(addresses) => debug.set_breakpoints(addresses),
+        SimCommand::ToggleBreakpoint(address) => debug.toggle_breakpoint(address),
+        SimCommand::SetBreakpointCondition(address, condition) => {
+            debug.set_condition(address, condition)
+        }
+        SimCommand::SetWatchpoints(watchpoints) => cpu.set_watchpoints(watchpoints),
+        SimCommand::SetPortBreakpoints(breakpoints) => cpu.set_port_breakpoints(breakpoints),
+        SimCommand::SetIoLogging(enabled) => cpu.set_io_logging(enabled),
+    }
+    LoopFlow::Continue
+}
+
+enum RunTarget {
+    // the stack pointer keeps recursive calls from stopping too deep
+    Address { address: u16, stack_pointer: Option<u16> },
+    // after the RET or Rcc that leaves the current routine, the return addresses of calls and
+    // interrupts taken inside it are kept so their own returns pass, whatever the routine pushed
+    Return { inner_returns: Vec<u16>, stack_pointer: u16 },
+}
+
+#[derive(Default)]
+struct DebugState {
+    breakpoints: HashMap<u16, Option<Condition>>,
+    // address the run was resumed from, its breakpoint is passed over once
+    resume_pc: Option<u16>,
+    target: Option<RunTarget>,
+}
+
+impl DebugState {
+    fn set_breakpoints(&mut self, addresses: Vec<u16>) {
+        self.breakpoints = addresses.into_iter().map(|address| (address, None)).collect();
+    }
+
+    fn toggle_breakpoint(&mut self, address: u16) {
+        if self.breakpoints.remove(&address).is_none() {
+            self.breakpoints.insert(address, None);
+        }
+    }
+
+    fn set_condition(&mut self, address: u16, condition: Option<Condition>) {
+        self.breakpoints.insert(address, condition);
+    }
+
+    fn resume_at(&mut self, pc: u16) {
+        self.resume_pc = Some(pc);
+    }
+
+    fn should_break<B: Bus>(&mut self, cpu: &Cpu<B>) -> bool {
+        let pc = cpu.program_counter;
+        if self.resume_pc.take() == Some(pc) {
+            return false;
+        }
+        let hit = match self.breakpoints.get(&pc) {
+            Some(Some(condition)) => condition.is_met(cpu),
+            Some(None) => true,
+            None => false,
+        };
+        if hit {
+            self.target = None;
+        }
+        hit
+    }
+
+    // arms the stop target of the stepping commands, they then run like Run
+    fn prepare<B: Bus>(&mut self, cmd: SimCommand, cpu: &Cpu<B>) -> SimCommand {
+        let target = match cmd {
+            SimCommand::StepOver => match call_length(cpu.bus.read(cpu.program_counter)) {
+                Some(length) => RunTarget::Address {
+                    address: cpu.program_counter.wrapping_add(length),
+                    stack_pointer: Some(cpu.stack_pointer),
+                },
+                None => return SimCommand::Step,
+            },
+            SimCommand::StepOut => RunTarget::Return { inner_returns: Vec::new(), stack_pointer: cpu.stack_pointer },
+            SimCommand::RunTo(address) => RunTarget::Address { address, stack_pointer: None },
+            SimCommand::Run | SimCommand::Stop | SimCommand::Reset => {
+                self.target = None;
+                return cmd;
+            }
+            _ => return cmd,
+        };
+        self.target = Some(target);
+        cmd
+    }
+
+    fn clear_target(&mut self) {
+        self.target = None;
+    }
+
+    // checked after every step of a run
+    fn reached_target<B: Bus>(&mut self, cpu: &Cpu<B>) -> bool {
+        let reached = match &mut self.target {
+            Some(RunTarget::Address { address, stack_pointer }) => {
+                cpu.program_counter == *address
+                    && stack_pointer.is_none_or(|stack_pointer| cpu.stack_pointer >= stack_pointer)
+            }
+            Some(RunTarget::Return { inner_returns, stack_pointer }) => {
+                let opcode = cpu.bus.read(cpu.instruction_address);
+                let before = std::mem::replace(stack_pointer, cpu.stack_pointer);
+                if cpu.stack_pointer == before.wrapping_sub(2) {
+                    let pushed = u16::from_le_bytes([
+                        cpu.bus.read(cpu.stack_pointer),
+                        cpu.bus.read(cpu.stack_pointer.wrapping_add(1)),
+                    ]);
+                    let after_call = call_length(opcode).map(|length| cpu.instruction_address.wrapping_add(length));
+                    // an interrupt pushes the address of the instruction it preempted
+                    if after_call == Some(pushed) || pushed == cpu.instruction_address {
+                        inner_returns.push(pushed);
+                    }
+                    false
+                } else if is_return(opcode) && cpu.stack_pointer == before.wrapping_add(2) {
+                    if inner_returns.last() == Some(&cpu.program_counter) {
+                        inner_returns.pop();
+                        false
+                    } else {
+                        true
+                    }
+                } else {
+                    false
+                }
+            }
+            None => false,
+        };
+        if reached {
+            self.target = None;
+        }
+        reached
+    }
+}
+
+// length of CALL, Cc and RST, the address after them is where they return
+fn call_length(opcode: u8) -> Option<u16> {
+    match opcode {
+        0xCD => Some(3),
+        _ if opcode & 0xC7 == 0xC4 => Some(3),
+        _ if opcode & 0xC7 == 0xC7 => Some(1),
+        _ => None,
+    }
+}
+
+fn is_return(opcode: u8) -> bool {
+    opcode == 0xC9 || opcode & 0xC7 == 0xC0
 }
 
 fn emit(sender: &Sender<SimulationEvent>, event: SimulationEvent) {
@@ -360,7 +569,7 @@ fn flush_runtime_events(
     }
 }
 
-fn publish_snapshot(cpu: &Cpu, event_sender: &Sender<SimulationEvent>, publish_debug_events: bool) {
+fn publish_snapshot(cpu: &Cpu<Mcs8Bus>, event_sender: &Sender<SimulationEvent>, publish_debug_events: bool) {
     if !publish_debug_events {
         return;
     }
@@ -372,7 +581,15 @@ fn publish_snapshot(cpu: &Cpu, event_sender: &Sender<SimulationEvent>, publish_d
     );
 }
 
-fn publish_halted(cpu: &Cpu, event_sender: &Sender<SimulationEvent>, last_halted: &mut bool) {
+fn is_stopped(cpu: &Cpu<Mcs8Bus>) -> bool {
+    cpu.is_halted() && !interrupt_expected(cpu)
+}
+
+fn interrupt_expected(cpu: &Cpu<Mcs8Bus>) -> bool {
+    cpu.interrupts_enabled() && (cpu.has_pending_interrupt() || cpu.bus().interrupt_source_armed())
+}
+
+fn publish_halted(cpu: &Cpu<Mcs8Bus>, event_sender: &Sender<SimulationEvent>, last_halted: &mut bool) {
     let halted = cpu.is_halted();
     if halted != *last_halted {
         emit(event_sender, SimulationEvent::Halted(halted));
@@ -380,15 +597,48 @@ fn publish_halted(cpu: &Cpu, event_sender: &Sender<SimulationEvent>, last_halted
     }
 }
 
+fn publish_ppi(cpu: &Cpu<Mcs8Bus>, event_sender: &Sender<SimulationEvent>, last_ppi: &mut PpiState) {
+    let state = cpu.bus().ppi().state();
+    if state != *last_ppi {
+        emit(event_sender, SimulationEvent::PpiState(state));
+        *last_ppi = state;
+    }
+}
+
+// watchpoint and port breakpoint hits of the last step
+fn report_stop_hits(cpu: &mut Cpu<Mcs8Bus>, event_sender: &Sender<SimulationEvent>) -> bool {
+    let watch_hit = cpu.take_watch_hit();
+    let port_hit = cpu.take_port_hit();
+    if let Some(hit) = watch_hit {
+        emit(event_sender, SimulationEvent::WatchpointHit(hit));
+    }
+    if let Some(access) = port_hit {
+        emit(event_sender, SimulationEvent::PortBreakpointHit(access));
+    }
+    let stopped = watch_hit.is_some() || port_hit.is_some();
+    if stopped {
+        publish_io_log(cpu, event_sender);
+        emit(event_sender, SimulationEvent::CpuState(cpu.snapshot()));
+    }
+    stopped
+}
+
+fn publish_io_log(cpu: &mut Cpu<Mcs8Bus>, event_sender: &Sender<SimulationEvent>) {
+    let accesses = cpu.take_io_log();
+    if !accesses.is_empty() {
+        emit(event_sender, SimulationEvent::IoLog(accesses));
+    }
+}
+
 fn step_once(
-    cpu: &mut Cpu,
+    cpu: &mut Cpu<Mcs8Bus>,
     emit_trace: bool,
     cycles_since_report: &mut u64,
     traces: &mut Vec<InstructionTrace>,
 ) -> u64 {
     if emit_trace {
         let (cycles, trace) = cpu.step_with_trace();
-        if !io_handler::take_trace_suppress() {
+        if !cpu.bus_mut().io_mut().take_trace_suppress() {
             traces.push(trace);
         }
         *cycles_since_report += cycles;
@@ -401,7 +651,7 @@ fn step_once(
 }
 
 fn reset_cpu(
-    cpu: &mut Cpu,
+    cpu: &mut Cpu<Mcs8Bus>,
     event_sender: &Sender<SimulationEvent>,
     publish_debug_events: bool,
     cycles_since_report: &mut u64,
@@ -0,0 +1,337 @@
+const COUNTERS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Lsb,
+    Msb,
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteState {
+    Lsb,
+    Msb,
+}
+
+#[derive(Debug, Clone)]
+struct Counter {
+    mode: u8,
+    bcd: bool,
+    access: Access,
+    write_state: WriteState,
+    read_msb_next: bool,
+    write_lsb: u8,
+    latched: Option<u16>,
+    period: u32,
+    pending_period: Option<u32>,
+    elapsed: u64,
+    loaded: bool,
+    counting: bool,
+    gate: bool,
+    out: bool,
+    rising_edge: bool,
+}
+
+impl Counter {
+    fn new() -> Self {
+        Self {
+            mode: 0,
+            bcd: false,
+            access: Access::Word,
+            write_state: WriteState::Lsb,
+            read_msb_next: false,
+            write_lsb: 0,
+            latched: None,
+            period: 0x10000,
+            pending_period: None,
+            elapsed: 0,
+            loaded: false,
+            counting: false,
+            gate: true,
+            out: true,
+            rising_edge: false,
+        }
+    }
+
+    fn modulus(&self) -> u32 {
+        if self.bcd { 10_000 } else { 0x10000 }
+    }
+
+    fn control(&mut self, value: u8) {
+        let access = match (value >> 4) & 0x03 {
+            0 => {
+                if self.latched.is_none() {
+                    self.latched = Some(self.encode(self.current_value()));
+                    self.read_msb_next = false;
+                }
+                return;
+            }
+            1 => Access::Lsb,
+            2 => Access::Msb,
+            _ => Access::Word,
+        };
+        let mode = (value >> 1) & 0x07;
+        // modes 6 and 7 are aliases of 2 and 3
+        self.mode = if mode >= 6 { mode - 4 } else { mode };
+        self.bcd = value & 0x01 != 0;
+        self.access = access;
+        self.write_state = WriteState::Lsb;
+        self.read_msb_next = false;
+        self.latched = None;
+        self.pending_period = None;
+        self.loaded = false;
+        self.counting = false;
+        self.elapsed = 0;
+        self.out = self.mode != 0;
+    }
+
+    fn write(&mut self, value: u8) {
+        let raw = match (self.access, self.write_state) {
+            (Access::Lsb, _) => value as u16,
+            (Access::Msb, _) => (value as u16) << 8,
+            (Access::Word, WriteState::Lsb) => {
+                self.write_lsb = value;
+                self.write_state = WriteState::Msb;
+                return;
+            }
+            (Access::Word, WriteState::Msb) => {
+                self.write_state = WriteState::Lsb;
+                ((value as u16) << 8) | self.write_lsb as u16
+            }
+        };
+        self.load(raw);
+    }
+
+    fn load(&mut self, raw: u16) {
+        let mut period = self.decode(raw);
+        if period == 0 {
+            period = self.modulus();
+        }
+        match self.mode {
+            0 | 4 => {
+                self.period = period;
+                self.start();
+            }
+            2 | 3 if self.counting => self.pending_period = Some(period),
+            2 | 3 => {
+                self.period = period;
+                self.start();
+            }
+            _ => self.period = period,
+        }
+        self.loaded = true;
+    }
+
+    fn start(&mut self) {
+        self.elapsed = 0;
+        self.counting = true;
+        self.out = self.output_at(0);
+    }
+
+    fn read(&mut self) -> u8 {
+        let value = self.latched.unwrap_or_else(|| self.encode(self.current_value()));
+        match self.access {
+            Access::Lsb => {
+                self.latched = None;
+                value as u8
+            }
+            Access::Msb => {
+                self.latched = None;
+                (value >> 8) as u8
+            }
+            Access::Word => {
+                let msb = self.read_msb_next;
+                self.read_msb_next = !msb;
+                if msb {
+                    self.latched = None;
+                    (value >> 8) as u8
+                } else {
+                    value as u8
+                }
+            }
+        }
+    }
+
+    fn set_gate(&mut self, level: bool) {
+        let rising = level && !self.gate;
+        self.gate = level;
+        match self.mode {
+            1 | 5 if rising && self.loaded => self.start(),
+            2 | 3 if !level => self.out = true,
+            2 | 3 if rising && self.loaded => self.start(),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, clocks: u64) {
+        if !self.counting || clocks == 0 {
+            return;
+        }
+        if !self.gate && matches!(self.mode, 0 | 2 | 3 | 4) {
+            return;
+        }
+        let start = self.elapsed;
+        let end = start + clocks;
+        let period = self.period as u64;
+        match self.mode {
+            0 | 1 => {
+                if start < period && end >= period {
+                    self.rising_edge = true;
+                }
+            }
+            4 | 5 => {
+                if start <= period && end > period {
+                    self.rising_edge = true;
+                }
+            }
+            _ => {
+                if end / period > start / period {
+                    if self.mode == 2 || period > 1 {
+                        self.rising_edge = true;
+                    }
+                    if let Some(next) = self.pending_period.take() {
+                        self.period = next;
+                        self.elapsed = 0;
+                        let remaining = clocks - (period - start % period);
+                        self.out = self.output_at(0);
+                        self.tick(remaining);
+                        return;
+                    }
+                }
+            }
+        }
+        self.elapsed = match self.mode {
+            2 | 3 => end % period,
+            _ => end,
+        };
+        self.out = self.output_at(self.elapsed);
+    }
+
+    fn is_armed(&self) -> bool {
+        if !self.counting {
+            return false;
+        }
+        match self.mode {
+            0 | 1 => self.elapsed < self.period as u64,
+            4 | 5 => self.elapsed <= self.period as u64,
+            _ => self.gate,
+        }
+    }
+
+    fn output_at(&self, elapsed: u64) -> bool {
+        let period = self.period as u64;
+        match self.mode {
+            0 | 1 => elapsed >= period,
+            2 => period == 1 || elapsed % period != period - 1,
+            3 => elapsed % period < period.div_ceil(2),
+            _ => elapsed != period,
+        }
+    }
+
+    fn current_value(&self) -> u32 {
+        let modulus = self.modulus() as u64;
+        let period = self.period as u64;
+        if !self.counting {
+            return self.period % self.modulus();
+        }
+        let value = match self.mode {
+            2 => period - self.elapsed % period,
+            3 => {
+                let position = self.elapsed % period;
+                let high = period.div_ceil(2);
+                let half_left = if position < high { high - position } else { period - position };
+                half_left * 2
+            }
+            _ => (period + modulus - self.elapsed % modulus) % modulus,
+        };
+        (value % modulus) as u32
+    }
+
+    fn encode(&self, value: u32) -> u16 {
+        if !self.bcd {
+            return value as u16;
+        }
+        let mut encoded = 0u16;
+        let mut rest = value;
+        for shift in [0, 4, 8, 12] {
+            encoded |= ((rest % 10) as u16) << shift;
+            rest /= 10;
+        }
+        encoded
+    }
+
+    fn decode(&self, raw: u16) -> u32 {
+        if !self.bcd {
+            return raw as u32;
+        }
+        [12, 8, 4, 0]
+            .iter()
+            .fold(0, |value, shift| value * 10 + ((raw >> shift) & 0x0F) as u32)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pit8253 {
+    counters: [Counter; COUNTERS],
+}
+
+impl Default for Pit8253 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pit8253 {
+    pub fn new() -> Self {
+        Self {
+            counters: [Counter::new(), Counter::new(), Counter::new()],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // port is the offset from the base address: 0-2 counters, 3 control word
+    pub fn write(&mut self, port: u8, value: u8) {
+        match port & 0x03 {
+            3 => {
+                let counter = (value >> 6) as usize;
+                if counter < COUNTERS {
+                    self.counters[counter].control(value);
+                }
+            }
+            counter => self.counters[counter as usize].write(value),
+        }
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        match port & 0x03 {
+            3 => 0xFF,
+            counter => self.counters[counter as usize].read(),
+        }
+    }
+
+    pub fn tick(&mut self, clocks: u64) {
+        for counter in &mut self.counters {
+            counter.tick(clocks);
+        }
+    }
+
+    pub fn set_gate(&mut self, counter: usize, level: bool) {
+        self.counters[counter].set_gate(level);
+    }
+
+    pub fn output(&self, counter: usize) -> bool {
+        self.counters[counter].out
+    }
+
+    // true while the counter can still produce a rising edge on its output
+    pub fn is_armed(&self, counter: usize) -> bool {
+        self.counters[counter].is_armed()
+    }
+
+    pub fn take_rising_edge(&mut self, counter: usize) -> bool {
+        std::mem::take(&mut self.counters[counter].rising_edge)
+    }
+}
@@ -0,0 +1,268 @@
+// mode word
+const PARITY_ENABLE: u8 = 0x10;
+const EVEN_PARITY: u8 = 0x20;
+// sync mode only
+const EXTERNAL_SYNC: u8 = 0x40;
+const SINGLE_SYNC: u8 = 0x80;
+
+// command word
+const TX_ENABLE: u8 = 0x01;
+const RX_ENABLE: u8 = 0x04;
+const ERROR_RESET: u8 = 0x10;
+const INTERNAL_RESET: u8 = 0x40;
+const ENTER_HUNT: u8 = 0x80;
+
+// status word
+pub const STATUS_TX_READY: u8 = 0x01;
+pub const STATUS_RX_READY: u8 = 0x02;
+pub const STATUS_TX_EMPTY: u8 = 0x04;
+pub const STATUS_PARITY_ERROR: u8 = 0x08;
+pub const STATUS_OVERRUN_ERROR: u8 = 0x10;
+pub const STATUS_FRAMING_ERROR: u8 = 0x20;
+// SYNDET in sync mode, BRKDET in async mode
+pub const STATUS_SYNC_DETECT: u8 = 0x40;
+pub const STATUS_DSR: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlState {
+    Mode,
+    SyncChar { remaining: u8 },
+    Command,
+}
+
+#[derive(Debug, Clone)]
+pub struct Usart8251 {
+    control_state: ControlState,
+    mode: u8,
+    command: u8,
+    rx_data: u8,
+    rx_ready: bool,
+    tx_data: u8,
+    tx_full: bool,
+    errors: u8,
+    dsr: bool,
+    // CTS input, the transmitter holds its character while the line is not clear
+    cts: bool,
+    sync_chars: [u8; 2],
+    // sync mode receiver looking for the sync characters, nothing is assembled meanwhile
+    hunting: bool,
+    // the first of two sync characters was just seen
+    first_sync_seen: bool,
+    // SYNDET or BRKDET
+    sync_detect: bool,
+}
+
+impl Default for Usart8251 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Usart8251 {
+    pub fn new() -> Self {
+        Self {
+            control_state: ControlState::Mode,
+            mode: 0,
+            command: 0,
+            rx_data: 0,
+            rx_ready: false,
+            tx_data: 0,
+            tx_full: false,
+            errors: 0,
+            dsr: true,
+            cts: true,
+            sync_chars: [0; 2],
+            hunting: false,
+            first_sync_seen: false,
+            sync_detect: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self { dsr: self.dsr, cts: self.cts, ..Self::new() };
+    }
+
+    pub fn set_dsr(&mut self, active: bool) {
+        self.dsr = active;
+    }
+
+    // returns the held character when the line becomes clear again
+    pub fn set_cts(&mut self, active: bool) -> Option<u8> {
+        self.cts = active;
+        self.transmit()
+    }
+
+    // returns a character released to the line when TxEN gets set with a byte still held
+    pub fn write_control(&mut self, value: u8) -> Option<u8> {
+        match self.control_state {
+            ControlState::Mode => {
+                self.mode = value;
+                self.control_state = if self.is_sync_mode() {
+                    ControlState::SyncChar { remaining: self.sync_char_count() }
+                } else {
+                    ControlState::Command
+                };
+                None
+            }
+            ControlState::SyncChar { remaining } => {
+                let count = self.sync_char_count();
+                self.sync_chars[(count - remaining) as usize] = value;
+                self.control_state = if remaining > 1 {
+                    ControlState::SyncChar { remaining: remaining - 1 }
+                } else {
+                    ControlState::Command
+                };
+                None
+            }
+            ControlState::Command => {
+                if value & INTERNAL_RESET != 0 {
+                    self.reset();
+                    return None;
+                }
+                self.command = value;
+                if value & ERROR_RESET != 0 {
+                    self.errors = 0;
+                }
+                if value & ENTER_HUNT != 0 && self.is_sync_mode() && self.mode & EXTERNAL_SYNC == 0 {
+                    self.hunting = true;
+                    self.first_sync_seen = false;
+                    self.sync_detect = false;
+                }
+                self.transmit()
+            }
+        }
+    }
+
+    pub fn write_data(&mut self, value: u8) -> Option<u8> {
+        self.tx_data = value;
+        self.tx_full = true;
+        self.transmit()
+    }
+
+    // in sync mode reading the status clears SYNDET, like the real part
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = self.errors;
+        if self.sync_detect {
+            status |= STATUS_SYNC_DETECT;
+            if self.is_sync_mode() {
+                self.sync_detect = false;
+            }
+        }
+        if !self.tx_full {
+            status |= STATUS_TX_READY | STATUS_TX_EMPTY;
+        }
+        if self.rx_ready {
+            status |= STATUS_RX_READY;
+        }
+        if self.dsr {
+            status |= STATUS_DSR;
+        }
+        status
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        self.rx_ready = false;
+        self.rx_data
+    }
+
+    pub fn can_receive(&self) -> bool {
+        self.receiver_enabled() && !self.rx_ready
+    }
+
+    pub fn receiver_enabled(&self) -> bool {
+        self.control_state == ControlState::Command && self.command & RX_ENABLE != 0
+    }
+
+    pub fn rx_ready(&self) -> bool {
+        self.rx_ready
+    }
+
+    // a well formed character, with the parity the mode word asks for and a stop bit
+    pub fn receive(&mut self, value: u8) {
+        let parity_bit = self.expected_parity_bit(value);
+        self.receive_frame(value, parity_bit, true);
+    }
+
+    // a character with the parity and stop bits as they arrived on the line,
+    // the parity bit is ignored without PEN and the stop bit in sync mode
+    pub fn receive_frame(&mut self, value: u8, parity_bit: bool, stop_bit: bool) {
+        if !self.receiver_enabled() {
+            return;
+        }
+        let value = value & self.char_mask();
+        let parity_error = self.mode & PARITY_ENABLE != 0 && parity_bit != self.expected_parity_bit(value);
+
+        if self.is_sync_mode() {
+            if self.hunting {
+                self.hunt(value);
+                return;
+            }
+        } else if stop_bit {
+            // the line went back to marking
+            self.sync_detect = false;
+        } else {
+            self.errors |= STATUS_FRAMING_ERROR;
+            // an all zero character without a stop bit is the line held in break
+            if value == 0 && (self.mode & PARITY_ENABLE == 0 || !parity_bit) {
+                self.sync_detect = true;
+            }
+        }
+
+        if parity_error {
+            self.errors |= STATUS_PARITY_ERROR;
+        }
+        if self.rx_ready {
+            self.errors |= STATUS_OVERRUN_ERROR;
+        }
+        self.rx_data = value;
+        self.rx_ready = true;
+    }
+
+    fn hunt(&mut self, value: u8) {
+        let [first, second] = self.sync_chars;
+        let found = if self.sync_char_count() == 1 {
+            value == first
+        } else if self.first_sync_seen && value == second {
+            true
+        } else {
+            self.first_sync_seen = value == first;
+            false
+        };
+        if found {
+            self.hunting = false;
+            self.first_sync_seen = false;
+            self.sync_detect = true;
+        }
+    }
+
+    // the bit that makes the count of ones even or odd, as the mode word selects
+    fn expected_parity_bit(&self, value: u8) -> bool {
+        let odd_ones = (value & self.char_mask()).count_ones() % 2 == 1;
+        if self.mode & EVEN_PARITY != 0 { odd_ones } else { !odd_ones }
+    }
+
+    fn transmit(&mut self) -> Option<u8> {
+        if !self.tx_full
+            || !self.cts
+            || self.control_state != ControlState::Command
+            || self.command & TX_ENABLE == 0
+        {
+            return None;
+        }
+        self.tx_full = false;
+        Some(self.tx_data & self.char_mask())
+    }
+
+    fn is_sync_mode(&self) -> bool {
+        self.mode & 0x03 == 0
+    }
+
+    fn sync_char_count(&self) -> u8 {
+        if self.mode & SINGLE_SYNC != 0 { 1 } else { 2 }
+    }
+
+    fn char_mask(&self) -> u8 {
+        let bits = 5 + ((self.mode >> 2) & 0x03);
+        (0xFFu16 >> (8 - bits)) as u8
+    }
+}
@@ -0,0 +1,99 @@
+use crate::assembler::expressions::DebugContext;
+
+use super::Cpu;
+use super::bus::Bus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, access: WatchKind) -> bool {
+        self == WatchKind::Access || self == access
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, kind: WatchKind) -> Self {
+        Watchpoint { start: start.min(end), end: start.max(end), kind }
+    }
+
+    pub fn triggers(&self, address: u16, access: WatchKind) -> bool {
+        (self.start..=self.end).contains(&address) && self.kind.matches(access)
+    }
+}
+
+// kind is Read or Write, old and new values are equal for reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub pc: u16,
+    pub address: u16,
+    pub kind: WatchKind,
+    pub old_value: u8,
+    pub new_value: u8,
+}
+
+// Read stands for IN, Write for OUT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortBreakpoint {
+    pub port: u8,
+    pub kind: WatchKind,
+}
+
+impl PortBreakpoint {
+    pub fn triggers(&self, port: u8, access: WatchKind) -> bool {
+        self.port == port && self.kind.matches(access)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortAccess {
+    pub cycle: u64,
+    pub pc: u16,
+    pub kind: WatchKind,
+    pub port: u8,
+    pub value: u8,
+}
+
+impl<B: Bus> DebugContext for Cpu<B> {
+    fn register(&self, name: &str) -> Option<i32> {
+        let pair = |high: u8, low: u8| (high as i32) << 8 | low as i32;
+        let flag = |mask: u8| (self.flags & mask != 0) as i32;
+        Some(match name {
+            "A" => self.a_reg as i32,
+            "B" => self.b_reg as i32,
+            "C" => self.c_reg as i32,
+            "D" => self.d_reg as i32,
+            "E" => self.e_reg as i32,
+            "H" => self.h_reg as i32,
+            "L" => self.l_reg as i32,
+            "M" => self.bus.read(u16::from_be_bytes([self.h_reg, self.l_reg])) as i32,
+            "BC" => pair(self.b_reg, self.c_reg),
+            "DE" => pair(self.d_reg, self.e_reg),
+            "HL" => pair(self.h_reg, self.l_reg),
+            "PSW" => pair(self.a_reg, self.flags),
+            "SP" => self.stack_pointer as i32,
+            "PC" => self.program_counter as i32,
+            "S" => flag(0x80),
+            "Z" => flag(0x40),
+            "AC" => flag(0x10),
+            "P" => flag(0x04),
+            "CY" => flag(0x01),
+            _ => return None,
+        })
+    }
+
+    fn read_memory(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+}
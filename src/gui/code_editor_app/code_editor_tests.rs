@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use iced::window;
+
+use crate::hex::OutputFormat;
+
+use super::utils::{with_output_extension, DebugWindowOrder};
+
+#[test]
+fn debug_commands_go_to_the_latest_started_or_focused_simulation() {
+    let (first, second) = (window::Id::unique(), window::Id::unique());
+    let mut order = DebugWindowOrder::default();
+    assert_eq!(order.latest(), None);
+
+    order.touch(first);
+    order.touch(second);
+    assert_eq!(order.latest(), Some(second));
+
+    // focusing the older debug window makes it the target again
+    order.touch(first);
+    assert_eq!(order.latest(), Some(first));
+
+    order.remove(first);
+    assert_eq!(order.latest(), Some(second));
+    order.remove(second);
+    assert_eq!(order.latest(), None);
+}
+
+#[test]
+fn compiled_files_get_the_extension_of_the_picked_format() {
+    let path = |name: &str, format| with_output_extension(PathBuf::from(name), format);
+    assert_eq!(path("prog", OutputFormat::IntelHex), PathBuf::from("prog.hex"));
+    assert_eq!(path("prog.v1", OutputFormat::SRecord), PathBuf::from("prog.v1.s19"));
+    assert_eq!(path("prog.IHX", OutputFormat::IntelHex), PathBuf::from("prog.IHX"));
+    assert_eq!(path("prog.hex", OutputFormat::Binary), PathBuf::from("prog.hex.bin"));
+    assert_eq!(path("prog.bin", OutputFormat::Binary), PathBuf::from("prog.bin"));
+}
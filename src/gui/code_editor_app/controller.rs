@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
@@ -10,13 +11,14 @@ use iced::{Subscription, Task, event, futures::SinkExt, keyboard, stream, window
 use iced::keyboard::key::Named::Enter;
 
 use crate::assembler::Assembler;
-use crate::cpu::{Cpu, CpuState, io_handler::OutputEvent, simulation_controller::{SimulationController, SimulationEvent}};
+use crate::cpu::{Cpu, CpuState, Mcs8Bus, io_handler::OutputEvent, ppi::{PpiInput, PpiState}, serial::{self, SerialLink}, watch::WatchKind, simulation_controller::{SimulationController, SimulationEvent}};
 use crate::encoding;
-use crate::gui::{deassembly, memory, preferences::Preferences, registers, simulation};
+use crate::hex::{self, Segment};
+use crate::gui::{deassembly, io_log, memory, ppi, preferences::{Preferences, SerialBackend, SerialPreferences}, registers, simulation};
 
-use super::utils::{build_gutter_text, copy_trimmed_nonzero_slice, normalize_output_chunk};
+use super::utils::{build_gutter_text, with_output_extension, shift_breakpoint_lines, is_hex_path, normalize_output_chunk, parse_address, parse_port_breakpoint, parse_watchpoint, resolve_address, split_breakpoint_condition};
 use super::{
-    AsyncMessage, CodeEditorApp, HScrollSource, Message, SimulationState, WindowKind,
+    AsyncMessage, CodeEditorApp, HScrollSource, MemoryImage, Message, SimulationState, UnsavedAction, UnsavedChoice, WindowKind,
     EDITOR_LINE_HEIGHT, EDITOR_SCROLL_ID, MAX_FONT_SIZE, MEMORY_SIZE, MIN_FONT_SIZE,
 };
 
@@ -84,7 +86,7 @@ impl CodeEditorApp {
         }
         let content = text_editor::Content::with_text("ORG 800h\n");
         let line_count = content.line_count().max(1);
-        let gutter_text = build_gutter_text(line_count);
+        let gutter_text = build_gutter_text(line_count, &BTreeSet::new());
         let line_lengths: Vec<usize> = content
             .lines()
             .map(|line| line.text.chars().count())
@@ -93,6 +95,8 @@ impl CodeEditorApp {
         let mut main_settings = window::Settings {
             size: Size::new(1024.0, 768.0),
             min_size: Some(Size::new(1024.0, 768.0)),
+            // closing goes through the unsaved changes prompt
+            exit_on_close_request: false,
             ..window::Settings::default()
         };
         if let Some(mut geom) = preferences.main_window {
@@ -117,19 +121,29 @@ impl CodeEditorApp {
                 font_size: preferences.font_size,
                 font_size_input: format!("{:.0}", preferences.font_size),
                 error_message: None,
-                error_line: None,
+                diagnostics: Vec::new(),
+                pc_line: None,
+                current_file: None,
+                dirty: false,
+                unsaved_action: None,
                 gutter_text,
+                breakpoint_lines: BTreeSet::new(),
+                gutter_hover_line: None,
                 max_line_len,
                 line_lengths,
                 hscroll_x: 0.0,
                 at_bottom: true,
                 load_bios: preferences.load_bios,
+                images: Vec::new(),
+                assemble_source: true,
                 theme: preferences.theme,
+                usart1_port_input: preferences.usart1.tcp_port.to_string(),
                 main_window,
                 simulation_windows: std::collections::HashMap::new(),
                 async_message_sender,
                 async_message_receiver: Arc::new(Mutex::new(async_message_receiver)),
                 pending_simulation_launch: None,
+                debug_windows: Default::default(),
                 preferences,
                 window_kinds,
             },
@@ -167,26 +181,48 @@ impl CodeEditorApp {
 
                 let mut grew = false;
                 if let Some(edit_action) = edit_action {
-                    if let Some(error_line) = self.error_line {
-                        let touched_start = prev_line;
-                        let mut touched_end = prev_line;
-                        if let text_editor::Edit::Paste(text) = &edit_action {
-                            if text.contains('\n') {
-                                touched_end = prev_line + text.matches('\n').count();
-                            }
-                        }
-                        if (error_line >= touched_start && error_line <= touched_end)
-                            || current_line == error_line
-                        {
-                            self.error_line = None;
-                        }
+                    self.dirty = true;
+                    let touched_start = prev_line;
+                    let mut touched_end = prev_line;
+                    if let text_editor::Edit::Paste(text) = &edit_action && text.contains('\n') {
+                        touched_end = prev_line + text.matches('\n').count();
                     }
+                    // diagnostics are 1-based
+                    self.diagnostics.retain(|d| {
+                        let line = d.line_number.wrapping_sub(1);
+                        if d.file.is_some() {
+                            return true;
+                        }
+                        !((line >= touched_start && line <= touched_end) || line == current_line)
+                    });
 
                     let line_count = self.code.line_count();
                     grew = line_count > self.last_line_count;
+                    let delta = line_count as isize - self.last_line_count as isize;
                     self.last_line_count = line_count;
-                    if grew {
-                        self.gutter_text = build_gutter_text(self.last_line_count.max(1));
+                    let shifted = delta != 0 && !self.breakpoint_lines.is_empty();
+                    if delta != 0 {
+                        // same rule as breakpoints, lines pulled into the edit are dropped
+                        let after = prev_line.min(current_line) + 1;
+                        self.diagnostics.retain_mut(|d| {
+                            if d.file.is_some() || d.line_number <= after {
+                                return true;
+                            }
+                            let moved = d.line_number as isize + delta;
+                            d.line_number = moved.max(0) as usize;
+                            moved > after as isize
+                        });
+                    }
+                    if shifted {
+                        self.breakpoint_lines = shift_breakpoint_lines(
+                            &self.breakpoint_lines,
+                            prev_line.min(current_line),
+                            delta,
+                        );
+                    }
+                    if grew || shifted {
+                        self.gutter_text =
+                            build_gutter_text(self.last_line_count.max(1), &self.breakpoint_lines);
                     }
 
                     let needs_full_rebuild = matches!(
@@ -288,6 +324,50 @@ impl CodeEditorApp {
                 self.theme = theme;
                 self.preferences.theme = theme;
             }
+            Message::Usart1BackendSelected(backend) => {
+                self.preferences.usart1.backend = backend;
+            }
+            Message::Usart1InputFileChanged(path) => {
+                self.preferences.usart1.input_file = path;
+            }
+            Message::Usart1OutputFileChanged(path) => {
+                self.preferences.usart1.output_file = path;
+            }
+            Message::Usart1PortInputChanged(value) => {
+                if let Ok(port) = value.trim().parse::<u16>() {
+                    self.preferences.usart1.tcp_port = port;
+                }
+                self.usart1_port_input = value;
+            }
+            Message::ToggleAssembleSource(v) => {
+                self.assemble_source = v;
+            }
+            Message::AddImage => {
+                task = Task::perform(
+                    async {
+                        rfd::FileDialog::new()
+                            .add_filter("Memory image", &["hex", "ihx", "bin"])
+                            .add_filter("All files", &["*"])
+                            .pick_file()
+                    },
+                    Message::AddImagePicked,
+                );
+            }
+            Message::AddImagePicked(path) => {
+                if let Some(path) = path {
+                    self.images.push(MemoryImage { path, load_address_input: "0000".into() });
+                }
+            }
+            Message::RemoveImage(index) => {
+                if index < self.images.len() {
+                    self.images.remove(index);
+                }
+            }
+            Message::ImageLoadAddressChanged(index, value) => {
+                if let Some(image) = self.images.get_mut(index) {
+                    image.load_address_input = value;
+                }
+            }
             Message::LoadFile => {
                 task = Task::perform(
                     async {
@@ -300,17 +380,14 @@ impl CodeEditorApp {
             }
             Message::LoadFilePicked(path) => {
                 if let Some(path) = path {
-                    task = Task::perform(
-                        async move {
-                            std::fs::read_to_string(&path)
-                                .map_err(|e| format!("Nie można odczytać pliku: {e}"))
-                        },
-                        Message::FileLoaded,
-                    );
+                    task = self.guard_unsaved_changes(UnsavedAction::Open(path));
                 }
             }
+            Message::OpenRecent(recent) => {
+                task = self.guard_unsaved_changes(UnsavedAction::Open(recent.0));
+            }
             Message::FileLoaded(result) => match result {
-                Ok(text) => {
+                Ok((path, text)) => {
                     let line_lengths: Vec<usize> = text
                         .lines()
                         .map(|line| line.chars().count())
@@ -318,73 +395,205 @@ impl CodeEditorApp {
                     let max_line_len = line_lengths.iter().copied().max().unwrap_or(0);
                     self.code = text_editor::Content::with_text(&text);
                     self.last_line_count = self.code.line_count();
-                    self.gutter_text = build_gutter_text(self.last_line_count.max(1));
+                    self.breakpoint_lines.clear();
+                    self.gutter_text = build_gutter_text(self.last_line_count.max(1), &self.breakpoint_lines);
                     self.error_message = None;
-                    self.error_line = None;
+                    self.diagnostics.clear();
                     self.max_line_len = max_line_len;
                     self.line_lengths = line_lengths;
+                    self.preferences.add_recent_file(path.clone());
+                    self.current_file = Some(path);
+                    self.dirty = false;
+                }
+                Err(err) => {
+                    self.error_message = Some(err);
+                    self.diagnostics.clear();
+                }
+            },
+            Message::Save => {
+                task = match self.current_file.clone() {
+                    Some(path) => self.save_source_file(path),
+                    None => save_source_dialog(),
+                };
+            }
+            Message::SaveAs => {
+                task = save_source_dialog();
+            }
+            Message::SavePicked(path) => match path {
+                Some(path) => task = self.save_source_file(path),
+                None => self.unsaved_action = None,
+            },
+            Message::FileSaved(result) => match result {
+                Ok(path) => {
+                    self.preferences.add_recent_file(path.clone());
+                    self.current_file = Some(path);
+                    self.dirty = false;
+                    if let Some(action) = self.unsaved_action.take() {
+                        return self.run_unsaved_action(action);
+                    }
                 }
                 Err(err) => {
                     self.error_message = Some(err);
-                    self.error_line = None;
+                    self.unsaved_action = None;
                 }
             },
+            Message::UnsavedChangesAnswered(choice) => match choice {
+                UnsavedChoice::Save => {
+                    task = match self.current_file.clone() {
+                        Some(path) => self.save_source_file(path),
+                        None => save_source_dialog(),
+                    };
+                }
+                UnsavedChoice::Discard => {
+                    if let Some(action) = self.unsaved_action.take() {
+                        return self.run_unsaved_action(action);
+                    }
+                }
+                UnsavedChoice::Cancel => self.unsaved_action = None,
+            },
             Message::CloseError => {
                 self.error_message = None;
-                self.error_line = None;
+                self.diagnostics.clear();
+            }
+            Message::GutterHovered(y) => {
+                let line_height = LineHeight::Relative(EDITOR_LINE_HEIGHT);
+                let line_height_px = line_height.to_absolute(iced::Pixels(self.font_size)).0;
+                let line = (y / line_height_px).floor();
+                self.gutter_hover_line = (line >= 0.0 && (line as usize) < self.last_line_count)
+                    .then_some(line as usize);
+            }
+            Message::GutterClicked => {
+                if let Some(line) = self.gutter_hover_line {
+                    let added = self.breakpoint_lines.insert(line);
+                    if !added {
+                        self.breakpoint_lines.remove(&line);
+                    }
+                    self.gutter_text = build_gutter_text(self.last_line_count.max(1), &self.breakpoint_lines);
+                    // breakpoints belong to the source, so every debug simulation gets them
+                    for state in self.simulation_windows.values_mut().filter(|state| state.debug_mode) {
+                        let Some(address) = state.assembler.source_map().address_for_line(0, line + 1) else {
+                            continue;
+                        };
+                        if added != state.breakpoints.contains_key(&address) {
+                            if added {
+                                state.breakpoints.insert(address, None);
+                            } else {
+                                state.breakpoints.remove(&address);
+                            }
+                            state.controller.toggle_breakpoint(address);
+                        }
+                    }
+                }
+            }
+            Message::RunToCursor => {
+                let line = self.code.cursor().position.line;
+                let target = self.debug_windows.latest();
+                if let Some(state) = target.and_then(|id| self.simulation_windows.get_mut(&id)) {
+                    match state.assembler.source_map().address_for_line(0, line + 1) {
+                        Some(address) => {
+                            self.pc_line = None;
+                            state.controller.run_to(address);
+                            state.is_running = true;
+                            state.stop_reason = None;
+                        }
+                        None => {
+                            self.error_message = Some(format!("No code at or below line {}", line + 1));
+                        }
+                    }
+                }
             }
             Message::Run | Message::RunDebug => {
                 let debug_mode = matches!(message, Message::RunDebug);
                 task = self.queue_or_start_simulation(debug_mode);
             }
-            Message::CompileToBin => {
-                let mut assembler = Assembler::new();
+            Message::CompileToBin(format) => {
+                let mut assembler = self.source_assembler();
                 match assembler.assemble(&self.code.text()) {
-                    Ok(assembled) => {
+                    Ok(_) => {
                         self.error_message = None;
-                        self.error_line = None;
+                        self.diagnostics = assembler.diagnostics().to_vec();
+                        let segments = assembler.segments();
+                        let start = assembler.start_address();
                         task = Task::perform(
                             async move {
                                 (
                                     rfd::FileDialog::new()
-                                        .add_filter("Binary", &["bin"])
-                                        .add_filter("All files", &["*"])
+                                        .add_filter(format.to_string(), format.extensions())
                                         .save_file(),
-                                    assembled,
+                                    segments,
                                 )
                             },
-                            |(path, assembled)| Message::CompileToBinPicked(path, Vec::from(assembled)),
+                            move |(path, segments)| Message::CompileToBinPicked(path, format, segments, start),
                         );
                     }
                     Err(err) => {
-                        self.error_line = err.line_number.checked_sub(1);
+                        self.diagnostics = assembler.diagnostics().to_vec();
                         self.error_message = Some(err.to_string());
                     }
                 }
             }
-            Message::CompileToBinPicked(path, assembled) => {
+            Message::CompileToBinPicked(path, format, segments, start) => {
                 if let Some(path) = path {
                     task = Task::perform(
                         async move {
-                            let trimmed = trim_trailing_zeros(&assembled);
-                            std::fs::write(&path, trimmed)
+                            let path = with_output_extension(path, format);
+                            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+                            let contents = format.write(&segments, start, name);
+                            std::fs::write(&path, contents)
                                 .map_err(|e| format!("Nie mozna zapisac pliku: {e}"))
                         },
                         Message::CompileToBinSaved,
                     );
                 }
             }
-            Message::CompileToBinSaved(result) => match result {
+            Message::CompileToListing => {
+                let source = self.code.text();
+                let mut assembler = self.source_assembler();
+                match assembler.assemble(&source) {
+                    Ok(_) => {
+                        self.error_message = None;
+                        self.diagnostics = assembler.diagnostics().to_vec();
+                        let listing = assembler.listing(&source);
+                        task = Task::perform(
+                            async move {
+                                (
+                                    rfd::FileDialog::new()
+                                        .add_filter("Listing", &["lst"])
+                                        .add_filter("All files", &["*"])
+                                        .save_file(),
+                                    listing,
+                                )
+                            },
+                            |(path, listing)| Message::CompileToListingPicked(path, listing),
+                        );
+                    }
+                    Err(err) => {
+                        self.diagnostics = assembler.diagnostics().to_vec();
+                        self.error_message = Some(err.to_string());
+                    }
+                }
+            }
+            Message::CompileToListingPicked(path, listing) => {
+                if let Some(path) = path {
+                    task = Task::perform(
+                        async move {
+                            std::fs::write(&path, listing)
+                                .map_err(|e| format!("Nie mozna zapisac pliku: {e}"))
+                        },
+                        Message::CompileToListingSaved,
+                    );
+                }
+            }
+            Message::CompileToBinSaved(result) | Message::CompileToListingSaved(result) => match result {
                 Ok(()) => {
                     self.error_message = None;
-                    self.error_line = None;
                 }
                 Err(err) => {
                     self.error_message = Some(err);
-                    self.error_line = None;
                 }
             },
             Message::SimulationEvent(id, event) => {
+                let mut pc_line = None;
                 if let Some(state) = self.simulation_windows.get_mut(&id) {
                     match event {
                         SimulationEvent::Output(OutputEvent::Append(text)) => {
@@ -410,6 +619,15 @@ impl CodeEditorApp {
                             }
                         }
                         SimulationEvent::CpuState(snapshot) => {
+                            if state.debug_mode && !state.is_running {
+                                // code from an INCLUDEd file shows on its INCLUDE line
+                                let map = state.assembler.source_map();
+                                pc_line = Some(
+                                    map.line_for_address(snapshot.program_counter)
+                                        .and_then(|(file, line)| map.enclosing_line(file, line, 0))
+                                        .map(|line| line - 1),
+                                );
+                            }
                             state.register_state = snapshot;
                         }
                         SimulationEvent::Trace(trace) => {
@@ -437,13 +655,48 @@ impl CodeEditorApp {
                                 );
                             }
                         }
+                        SimulationEvent::PpiState(ppi_state) => {
+                            state.ppi_state = ppi_state;
+                        }
+                        SimulationEvent::BreakpointHit(address) => {
+                            state.is_running = false;
+                            state.stop_reason = Some(format!("BREAK @ {:04X}h", address));
+                        }
+                        SimulationEvent::TargetReached(address) => {
+                            state.is_running = false;
+                            state.stop_reason = Some(format!("STOP @ {:04X}h", address));
+                        }
+                        SimulationEvent::PortBreakpointHit(access) => {
+                            state.is_running = false;
+                            let direction = if access.kind == WatchKind::Read { "IN" } else { "OUT" };
+                            state.stop_reason = Some(format!(
+                                "PORT {} {:02X}h={:02X} @ {:04X}h",
+                                direction, access.port, access.value, access.pc
+                            ));
+                        }
+                        SimulationEvent::IoLog(accesses) => {
+                            io_log::push_entries(&mut state.io_log, accesses);
+                        }
+                        SimulationEvent::WatchpointHit(hit) => {
+                            state.is_running = false;
+                            let access = if hit.kind == WatchKind::Read { "R" } else { "W" };
+                            state.stop_reason = Some(format!(
+                                "WATCH {} {:04X}h {:02X}->{:02X} @ {:04X}h",
+                                access, hit.address, hit.old_value, hit.new_value, hit.pc
+                            ));
+                        }
                     }
                 }
+                if let Some(line) = pc_line {
+                    task = self.show_pc_line(line);
+                }
             }
             Message::SimStart(id) => {
+                self.pc_line = None;
                 if let Some(state) = self.simulation_windows.get_mut(&id) {
                     state.controller.run();
                     state.is_running = true;
+                    state.stop_reason = None;
                 }
             }
             Message::SimStop(id) => {
@@ -462,11 +715,138 @@ impl CodeEditorApp {
                     state.waiting_for_input = false;
                     state.input_pending = false;
                     state.deassembly_entries.clear();
+                    state.stop_reason = None;
                 }
             }
             Message::SimStep(id) => {
                 if let Some(state) = self.simulation_windows.get_mut(&id) {
                     state.controller.step();
+                    state.stop_reason = None;
+                }
+            }
+            Message::SimStepOver(id) => {
+                self.pc_line = None;
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.controller.step_over();
+                    state.is_running = true;
+                    state.stop_reason = None;
+                }
+            }
+            Message::SimStepOut(id) => {
+                self.pc_line = None;
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.controller.step_out();
+                    state.is_running = true;
+                    state.stop_reason = None;
+                }
+            }
+            Message::SimRunToInputChanged(id, value) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.run_to_input = value;
+                }
+            }
+            Message::SimRunTo(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    match resolve_address(&state.assembler, &state.run_to_input) {
+                        Ok(address) => {
+                            self.pc_line = None;
+                            state.controller.run_to(address);
+                            state.is_running = true;
+                            state.stop_reason = None;
+                        }
+                        Err(err) => state.stop_reason = Some(err),
+                    }
+                }
+            }
+            Message::SimBreakpointInputChanged(id, value) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.breakpoint_input = value;
+                }
+            }
+            Message::SimWatchInputChanged(id, value) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.watch_input = value;
+                }
+            }
+            Message::SimWatchSubmitted(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id)
+                    && let Some(watchpoint) = parse_watchpoint(&state.watch_input)
+                {
+                    if let Some(index) = state.watchpoints.iter().position(|watch| *watch == watchpoint) {
+                        state.watchpoints.remove(index);
+                    } else {
+                        state.watchpoints.push(watchpoint);
+                    }
+                    state.controller.set_watchpoints(state.watchpoints.clone());
+                    state.watch_input.clear();
+                }
+            }
+            Message::SimPortBreakpointInputChanged(id, value) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    state.port_input = value;
+                }
+            }
+            Message::SimPortBreakpointSubmitted(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id)
+                    && let Some(breakpoint) = parse_port_breakpoint(&state.port_input)
+                {
+                    if let Some(index) = state.port_breakpoints.iter().position(|bp| *bp == breakpoint) {
+                        state.port_breakpoints.remove(index);
+                    } else {
+                        state.port_breakpoints.push(breakpoint);
+                    }
+                    state.controller.set_port_breakpoints(state.port_breakpoints.clone());
+                    state.port_input.clear();
+                }
+            }
+            Message::SimToggleIoLog(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    if !state.debug_mode {
+                        return Task::none();
+                    }
+                    if let Some(log_id) = state.io_log_window_id.take() {
+                        self.preferences.show_io_log = false;
+                        self.window_kinds.remove(&log_id);
+                        state.controller.set_io_logging(false);
+                        task = window::close::<Message>(log_id);
+                    } else {
+                        let (log_id, open_task) =
+                            io_log::open_window_with_geometry(self.preferences.io_log_window);
+                        state.io_log_window_id = Some(log_id);
+                        self.window_kinds.insert(log_id, WindowKind::IoLog);
+                        self.preferences.show_io_log = true;
+                        state.io_log.clear();
+                        state.controller.set_io_logging(true);
+                        task = open_task.map(Message::WindowOpened);
+                    }
+                }
+            }
+            Message::SimBreakpointSubmitted(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    let (address_text, condition_text) = split_breakpoint_condition(&state.breakpoint_input);
+                    let address = resolve_address(&state.assembler, address_text);
+                    let result = address.and_then(|address| match condition_text {
+                        Some(text) => {
+                            let condition = state.assembler.parse_condition(text).map_err(|err| err.to_string())?;
+                            state.breakpoints.insert(address, Some(condition.source().to_string()));
+                            state.controller.set_breakpoint_condition(address, Some(condition));
+                            Ok(())
+                        }
+                        None => {
+                            if state.breakpoints.remove(&address).is_none() {
+                                state.breakpoints.insert(address, None);
+                            }
+                            state.controller.toggle_breakpoint(address);
+                            Ok(())
+                        }
+                    });
+                    match result {
+                        Ok(()) => {
+                            state.breakpoint_error = None;
+                            state.breakpoint_input.clear();
+                        }
+                        Err(err) => state.breakpoint_error = Some(err),
+                    }
                 }
             }
             Message::SimCyclesLimitInputChanged(id, value) => {
@@ -559,6 +939,41 @@ impl CodeEditorApp {
                     }
                 }
             }
+            Message::SimTogglePpi(id) => {
+                if let Some(state) = self.simulation_windows.get_mut(&id) {
+                    if let Some(ppi_id) = state.ppi_window_id.take() {
+                        self.preferences.show_ppi = false;
+                        self.window_kinds.remove(&ppi_id);
+                        task = window::close::<Message>(ppi_id);
+                    } else {
+                        let (ppi_id, open_task) =
+                            ppi::open_window_with_geometry(self.preferences.ppi_window);
+                        state.ppi_window_id = Some(ppi_id);
+                        self.window_kinds.insert(ppi_id, WindowKind::Ppi);
+                        self.preferences.show_ppi = true;
+                        task = open_task.map(Message::WindowOpened);
+                    }
+                }
+            }
+            Message::SimPpiSwitch(id, port, bit) => {
+                if let Some(state) = self
+                    .simulation_windows
+                    .values()
+                    .find(|state| state.ppi_window_id == Some(id))
+                {
+                    let value = state.ppi_state.inputs[port] ^ (1 << bit);
+                    state.controller.ppi_input(PpiInput::Pins(port, value));
+                }
+            }
+            Message::SimPpiInput(id, input) => {
+                if let Some(state) = self
+                    .simulation_windows
+                    .values()
+                    .find(|state| state.ppi_window_id == Some(id))
+                {
+                    state.controller.ppi_input(input);
+                }
+            }
             Message::SimMemoryScrolled(id, y) => {
                 if let Some(state) = self
                     .simulation_windows
@@ -600,6 +1015,9 @@ impl CodeEditorApp {
                         if let Some(state) = self.simulation_windows.get_mut(&id) {
                             state.is_focused = true;
                         }
+                        if let Some(sim_window) = self.debug_simulation_of(id) {
+                            self.debug_windows.touch(sim_window);
+                        }
                     }
                     window::Event::Unfocused => {
                         if let Some(state) = self.simulation_windows.get_mut(&id) {
@@ -642,7 +1060,7 @@ impl CodeEditorApp {
             }
             Message::CloseRequested(id) => {
                 if id == self.main_window {
-                    return self.close_all_and_exit();
+                    return self.guard_unsaved_changes(UnsavedAction::Exit);
                 }
                 task = self.handle_close_requested(id);
             }
@@ -657,41 +1075,73 @@ impl CodeEditorApp {
     }
 
     fn start_simulation(&mut self, debug_mode: bool) -> Task<Message> {
-        let mut assembler = Assembler::new();
-        match assembler.assemble(&self.code.text()) {
-            Ok(assembled) => {
+        let mut assembler = self.source_assembler();
+        // only the bytes the program emitted, so zeros it defines are laid down too
+        let program = if self.assemble_source {
+            assembler.assemble(&self.code.text()).map(|_| assembler.segments())
+        } else {
+            Ok(Vec::new())
+        };
+        match program {
+            Ok(program) => {
                 let mut memory = [0u8; MEMORY_SIZE];
 
+                let mut bios = Vec::new();
                 if self.load_bios {
                     let bios_path = std::env::current_exe()
                         .ok()
                         .and_then(|path| path.parent().map(|dir| dir.join("bios.bin")))
                         .unwrap_or_else(|| "bios.bin".into());
-                    let bios = match std::fs::read(&bios_path) {
-                        Ok(bios) => bios,
+                    let data = match std::fs::read(&bios_path) {
+                        Ok(data) => data,
                         Err(err) => {
                             self.error_message =
                                 Some(format!("Can't read BIOS file ({:?}): {err}", bios_path));
-                            self.error_line = None;
+                            self.diagnostics.clear();
                             return Task::none();
                         }
                     };
 
-                    if let Err(err) = copy_trimmed_nonzero_slice(&bios, &mut memory) {
-                        self.error_message = Some(err);
-                        self.error_line = None;
-                        return Task::none();
+                    match hex::binary_segment(data, 0) {
+                        Ok(segment) => bios.push(segment),
+                        Err(err) => {
+                            self.error_message = Some(format!("BIOS: {err}"));
+                            self.diagnostics.clear();
+                            return Task::none();
+                        }
                     }
                 }
 
-                if let Err(err) = copy_trimmed_nonzero_slice(&assembled, &mut memory) {
-                    self.error_message = Some(err);
-                    self.error_line = None;
-                    return Task::none();
+                let mut images = Vec::new();
+                for image in &self.images {
+                    match read_image(image) {
+                        Ok(segments) => images.extend(segments),
+                        Err(err) => {
+                            self.error_message = Some(err);
+                            self.diagnostics.clear();
+                            return Task::none();
+                        }
+                    }
                 }
 
-                self.error_message = None;
-                self.error_line = None;
+                hex::copy_segments(&bios, &mut memory);
+                hex::copy_segments(&images, &mut memory);
+                hex::copy_segments(&program, &mut memory);
+                let bios_warning = bios_overlap_warning(&bios, &images, &program);
+
+                let usart1_link = match open_usart1_link(&self.preferences.usart1) {
+                    Ok(link) => link,
+                    Err(err) => {
+                        self.error_message = Some(format!("Can't open USART1 link: {err}"));
+                        self.diagnostics.clear();
+                        return Task::none();
+                    }
+                };
+                let usart1_description = usart1_link.as_ref().map(|link| link.description());
+
+                // overwriting the BIOS is allowed, it only gets pointed out
+                self.error_message = bios_warning;
+                self.diagnostics = assembler.diagnostics().to_vec();
                 let sim_geometry = if debug_mode {
                     self.preferences.sim_debug_window
                 } else {
@@ -735,14 +1185,46 @@ impl CodeEditorApp {
                 } else {
                     (None, None)
                 };
+                let (io_log_window, io_log_task) = if debug_mode && self.preferences.show_io_log {
+                    let (log_id, task) =
+                        io_log::open_window_with_geometry(self.preferences.io_log_window);
+                    (Some(log_id), Some(task))
+                } else {
+                    (None, None)
+                };
+                let (ppi_window, ppi_task) = if self.preferences.show_ppi {
+                    let (ppi_id, task) = ppi::open_window_with_geometry(self.preferences.ppi_window);
+                    (Some(ppi_id), Some(task))
+                } else {
+                    (None, None)
+                };
+                let mut bus = Mcs8Bus::new(memory);
+                bus.serial_mut().set_link(usart1_link);
                 let controller = SimulationController::new(
-                    Cpu::with_memory(memory),
+                    Cpu::with_bus(bus),
                     Some(input_rx),
                     event_tx,
                     debug_mode,
                     debug_mode.then_some(1000),
                 );
-                if !debug_mode {
+                if io_log_window.is_some() {
+                    controller.set_io_logging(true);
+                }
+                let breakpoints: BTreeMap<u16, Option<String>> = if debug_mode {
+                    self.breakpoint_lines
+                        .iter()
+                        .filter_map(|line| assembler.source_map().address_for_line(0, line + 1))
+                        .map(|address| (address, None))
+                        .collect()
+                } else {
+                    BTreeMap::new()
+                };
+                if !breakpoints.is_empty() {
+                    controller.set_breakpoints(breakpoints.keys().copied().collect());
+                }
+                if debug_mode {
+                    self.debug_windows.touch(sim_window);
+                } else {
                     controller.run();
                 }
                 self.simulation_windows.insert(
@@ -766,10 +1248,25 @@ impl CodeEditorApp {
                         memory_snapshot: Vec::new(),
                         memory_text: String::new(),
                         memory_start_row: 0,
+                        ppi_window_id: ppi_window,
+                        ppi_state: PpiState::default(),
+                        usart1_link: usart1_description,
                         cycles_limit_input: debug_mode
                             .then_some("1000".to_string())
                             .unwrap_or_default(),
                         cycles_limit: debug_mode.then_some(1000),
+                        breakpoints,
+                        breakpoint_input: String::new(),
+                        breakpoint_error: None,
+                        run_to_input: String::new(),
+                        assembler,
+                        watchpoints: Vec::new(),
+                        watch_input: String::new(),
+                        port_breakpoints: Vec::new(),
+                        port_input: String::new(),
+                        io_log_window_id: io_log_window,
+                        io_log: VecDeque::new(),
+                        stop_reason: None,
                     },
                 );
                 self.window_kinds.insert(
@@ -789,6 +1286,12 @@ impl CodeEditorApp {
                 if let Some(memory_id) = memory_window {
                     self.window_kinds.insert(memory_id, WindowKind::Memory);
                 }
+                if let Some(ppi_id) = ppi_window {
+                    self.window_kinds.insert(ppi_id, WindowKind::Ppi);
+                }
+                if let Some(log_id) = io_log_window {
+                    self.window_kinds.insert(log_id, WindowKind::IoLog);
+                }
                 let mut tasks = Vec::new();
                 tasks.push(open_task.map(Message::WindowOpened));
                 if let Some(reg_task) = reg_task {
@@ -800,10 +1303,16 @@ impl CodeEditorApp {
                 if let Some(memory_task) = memory_task {
                     tasks.push(memory_task.map(Message::WindowOpened));
                 }
+                if let Some(ppi_task) = ppi_task {
+                    tasks.push(ppi_task.map(Message::WindowOpened));
+                }
+                if let Some(io_log_task) = io_log_task {
+                    tasks.push(io_log_task.map(Message::WindowOpened));
+                }
                 Task::batch(tasks)
             }
             Err(err) => {
-                self.error_line = err.line_number.checked_sub(1);
+                self.diagnostics = assembler.diagnostics().to_vec();
                 self.error_message = Some(err.to_string());
                 Task::none()
             }
@@ -853,6 +1362,57 @@ impl CodeEditorApp {
         ])
     }
 
+    pub fn title(&self, window: window::Id) -> String {
+        if window != self.main_window {
+            return "MCS8Sim".into();
+        }
+        let name = self
+            .current_file
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".into());
+        format!("MCS8Sim - {}{}", name, if self.dirty { "*" } else { "" })
+    }
+
+    // INCLUDE paths resolve next to the loaded file
+    fn source_assembler(&self) -> Assembler {
+        let mut assembler = Assembler::new();
+        if let Some(path) = &self.current_file {
+            assembler.set_source_path(path);
+        }
+        assembler
+    }
+
+    fn save_source_file(&mut self, path: PathBuf) -> Task<Message> {
+        let text = self.code.text();
+        Task::perform(
+            async move {
+                std::fs::write(&path, text)
+                    .map(|_| path)
+                    .map_err(|e| format!("Nie mozna zapisac pliku: {e}"))
+            },
+            Message::FileSaved,
+        )
+    }
+
+    // closing or replacing the program asks first while it has unsaved changes
+    fn guard_unsaved_changes(&mut self, action: UnsavedAction) -> Task<Message> {
+        if !self.dirty {
+            return self.run_unsaved_action(action);
+        }
+        let task = ask_about_unsaved_changes(&action);
+        self.unsaved_action = Some(action);
+        task
+    }
+
+    fn run_unsaved_action(&mut self, action: UnsavedAction) -> Task<Message> {
+        match action {
+            UnsavedAction::Exit => self.close_all_and_exit(),
+            UnsavedAction::Open(path) => load_source_file(path),
+        }
+    }
+
     fn close_all_and_exit(&mut self) -> Task<Message> {
         let mut tasks: Vec<Task<Message>> = self
             .simulation_windows
@@ -870,6 +1430,12 @@ impl CodeEditorApp {
             if let Some(mem_id) = state.memory_window_id {
                 tasks.push(window::close::<Message>(mem_id));
             }
+            if let Some(ppi_id) = state.ppi_window_id {
+                tasks.push(window::close::<Message>(ppi_id));
+            }
+            if let Some(log_id) = state.io_log_window_id {
+                tasks.push(window::close::<Message>(log_id));
+            }
         }
         self.preferences.save();
         tasks.push(iced::exit());
@@ -895,6 +1461,12 @@ impl CodeEditorApp {
             if let Some(mem_id) = state.memory_window_id {
                 tasks.push(window::close::<Message>(mem_id));
             }
+            if let Some(ppi_id) = state.ppi_window_id {
+                tasks.push(window::close::<Message>(ppi_id));
+            }
+            if let Some(log_id) = state.io_log_window_id {
+                tasks.push(window::close::<Message>(log_id));
+            }
         }
 
         Task::batch(tasks)
@@ -908,11 +1480,34 @@ impl CodeEditorApp {
         window::close::<Message>(id)
     }
 
+    // the debug simulation a simulation, register, memory or other tool window belongs to
+    fn debug_simulation_of(&self, id: window::Id) -> Option<window::Id> {
+        self.simulation_windows
+            .iter()
+            .filter(|(_, state)| state.debug_mode)
+            .find(|(sim_window, state)| {
+                **sim_window == id
+                    || [
+                        state.register_window_id,
+                        state.deassembly_window_id,
+                        state.memory_window_id,
+                        state.ppi_window_id,
+                        state.io_log_window_id,
+                    ]
+                    .contains(&Some(id))
+            })
+            .map(|(sim_window, _)| *sim_window)
+    }
+
     fn handle_window_closed(&mut self, id: window::Id) -> Task<Message> {
         let mut tasks: Vec<Task<Message>> = Vec::new();
 
         if let Some(state) = self.simulation_windows.remove(&id) {
+            self.debug_windows.remove(id);
             state.controller.stop();
+            if state.debug_mode {
+                self.pc_line = None;
+            }
             if let Some(reg_id) = state.register_window_id {
                 tasks.push(window::close::<Message>(reg_id));
             }
@@ -922,6 +1517,12 @@ impl CodeEditorApp {
             if let Some(mem_id) = state.memory_window_id {
                 tasks.push(window::close::<Message>(mem_id));
             }
+            if let Some(ppi_id) = state.ppi_window_id {
+                tasks.push(window::close::<Message>(ppi_id));
+            }
+            if let Some(log_id) = state.io_log_window_id {
+                tasks.push(window::close::<Message>(log_id));
+            }
             self.window_kinds.remove(&id);
         } else {
             for state in self.simulation_windows.values_mut() {
@@ -937,6 +1538,15 @@ impl CodeEditorApp {
                     state.memory_window_id = None;
                     self.preferences.show_memory = false;
                 }
+                if state.ppi_window_id == Some(id) {
+                    state.ppi_window_id = None;
+                    self.preferences.show_ppi = false;
+                }
+                if state.io_log_window_id == Some(id) {
+                    state.io_log_window_id = None;
+                    self.preferences.show_io_log = false;
+                    state.controller.set_io_logging(false);
+                }
             }
             self.window_kinds.remove(&id);
         }
@@ -968,6 +1578,24 @@ impl CodeEditorApp {
             .any(|kind| *kind != WindowKind::Main)
     }
 
+    // highlights the paused program counter's line and scrolls a few lines above it
+    fn show_pc_line(&mut self, line: Option<usize>) -> Task<Message> {
+        if line == self.pc_line {
+            return Task::none();
+        }
+        self.pc_line = line;
+        let Some(line) = line else {
+            return Task::none();
+        };
+        let line_height = LineHeight::Relative(EDITOR_LINE_HEIGHT);
+        let line_height_px = line_height.to_absolute(iced::Pixels(self.font_size)).0;
+        let offset = scroll_op::AbsoluteOffset {
+            x: None,
+            y: Some(line.saturating_sub(5) as f32 * line_height_px),
+        };
+        iced::advanced::widget::operate(scroll_op::scroll_to(Id::new(EDITOR_SCROLL_ID), offset))
+    }
+
     fn rebuild_line_cache(&mut self) {
         self.line_lengths = self
             .code
@@ -995,6 +1623,8 @@ impl CodeEditorApp {
             WindowKind::Registers => &mut self.preferences.registers_window,
             WindowKind::Deassembly => &mut self.preferences.deassembly_window,
             WindowKind::Memory => &mut self.preferences.memory_window,
+            WindowKind::Ppi => &mut self.preferences.ppi_window,
+            WindowKind::IoLog => &mut self.preferences.io_log_window,
         };
 
         let mut geom = target.unwrap_or_default();
@@ -1018,7 +1648,109 @@ impl CodeEditorApp {
     }
 }
 
-fn trim_trailing_zeros(data: &[u8]) -> &[u8] {
-    let end = data.iter().rposition(|&byte| byte != 0).map_or(0, |pos| pos + 1);
-    &data[..end]
+fn open_usart1_link(
+    preferences: &SerialPreferences,
+) -> std::io::Result<Option<Box<dyn SerialLink>>> {
+    match preferences.backend {
+        SerialBackend::Disconnected => Ok(None),
+        SerialBackend::Pty => serial::open_pty().map(Some),
+        SerialBackend::Files => serial::open_files(
+            Path::new(&preferences.input_file),
+            Path::new(&preferences.output_file),
+        )
+        .map(Some),
+        SerialBackend::Tcp => serial::open_tcp(preferences.tcp_port).map(Some),
+    }
+}
+
+fn load_source_file(path: PathBuf) -> Task<Message> {
+    Task::perform(
+        async move {
+            std::fs::read_to_string(&path)
+                .map(|text| (path, text))
+                .map_err(|e| format!("Nie można odczytać pliku: {e}"))
+        },
+        Message::FileLoaded,
+    )
+}
+
+fn save_source_dialog() -> Task<Message> {
+    Task::perform(
+        async {
+            rfd::FileDialog::new()
+                .add_filter("Assembly", &["asm"])
+                .add_filter("Text", &["txt"])
+                .save_file()
+        },
+        Message::SavePicked,
+    )
+}
+
+fn ask_about_unsaved_changes(action: &UnsavedAction) -> Task<Message> {
+    let description = match action {
+        UnsavedAction::Exit => "The program has unsaved changes. Save them before closing?",
+        UnsavedAction::Open(_) => "The program has unsaved changes. Save them before opening another file?",
+    };
+    Task::perform(
+        async move {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Warning)
+                .set_title("Unsaved changes")
+                .set_description(description)
+                .set_buttons(rfd::MessageButtons::YesNoCancel)
+                .show()
+        },
+        |result| {
+            Message::UnsavedChangesAnswered(match result {
+                rfd::MessageDialogResult::Yes => UnsavedChoice::Save,
+                rfd::MessageDialogResult::No => UnsavedChoice::Discard,
+                _ => UnsavedChoice::Cancel,
+            })
+        },
+    )
 }
+
+fn bios_overlap_warning(bios: &[Segment], images: &[Segment], program: &[Segment]) -> Option<String> {
+    let describe = |what: &str, ranges: Vec<(u16, u16)>| {
+        (!ranges.is_empty()).then(|| {
+            let ranges = ranges
+                .iter()
+                .map(|(start, end)| {
+                    if start == end { format!("{start:04X}h") } else { format!("{start:04X}h-{end:04X}h") }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{what} overwrites BIOS at {ranges}")
+        })
+    };
+    let warnings: Vec<String> = [
+        describe("Memory image", hex::overlapping_ranges(bios, images)),
+        describe("Program", hex::overlapping_ranges(bios, program)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!warnings.is_empty()).then(|| format!("Warning: {}", warnings.join("; ")))
+}
+
+fn read_image(image: &MemoryImage) -> Result<Vec<Segment>, String> {
+    let name = image.path.display();
+    if is_hex_path(&image.path) {
+        let text = std::fs::read_to_string(&image.path)
+            .map_err(|err| format!("Can't read image {name}: {err}"))?;
+        hex::read_intel_hex(&text).map_err(|err| format!("{name}: {err}"))
+    } else {
+        let load_address = if image.load_address_input.trim().is_empty() {
+            0
+        } else {
+            parse_address(&image.load_address_input)
+                .ok_or_else(|| format!("{name}: invalid load address {}", image.load_address_input))?
+        };
+        let data = std::fs::read(&image.path)
+            .map_err(|err| format!("Can't read image {name}: {err}"))?;
+        hex::binary_segment(data, load_address)
+            .map(|segment| vec![segment])
+            .map_err(|err| format!("{name}: {err}"))
+    }
+}
+
@@ -1,17 +1,22 @@
+#[cfg(test)]
+mod code_editor_tests;
 mod controller;
 mod syntax;
 mod utils;
 mod view;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex, mpsc};
 
 use iced::widget::text_editor;
 use iced::window;
 
-use crate::cpu::{CpuState, InstructionTrace, simulation_controller::{SimulationController, SimulationEvent}};
-use crate::gui::preferences::{AppTheme, Preferences};
+use crate::assembler::Assembler;
+use crate::assembler::errors::Diagnostic;
+use crate::cpu::{CpuState, InstructionTrace, ppi::{PpiInput, PpiState}, watch::{PortAccess, PortBreakpoint, Watchpoint}, simulation_controller::{SimulationController, SimulationEvent}};
+use crate::hex::{OutputFormat, Segment};
+use crate::gui::preferences::{AppTheme, Preferences, SerialBackend};
 
 const MIN_FONT_SIZE: f32 = 8.0;
 const MAX_FONT_SIZE: f32 = 64.0;
@@ -40,8 +45,32 @@ struct SimulationState {
     memory_snapshot: Vec<u8>,
     memory_text: String,
     memory_start_row: usize,
+    ppi_window_id: Option<window::Id>,
+    ppi_state: PpiState,
+    usart1_link: Option<String>,
     cycles_limit_input: String,
     cycles_limit: Option<u64>,
+    // address -> condition text
+    breakpoints: BTreeMap<u16, Option<String>>,
+    breakpoint_input: String,
+    breakpoint_error: Option<String>,
+    run_to_input: String,
+    // kept to resolve labels in breakpoint addresses and conditions
+    assembler: Assembler,
+    watchpoints: Vec<Watchpoint>,
+    watch_input: String,
+    port_breakpoints: Vec<PortBreakpoint>,
+    port_input: String,
+    io_log_window_id: Option<window::Id>,
+    io_log: VecDeque<PortAccess>,
+    stop_reason: Option<String>,
+}
+
+// a .hex or raw binary file laid into memory when a simulation starts
+struct MemoryImage {
+    path: PathBuf,
+    // only used by raw binaries, HEX records carry their own addresses
+    load_address_input: String,
 }
 
 pub struct CodeEditorApp {
@@ -50,16 +79,32 @@ pub struct CodeEditorApp {
     font_size_input: String,
     last_line_count: usize,
     gutter_text: String,
+    // 0-based source lines, turned into addresses when a debug run starts
+    breakpoint_lines: BTreeSet<usize>,
+    gutter_hover_line: Option<usize>,
     max_line_len: usize,
     line_lengths: Vec<usize>,
     hscroll_x: f32,
     at_bottom: bool,
     error_message: Option<String>,
-    error_line: Option<usize>,
+    // errors and warnings of the last assembly, marked in the editor and listed under it
+    diagnostics: Vec<Diagnostic>,
+    // 0-based line of the paused debug simulation's program counter
+    pc_line: Option<usize>,
+    // file the editor text was loaded from or last saved to
+    current_file: Option<PathBuf>,
+    dirty: bool,
+    // what the unsaved changes prompt holds back, done once the program is saved or discarded
+    unsaved_action: Option<UnsavedAction>,
     load_bios: bool,
+    // loaded in order after the BIOS and before the assembled program
+    images: Vec<MemoryImage>,
+    assemble_source: bool,
     theme: AppTheme,
+    usart1_port_input: String,
     main_window: window::Id,
     simulation_windows: HashMap<window::Id, SimulationState>,
+    debug_windows: utils::DebugWindowOrder,
     async_message_sender: mpsc::Sender<AsyncMessage>,
     async_message_receiver: Arc<Mutex<mpsc::Receiver<AsyncMessage>>>,
     pending_simulation_launch: Option<bool>,
@@ -105,35 +150,96 @@ pub enum Message {
     FontSizeSubmitted,
     HorizontalScrollChanged(HScrollSource, f32),
     EditorScrolled(f32),
+    GutterHovered(f32),
+    GutterClicked,
+    RunToCursor,
     ToggleBios(bool),
+    ToggleAssembleSource(bool),
+    AddImage,
+    AddImagePicked(Option<PathBuf>),
+    RemoveImage(usize),
+    ImageLoadAddressChanged(usize, String),
     ThemeSelected(AppTheme),
+    Usart1BackendSelected(SerialBackend),
+    Usart1InputFileChanged(String),
+    Usart1OutputFileChanged(String),
+    Usart1PortInputChanged(String),
     LoadFile,
     LoadFilePicked(Option<PathBuf>),
-    FileLoaded(Result<String, String>),
+    FileLoaded(Result<(PathBuf, String), String>),
+    OpenRecent(RecentFile),
+    Save,
+    SaveAs,
+    SavePicked(Option<PathBuf>),
+    FileSaved(Result<PathBuf, String>),
+    UnsavedChangesAnswered(UnsavedChoice),
     CloseError,
     Run,
     RunDebug,
-    CompileToBin,
-    CompileToBinPicked(Option<PathBuf>, Vec<u8>),
+    CompileToBin(OutputFormat),
+    CompileToBinPicked(Option<PathBuf>, OutputFormat, Vec<Segment>, Option<u16>),
     CompileToBinSaved(Result<(), String>),
+    CompileToListing,
+    CompileToListingPicked(Option<PathBuf>, String),
+    CompileToListingSaved(Result<(), String>),
     SimulationEvent(window::Id, SimulationEvent),
     SimStart(window::Id),
     SimStop(window::Id),
     SimReset(window::Id),
     SimStep(window::Id),
+    SimStepOver(window::Id),
+    SimStepOut(window::Id),
+    SimRunToInputChanged(window::Id, String),
+    SimRunTo(window::Id),
     SimKeyInput(window::Id, u8),
     SimCyclesLimitInputChanged(window::Id, String),
     SimCyclesLimitSubmitted(window::Id),
+    SimBreakpointInputChanged(window::Id, String),
+    SimBreakpointSubmitted(window::Id),
+    SimWatchInputChanged(window::Id, String),
+    SimWatchSubmitted(window::Id),
+    SimPortBreakpointInputChanged(window::Id, String),
+    SimPortBreakpointSubmitted(window::Id),
+    SimToggleIoLog(window::Id),
     SimToggleRegisters(window::Id),
     SimToggleDeassembly(window::Id),
     SimToggleMemory(window::Id),
     SimMemoryScrolled(window::Id, f32),
+    SimTogglePpi(window::Id),
+    SimPpiSwitch(window::Id, usize, u8),
+    SimPpiInput(window::Id, PpiInput),
     WindowEvent(window::Id, window::Event),
     WindowOpened(window::Id),
     CloseRequested(window::Id),
     WindowClosed(window::Id),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsavedChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsavedAction {
+    Exit,
+    Open(PathBuf),
+}
+
+// entry of the recent files list, shown by file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentFile(pub PathBuf);
+
+impl std::fmt::Display for RecentFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.file_name() {
+            Some(name) => write!(f, "{}", name.to_string_lossy()),
+            None => write!(f, "{}", self.0.display()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AsyncMessage {
     SimulationEvent(window::Id, SimulationEvent),
@@ -152,4 +258,6 @@ enum WindowKind {
     Registers,
     Deassembly,
     Memory,
+    Ppi,
+    IoLog,
 }
@@ -1,27 +1,150 @@
-pub(super) fn build_gutter_text(line_count: usize) -> String {
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use iced::window;
+
+use crate::assembler::Assembler;
+use crate::cpu::watch::{PortBreakpoint, WatchKind, Watchpoint};
+use crate::hex::OutputFormat;
+
+// debug simulations by when they were last started or focused, the editor's debug commands go to the latest
+#[derive(Default)]
+pub(super) struct DebugWindowOrder(Vec<window::Id>);
+
+impl DebugWindowOrder {
+    pub(super) fn touch(&mut self, id: window::Id) {
+        self.0.retain(|window| *window != id);
+        self.0.push(id);
+    }
+
+    pub(super) fn remove(&mut self, id: window::Id) {
+        self.0.retain(|window| *window != id);
+    }
+
+    pub(super) fn latest(&self) -> Option<window::Id> {
+        self.0.last().copied()
+    }
+}
+
+// breakpoint lines are 0-based and get a marker in front of the number
+pub(super) fn build_gutter_text(line_count: usize, breakpoint_lines: &BTreeSet<usize>) -> String {
     (1..=line_count)
-        .map(|i| i.to_string())
+        .map(|i| {
+            if breakpoint_lines.contains(&(i - 1)) {
+                format!("\u{25CF}{}", i)
+            } else {
+                i.to_string()
+            }
+        })
         .collect::<Vec<_>>()
         .join("\n")
 }
 
-pub(super) fn copy_trimmed_nonzero_slice(src: &[u8], dest: &mut [u8]) -> Result<(), String> {
-    if src.len() > dest.len() {
-        return Err(format!(
-            "Źródło ({}) jest większe niż pamięć ({})",
-            src.len(),
-            dest.len()
-        ));
+// moves breakpoints below an edit that added or removed lines, ones in removed lines are dropped
+pub(super) fn shift_breakpoint_lines(lines: &BTreeSet<usize>, after: usize, delta: isize) -> BTreeSet<usize> {
+    lines
+        .iter()
+        .filter_map(|&line| {
+            if line <= after {
+                return Some(line);
+            }
+            let moved = line as isize + delta;
+            (moved > after as isize).then_some(moved as usize)
+        })
+        .collect()
+}
+
+pub(super) fn is_hex_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("hex") || extension.eq_ignore_ascii_case("ihx"))
+}
+
+// the format picked for Compile to bin decides the extension, "prog" is saved as "prog.hex"
+pub(super) fn with_output_extension(path: PathBuf, format: OutputFormat) -> PathBuf {
+    let has_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| format.extensions().iter().any(|known| extension.eq_ignore_ascii_case(known)));
+    if has_extension {
+        return path;
     }
+    let mut name = path.into_os_string();
+    name.push(".");
+    name.push(format.extensions()[0]);
+    name.into()
+}
+
+// accepts 0810, 0810H and 0x0810
+pub(super) fn parse_address(text: &str) -> Option<u16> {
+    let trimmed = text.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .or_else(|| trimmed.strip_suffix('h'))
+        .or_else(|| trimmed.strip_suffix('H'))
+        .unwrap_or(trimmed);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+// hex address first, then a label expression of the assembled program
+pub(super) fn resolve_address(assembler: &Assembler, text: &str) -> Result<u16, String> {
+    if let Some(address) = parse_address(text) {
+        return Ok(address);
+    }
+    let expression = assembler.parse_condition(text).map_err(|err| err.to_string())?;
+    expression
+        .constant_value()
+        .map(|value| value as u16)
+        .ok_or_else(|| format!("{} is not a constant address", text.trim()))
+}
+
+// "0100 if A = 0" splits into the address and the condition
+pub(super) fn split_breakpoint_condition(text: &str) -> (&str, Option<&str>) {
+    let upper = text.to_ascii_uppercase();
+    match upper.find(" IF ") {
+        Some(index) => (text[..index].trim(), Some(text[index + 4..].trim())),
+        None => (text.trim(), None),
+    }
+}
 
-    let start = src.iter().position(|&b| b != 0);
-    let end = src.iter().rposition(|&b| b != 0);
-    let (Some(start), Some(end)) = (start, end) else {
-        return Ok(());
+// "0800-0FFF w", "0810 r" or "0900" (read and write)
+pub(super) fn parse_watchpoint(text: &str) -> Option<Watchpoint> {
+    let mut parts = text.split_whitespace();
+    let range = parts.next()?;
+    let kind = match parts.next().map(|kind| kind.to_ascii_lowercase()).as_deref() {
+        None | Some("rw") => WatchKind::Access,
+        Some("r") => WatchKind::Read,
+        Some("w") => WatchKind::Write,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+        None => {
+            let address = parse_address(range)?;
+            (address, address)
+        }
     };
+    Some(Watchpoint::new(start, end, kind))
+}
 
-    dest[start..=end].copy_from_slice(&src[start..=end]);
-    Ok(())
+// "85", "85h in" or "0A5 out"
+pub(super) fn parse_port_breakpoint(text: &str) -> Option<PortBreakpoint> {
+    let mut parts = text.split_whitespace();
+    let port = parse_address(parts.next()?)?;
+    let kind = match parts.next().map(|kind| kind.to_ascii_lowercase()).as_deref() {
+        None => WatchKind::Access,
+        Some("in") => WatchKind::Read,
+        Some("out") => WatchKind::Write,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(PortBreakpoint { port: u8::try_from(port).ok()?, kind })
 }
 
 pub(super) fn normalize_output_chunk(chunk: &str) -> String {
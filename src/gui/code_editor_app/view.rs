@@ -1,15 +1,19 @@
 use iced::advanced::text::{Wrapping};
 use iced::widget::{
-    button, checkbox, column, container, pick_list, row, scrollable, text, text_editor, text_input,
+    button, checkbox, column, container, mouse_area, pick_list, row, scrollable, text, text_editor, text_input,
 };
 use iced::{alignment, border, window, Element, Length, Theme};
 
-use crate::gui::{deassembly, memory, registers, simulation};
-use crate::gui::preferences::AppTheme;
+use crate::gui::{deassembly, io_log, memory, ppi, registers, simulation};
+use crate::assembler::errors::Severity;
+use crate::cpu::ppi::PpiInput;
+use crate::hex::OutputFormat;
+use crate::gui::preferences::{AppTheme, SerialBackend};
 
 use super::syntax::{SyntaxHighlighter, TokenKind};
+use super::utils::is_hex_path;
 use super::{
-    CodeEditorApp, HScrollSource, Message, EDITOR_LINE_HEIGHT, EDITOR_PADDING, EDITOR_SCROLL_ID,
+    CodeEditorApp, HScrollSource, Message, RecentFile, EDITOR_LINE_HEIGHT, EDITOR_PADDING, EDITOR_SCROLL_ID,
     EXTERNAL_HSCROLL_ID,
 };
 
@@ -40,7 +44,60 @@ impl CodeEditorApp {
                 move |viewport| Message::SimMemoryScrolled(window, viewport.absolute_offset().y),
             );
         }
+        if let Some(state) = self
+            .simulation_windows
+            .values()
+            .find(|state| state.ppi_window_id == Some(window))
+        {
+            return ppi::view(
+                &state.ppi_state,
+                move |port, bit| Message::SimPpiSwitch(window, port, bit),
+                move |port| Message::SimPpiInput(window, PpiInput::Strobe(port)),
+                move |port| Message::SimPpiInput(window, PpiInput::Acknowledge(port)),
+            );
+        }
+        if let Some(state) = self
+            .simulation_windows
+            .values()
+            .find(|state| state.io_log_window_id == Some(window))
+        {
+            return io_log::view(&state.io_log);
+        }
         if let Some(state) = self.simulation_windows.get(&window) {
+            let mut breakpoints_panel = column![
+                simulation::breakpoint_field(
+                    "Breakpoint LOOP if A = 0",
+                    &state.breakpoint_input,
+                    move |value| Message::SimBreakpointInputChanged(window, value),
+                    Message::SimBreakpointSubmitted(window),
+                    state
+                        .breakpoints
+                        .iter()
+                        .map(|(address, condition)| match condition {
+                            Some(condition) => format!("{:04X}h if {}", address, condition),
+                            None => format!("{:04X}h", address),
+                        })
+                        .collect(),
+                ),
+                simulation::breakpoint_field(
+                    "Watch 0800-0FFF w",
+                    &state.watch_input,
+                    move |value| Message::SimWatchInputChanged(window, value),
+                    Message::SimWatchSubmitted(window),
+                    state.watchpoints.iter().map(simulation::format_watchpoint).collect(),
+                ),
+                simulation::breakpoint_field(
+                    "Port 85 in/out",
+                    &state.port_input,
+                    move |value| Message::SimPortBreakpointInputChanged(window, value),
+                    Message::SimPortBreakpointSubmitted(window),
+                    state.port_breakpoints.iter().map(simulation::format_port_breakpoint).collect(),
+                ),
+            ]
+            .spacing(4);
+            if let Some(error) = &state.breakpoint_error {
+                breakpoints_panel = breakpoints_panel.push(text(error.clone()).size(12));
+            }
             return simulation::view(
                 &state.output,
                 state.waiting_for_input,
@@ -48,16 +105,26 @@ impl CodeEditorApp {
                 state.cycles_per_second,
                 state.is_halted,
                 state.is_running,
+                state.usart1_link.as_deref(),
                 &state.cycles_limit_input,
                 move |value| Message::SimCyclesLimitInputChanged(window, value),
                 Message::SimCyclesLimitSubmitted(window),
+                breakpoints_panel.into(),
+                state.stop_reason.as_deref(),
                 Message::SimToggleRegisters(window),
                 Message::SimToggleDeassembly(window),
                 Message::SimToggleMemory(window),
+                Message::SimTogglePpi(window),
+                Message::SimToggleIoLog(window),
                 Message::SimStart(window),
                 Message::SimStop(window),
                 Message::SimReset(window),
                 Message::SimStep(window),
+                Message::SimStepOver(window),
+                Message::SimStepOut(window),
+                &state.run_to_input,
+                move |value| Message::SimRunToInputChanged(window, value),
+                Message::SimRunTo(window),
             );
         }
 
@@ -65,9 +132,11 @@ impl CodeEditorApp {
         let right_panel = self.right_panel();
         let bottom_bar = self.bottom_bar();
         let error_bar = self.error_bar();
+        let diagnostics = self.diagnostics_panel();
 
         column![
             row![editor, right_panel].height(Length::Fill),
+            diagnostics,
             error_bar,
             bottom_bar
         ]
@@ -77,10 +146,15 @@ impl CodeEditorApp {
     fn editor_view(&self) -> Element<'_, Message> {
         let line_count = self.last_line_count.max(1);
 
-        let gutter = text(&self.gutter_text)
-            .size(self.font_size)
-            .align_x(alignment::Horizontal::Right)
-            .width(Length::Fill);
+        let gutter = mouse_area(
+            text(&self.gutter_text)
+                .size(self.font_size)
+                .align_x(alignment::Horizontal::Right)
+                .width(Length::Fill),
+        )
+        .on_move(|point| Message::GutterHovered(point.y))
+        .on_press(Message::GutterClicked)
+        .interaction(iced::mouse::Interaction::Pointer);
 
         let line_height = iced::advanced::text::LineHeight::Relative(EDITOR_LINE_HEIGHT);
         let line_height_px = line_height.to_absolute(iced::Pixels(self.font_size)).0;
@@ -124,42 +198,35 @@ impl CodeEditorApp {
         let editor_width =
             (max_line_len * approx_char_width + self.font_size * 2.0).max(300.0);
 
-        let highlight_overlay = self
-            .error_line
-            .filter(|&line| line < line_count)
-            .map(|line| {
-                let offset = EDITOR_PADDING + (line as f32 * line_height_px);
-                let bar = container(
-                    iced::widget::Space::new().height(Length::Fixed(line_height_px)),
-                )
-                .width(Length::Fill)
-                .style(|theme: &Theme| {
-                    let palette = theme.extended_palette();
-                    let color = iced::Color {
-                        a: 0.35,
-                        ..palette.danger.weak.color
-                    };
-                    container::Style::default().background(color)
-                });
-                column![
-                    iced::widget::Space::new().height(Length::Fixed(offset)),
-                    bar,
-                    iced::widget::Space::new().height(Length::Fill),
-                ]
-                .width(Length::Fill)
-                .height(Length::Fill)
-            })
-            .unwrap_or_else(|| {
-                column![iced::widget::Space::new().height(Length::Fill)]
-                    .width(Length::Fill)
-                    .height(Length::Fill)
-            });
+        let lines_with = |severity: Severity| {
+            self.diagnostics
+                .iter()
+                .filter(move |d| d.severity == severity && d.file.is_none())
+                .filter_map(|d| d.line_number.checked_sub(1))
+                .filter(|&line| line < line_count)
+                .collect::<Vec<_>>()
+        };
+        let warning_overlay = line_highlight(
+            lines_with(Severity::Warning),
+            line_height_px,
+            |palette| palette.warning.weak.color,
+        );
+        let error_overlay = line_highlight(
+            lines_with(Severity::Error),
+            line_height_px,
+            |palette| palette.danger.weak.color,
+        );
+        let pc_overlay = line_highlight(
+            self.pc_line.filter(|&line| line < line_count).into_iter().collect(),
+            line_height_px,
+            |palette| palette.primary.weak.color,
+        );
 
-        let editor_stack = iced::widget::stack![highlight_overlay, editor]
+        let editor_stack = iced::widget::stack![warning_overlay, error_overlay, pc_overlay, editor]
             .width(Length::Fixed(editor_width))
             .height(Length::Fill);
 
-        let gutter_width = 38.0 * (self.font_size / 14.0);
+        let gutter_width = 48.0 * (self.font_size / 14.0);
         let editor_vscroll = scrollable(row![
             container(gutter)
                 .width(Length::Fixed(gutter_width))
@@ -211,6 +278,53 @@ impl CodeEditorApp {
     }
 
     fn right_panel(&self) -> Element<'_, Message> {
+        let usart1 = &self.preferences.usart1;
+        let usart1_settings: Element<'_, Message> = match usart1.backend {
+            SerialBackend::Files => column![
+                text_input("Input file", &usart1.input_file)
+                    .on_input(Message::Usart1InputFileChanged)
+                    .width(Length::Fixed(180.0)),
+                text_input("Output file", &usart1.output_file)
+                    .on_input(Message::Usart1OutputFileChanged)
+                    .width(Length::Fixed(180.0)),
+            ]
+            .spacing(8)
+            .into(),
+            SerialBackend::Tcp => text_input("Port", &self.usart1_port_input)
+                .on_input(Message::Usart1PortInputChanged)
+                .width(Length::Fixed(120.0))
+                .into(),
+            _ => iced::widget::Space::new().height(Length::Shrink).into(),
+        };
+
+        let images = column(self.images.iter().enumerate().map(|(index, image)| {
+            let name = image
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let address: Element<'_, Message> = if !is_hex_path(&image.path) {
+                text_input("Addr", &image.load_address_input)
+                    .on_input(move |value| Message::ImageLoadAddressChanged(index, value))
+                    .width(Length::Fixed(60.0))
+                    .into()
+            } else {
+                iced::widget::Space::new().width(Length::Fixed(60.0)).into()
+            };
+            row![
+                text(name).size(14).width(Length::Fill),
+                address,
+                button(text("x").size(14))
+                    .padding([0, 6])
+                    .on_press(Message::RemoveImage(index)),
+            ]
+            .spacing(4)
+            .align_y(alignment::Vertical::Center)
+            .into()
+        }))
+        .spacing(4)
+        .width(Length::Fixed(180.0));
+
         container(
             column![
                 text("Theme").width(Length::Fixed(120.0)),
@@ -227,6 +341,18 @@ impl CodeEditorApp {
                 button("Font +")
                     .on_press(Message::FontInc)
                     .width(Length::Fixed(120.0)),
+                text("USART1 (A4h)").width(Length::Fixed(120.0)),
+                pick_list(SerialBackend::ALL, Some(usart1.backend), Message::Usart1BackendSelected)
+                    .width(Length::Fixed(180.0)),
+                usart1_settings,
+                text("Memory images").width(Length::Fixed(120.0)),
+                images,
+                button("Add image")
+                    .on_press(Message::AddImage)
+                    .width(Length::Fixed(120.0)),
+                checkbox(self.assemble_source)
+                    .label("Assemble editor code")
+                    .on_toggle(Message::ToggleAssembleSource),
             ]
             .spacing(8)
             .align_x(alignment::Horizontal::Center),
@@ -264,14 +390,60 @@ impl CodeEditorApp {
         }
     }
 
+    fn diagnostics_panel(&self) -> Element<'_, Message> {
+        if self.diagnostics.is_empty() {
+            return container(iced::widget::Space::new().height(Length::Fixed(0.0))).into();
+        }
+        let entries = column(self.diagnostics.iter().map(|diagnostic| {
+            let severity = diagnostic.severity;
+            text(diagnostic.to_string())
+                .size(13)
+                .font(iced::Font::MONOSPACE)
+                .style(move |theme: &Theme| {
+                    let palette = theme.extended_palette();
+                    text::Style {
+                        color: Some(match severity {
+                            Severity::Error => palette.danger.base.color,
+                            Severity::Warning => palette.warning.strong.color,
+                        }),
+                    }
+                })
+                .into()
+        }))
+        .spacing(2);
+        container(scrollable(entries).width(Length::Fill))
+            .padding(6)
+            .width(Length::Fill)
+            .max_height(120)
+            .into()
+    }
+
     fn bottom_bar(&self) -> Element<'_, Message> {
         container(
             row![
                 button("Load file").on_press(Message::LoadFile),
+                button("Save").on_press(Message::Save),
+                button("Save as").on_press(Message::SaveAs),
+                pick_list(
+                    self.preferences
+                        .recent_files
+                        .iter()
+                        .cloned()
+                        .map(RecentFile)
+                        .collect::<Vec<_>>(),
+                    None::<RecentFile>,
+                    Message::OpenRecent,
+                )
+                .placeholder("Recent files"),
                 button("Run simulation").on_press(Message::Run),
                 button("Run simulation with debug").on_press(Message::RunDebug),
+                button("Run to cursor").on_press_maybe(
+                    self.debug_windows.latest().map(|_| Message::RunToCursor),
+                ),
                 iced::widget::Space::new().width(Length::Fill),
-                button("Compile to bin").on_press(Message::CompileToBin),
+                pick_list(OutputFormat::ALL, None::<OutputFormat>, Message::CompileToBin)
+                    .placeholder("Compile to bin"),
+                button("Compile to listing").on_press(Message::CompileToListing),
                 checkbox(self.load_bios)
                     .label("Load BIOS")
                     .on_toggle(Message::ToggleBios),
@@ -283,3 +455,33 @@ impl CodeEditorApp {
         .into()
     }
 }
+
+fn line_highlight<'a>(
+    mut lines: Vec<usize>,
+    line_height_px: f32,
+    color: fn(&iced::theme::palette::Extended) -> iced::Color,
+) -> Element<'a, Message> {
+    lines.sort_unstable();
+    lines.dedup();
+    let mut bars = column![].width(Length::Fill).height(Length::Fill);
+    let mut top = EDITOR_PADDING;
+    let mut next_line = 0;
+    for line in lines {
+        let offset = top + ((line - next_line) as f32 * line_height_px);
+        let bar = container(iced::widget::Space::new().height(Length::Fixed(line_height_px)))
+            .width(Length::Fill)
+            .style(move |theme: &Theme| {
+                let color = iced::Color {
+                    a: 0.35,
+                    ..color(theme.extended_palette())
+                };
+                container::Style::default().background(color)
+            });
+        bars = bars
+            .push(iced::widget::Space::new().height(Length::Fixed(offset)))
+            .push(bar);
+        top = 0.0;
+        next_line = line + 1;
+    }
+    bars.push(iced::widget::Space::new().height(Length::Fill)).into()
+}
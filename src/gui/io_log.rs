@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use iced::{window, Element, Length, Task};
+use iced::widget::{column, container, scrollable, text};
+
+use crate::cpu::watch::{PortAccess, WatchKind};
+use crate::gui::preferences::WindowGeometry;
+
+const WINDOW_WIDTH: f32 = 460.0;
+const WINDOW_HEIGHT: f32 = 360.0;
+pub const MAX_ENTRIES: usize = 256;
+
+pub fn open_window() -> (window::Id, Task<window::Id>) {
+    open_window_with_geometry(None)
+}
+
+pub fn open_window_with_geometry(
+    geometry: Option<WindowGeometry>,
+) -> (window::Id, Task<window::Id>) {
+    let mut settings = window::Settings {
+        size: iced::Size::new(WINDOW_WIDTH, WINDOW_HEIGHT),
+        min_size: Some(iced::Size::new(WINDOW_WIDTH, 200.0)),
+        ..window::Settings::default()
+    };
+    if let Some(geometry) = geometry {
+        geometry.apply_to_settings(&mut settings);
+    }
+    window::open(settings)
+}
+
+pub fn push_entries(log: &mut VecDeque<PortAccess>, accesses: Vec<PortAccess>) {
+    log.extend(accesses);
+    if log.len() > MAX_ENTRIES {
+        let excess = log.len() - MAX_ENTRIES;
+        log.drain(0..excess);
+    }
+}
+
+fn format_entry(access: &PortAccess) -> String {
+    let direction = if access.kind == WatchKind::Read { "IN " } else { "OUT" };
+    format!(
+        "{:>12}  {:04X}h  {}  {:02X}h  {:02X}h",
+        access.cycle, access.pc, direction, access.port, access.value
+    )
+}
+
+pub fn view<'a, Message: 'a>(
+    entries: &VecDeque<PortAccess>,
+) -> Element<'a, Message> {
+    let header = text("       CYCLE  PC     DIR  PORT VALUE").font(iced::Font::MONOSPACE);
+    let body = if entries.is_empty() {
+        text("No port accesses yet.").font(iced::Font::MONOSPACE)
+    } else {
+        let lines = entries.iter().rev().map(format_entry).collect::<Vec<_>>().join("\n");
+        text(lines).font(iced::Font::MONOSPACE)
+    };
+
+    let content = column![
+        header,
+        scrollable(body).width(Length::Fill).height(Length::Fill),
+    ]
+    .spacing(6);
+
+    container(content)
+        .padding(10)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+}
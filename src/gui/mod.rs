@@ -3,4 +3,6 @@ pub mod simulation;
 pub mod registers;
 pub mod deassembly;
 pub mod memory;
+pub mod io_log;
+pub mod ppi;
 pub mod preferences;
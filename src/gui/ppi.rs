@@ -0,0 +1,153 @@
+use iced::border;
+use iced::widget::{button, column, container, row, text};
+use iced::{window, Element, Length, Task, Theme};
+use crate::cpu::ppi::{PpiState, PORT_A, PORT_B, PORT_C};
+use crate::gui::preferences::WindowGeometry;
+
+const WINDOW_WIDTH: f32 = 520.0;
+const WINDOW_HEIGHT: f32 = 300.0;
+const LABEL_W: f32 = 52.0;
+const BIT_W: f32 = 36.0;
+const LED_SIZE: f32 = 18.0;
+
+pub fn open_window() -> (window::Id, Task<window::Id>) {
+    open_window_with_geometry(None)
+}
+
+pub fn open_window_with_geometry(
+    geometry: Option<WindowGeometry>,
+) -> (window::Id, Task<window::Id>) {
+    let mut settings = window::Settings {
+        size: iced::Size::new(WINDOW_WIDTH, WINDOW_HEIGHT),
+        min_size: Some(iced::Size::new(WINDOW_WIDTH, WINDOW_HEIGHT)),
+        ..window::Settings::default()
+    };
+    if let Some(geometry) = geometry {
+        geometry.apply_to_settings(&mut settings);
+    }
+    window::open(settings)
+}
+
+fn describe_mode(control: u8) -> String {
+    let direction = |input: bool| if input { "in" } else { "out" };
+    let group_a_mode = match (control >> 5) & 0x03 {
+        0 => 0,
+        1 => 1,
+        _ => 2,
+    };
+    let group_b_mode = (control >> 2) & 0x01;
+    format!(
+        "A: mode {} {} | B: mode {} {} | C: upper {} lower {}",
+        group_a_mode,
+        if group_a_mode == 2 { "bidir" } else { direction(control & 0x10 != 0) },
+        group_b_mode,
+        direction(control & 0x02 != 0),
+        direction(control & 0x08 != 0),
+        direction(control & 0x01 != 0),
+    )
+}
+
+fn led<'a, Message: 'a>(lit: bool) -> Element<'a, Message> {
+    let dot = container(iced::widget::Space::new())
+        .width(Length::Fixed(LED_SIZE))
+        .height(Length::Fixed(LED_SIZE))
+        .style(move |theme: &Theme| {
+            let palette = theme.extended_palette();
+            let color = if lit {
+                palette.danger.strong.color
+            } else {
+                palette.background.strong.color
+            };
+            container::Style::default()
+                .background(color)
+                .border(border::rounded(LED_SIZE / 2.0))
+        });
+    container(dot)
+        .width(Length::Fixed(BIT_W))
+        .center_x(Length::Fixed(BIT_W))
+        .into()
+}
+
+fn port_row<'a, Message: 'a + Clone>(
+    label: &'a str,
+    port: usize,
+    state: &PpiState,
+    on_switch: &(impl Fn(usize, u8) -> Message + 'a),
+) -> Element<'a, Message> {
+    let mut cells = vec![
+        text(label)
+            .font(iced::Font::MONOSPACE)
+            .width(Length::Fixed(LABEL_W))
+            .into(),
+    ];
+    for bit in (0..8u8).rev() {
+        let mask = 1 << bit;
+        let cell: Element<'a, Message> = if state.input_mask[port] & mask != 0 {
+            let label = if state.inputs[port] & mask != 0 { "1" } else { "0" };
+            button(text(label).font(iced::Font::MONOSPACE))
+                .on_press(on_switch(port, bit))
+                .width(Length::Fixed(BIT_W))
+                .into()
+        } else {
+            led(state.pins[port] & mask != 0)
+        };
+        cells.push(cell);
+    }
+    cells.push(
+        text(format!("{:02X}h", state.pins[port]))
+            .font(iced::Font::MONOSPACE)
+            .into(),
+    );
+    row(cells)
+        .spacing(4)
+        .align_y(iced::alignment::Vertical::Center)
+        .into()
+}
+
+pub fn view<'a, Message: 'a + Clone>(
+    state: &PpiState,
+    on_switch: impl Fn(usize, u8) -> Message + 'a,
+    on_strobe: impl Fn(usize) -> Message + 'a,
+    on_acknowledge: impl Fn(usize) -> Message + 'a,
+) -> Element<'a, Message> {
+    let mut header = vec![
+        text("PORT")
+            .font(iced::Font::MONOSPACE)
+            .width(Length::Fixed(LABEL_W))
+            .into(),
+    ];
+    for bit in (0..8).rev() {
+        header.push(
+            text(format!("{}", bit))
+                .font(iced::Font::MONOSPACE)
+                .width(Length::Fixed(BIT_W))
+                .center()
+                .into(),
+        );
+    }
+
+    let handshake = row![
+        button("STB A").on_press(on_strobe(PORT_A)),
+        button("ACK A").on_press(on_acknowledge(PORT_A)),
+        button("STB B").on_press(on_strobe(PORT_B)),
+        button("ACK B").on_press(on_acknowledge(PORT_B)),
+    ]
+    .spacing(8);
+
+    let content = column![
+        text(format!("Control: {:02X}h", state.control)).font(iced::Font::MONOSPACE),
+        text(describe_mode(state.control)).font(iced::Font::MONOSPACE),
+        row(header).spacing(4),
+        port_row("A0h A", PORT_A, state, &on_switch),
+        port_row("A1h B", PORT_B, state, &on_switch),
+        port_row("A2h C", PORT_C, state, &on_switch),
+        handshake,
+    ]
+    .spacing(10);
+
+    container(content)
+        .padding(12)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+}
@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use iced::{window, Point, Size};
 
 const PREFERENCES_FILE: &str = "mcs8sim_prefs.toml";
+const MAX_RECENT_FILES: usize = 8;
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct WindowGeometry {
@@ -37,13 +38,20 @@ pub struct Preferences {
     pub show_registers: bool,
     pub show_deassembly: bool,
     pub show_memory: bool,
+    pub show_ppi: bool,
+    pub show_io_log: bool,
     pub theme: AppTheme,
+    // most recent first
+    pub recent_files: Vec<PathBuf>,
     pub main_window: Option<WindowGeometry>,
     pub sim_window: Option<WindowGeometry>,
     pub sim_debug_window: Option<WindowGeometry>,
     pub registers_window: Option<WindowGeometry>,
     pub deassembly_window: Option<WindowGeometry>,
     pub memory_window: Option<WindowGeometry>,
+    pub ppi_window: Option<WindowGeometry>,
+    pub io_log_window: Option<WindowGeometry>,
+    pub usart1: SerialPreferences,
 }
 
 impl Default for Preferences {
@@ -54,13 +62,69 @@ impl Default for Preferences {
             show_registers: true,
             show_deassembly: true,
             show_memory: true,
+            show_ppi: false,
+            show_io_log: false,
             theme: AppTheme::Dark,
+            recent_files: Vec::new(),
             main_window: None,
             sim_window: None,
             sim_debug_window: None,
             registers_window: None,
             deassembly_window: None,
             memory_window: None,
+            ppi_window: None,
+            io_log_window: None,
+            usart1: SerialPreferences::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerialBackend {
+    #[default]
+    Disconnected,
+    Pty,
+    Files,
+    Tcp,
+}
+
+impl SerialBackend {
+    pub const ALL: [SerialBackend; 4] = [
+        SerialBackend::Disconnected,
+        SerialBackend::Pty,
+        SerialBackend::Files,
+        SerialBackend::Tcp,
+    ];
+}
+
+impl std::fmt::Display for SerialBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            SerialBackend::Disconnected => "Not connected",
+            SerialBackend::Pty => "Pseudo-terminal",
+            SerialBackend::Files => "Files",
+            SerialBackend::Tcp => "TCP socket",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialPreferences {
+    pub backend: SerialBackend,
+    pub input_file: String,
+    pub output_file: String,
+    pub tcp_port: u16,
+}
+
+impl Default for SerialPreferences {
+    fn default() -> Self {
+        Self {
+            backend: SerialBackend::Disconnected,
+            input_file: String::new(),
+            output_file: String::new(),
+            tcp_port: 8251,
         }
     }
 }
@@ -164,6 +228,12 @@ impl Preferences {
             let _ = std::fs::write(path, text);
         }
     }
+
+    pub fn add_recent_file(&mut self, path: PathBuf) {
+        self.recent_files.retain(|recent| recent != &path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
 }
 
 fn prefs_path() -> PathBuf {
@@ -1,5 +1,7 @@
 use crate::gui::preferences::WindowGeometry;
 use iced::widget::{button, container, row, text, text_input};
+
+use crate::cpu::watch::{PortBreakpoint, WatchKind, Watchpoint};
 use iced::{window, Element, Length, Task};
 
 const CHAR_WIDTH_PX: f32 = 9.0;
@@ -40,6 +42,47 @@ pub fn open_window_with_geometry(
     window::open(settings)
 }
 
+// text input plus the list of entries it toggles, stacked in the debug panel
+pub fn breakpoint_field<'a, Message: 'a + Clone>(
+    placeholder: &'a str,
+    value: &'a str,
+    on_input: impl Fn(String) -> Message + 'a,
+    on_submit: Message,
+    entries: Vec<String>,
+) -> Element<'a, Message> {
+    iced::widget::column![
+        text_input(placeholder, value)
+            .on_input(on_input)
+            .on_submit(on_submit)
+            .width(Length::Fill),
+        text(entries.join("\n")).font(iced::Font::MONOSPACE).size(12),
+    ]
+    .spacing(4)
+    .into()
+}
+
+pub fn format_watchpoint(watch: &Watchpoint) -> String {
+    let kind = match watch.kind {
+        WatchKind::Read => "r",
+        WatchKind::Write => "w",
+        WatchKind::Access => "rw",
+    };
+    if watch.start == watch.end {
+        format!("{:04X}h {}", watch.start, kind)
+    } else {
+        format!("{:04X}-{:04X} {}", watch.start, watch.end, kind)
+    }
+}
+
+pub fn format_port_breakpoint(breakpoint: &PortBreakpoint) -> String {
+    let kind = match breakpoint.kind {
+        WatchKind::Read => "in",
+        WatchKind::Write => "out",
+        WatchKind::Access => "in/out",
+    };
+    format!("{:02X}h {}", breakpoint.port, kind)
+}
+
 pub fn view<'a, Message: 'a + Clone>(
     output: &'a str,
     waiting_for_input: bool,
@@ -47,16 +90,26 @@ pub fn view<'a, Message: 'a + Clone>(
     cycles_per_second: u64,
     is_halted: bool,
     is_running: bool,
+    usart1_link: Option<&'a str>,
     cycles_limit_input: &'a str,
     on_cycles_limit_input: impl Fn(String) -> Message + 'a,
     on_cycles_limit_submit: Message,
+    breakpoints_panel: Element<'a, Message>,
+    stop_reason: Option<&'a str>,
     toggle_registers: Message,
     toggle_deassembly: Message,
     toggle_memory: Message,
+    toggle_ppi: Message,
+    toggle_io_log: Message,
     start: Message,
     stop: Message,
     reset: Message,
     step: Message,
+    step_over: Message,
+    step_out: Message,
+    run_to_input: &'a str,
+    on_run_to_input: impl Fn(String) -> Message + 'a,
+    run_to: Message,
 ) -> Element<'a, Message> {
     let indicator = if waiting_for_input {
         text("Waiting for input...")
@@ -65,7 +118,21 @@ pub fn view<'a, Message: 'a + Clone>(
     };
 
     let step_button: Element<'a, Message> = if debug_mode {
-        button("Step").on_press(step).width(Length::Fill).into()
+        iced::widget::column![
+            button("Step").on_press(step).width(Length::Fill),
+            row![
+                button("Over").on_press(step_over).width(Length::Fill),
+                button("Out").on_press(step_out).width(Length::Fill),
+            ]
+            .spacing(4),
+            text_input("Run to address", run_to_input)
+                .on_input(on_run_to_input)
+                .on_submit(run_to.clone())
+                .width(Length::Fill),
+            button("Run to").on_press(run_to).width(Length::Fill),
+        ]
+        .spacing(8)
+        .into()
     } else {
         iced::widget::Space::new()
             .width(Length::Fill)
@@ -89,16 +156,21 @@ pub fn view<'a, Message: 'a + Clone>(
         .height(Length::Fill);
 
     let state_label = if is_halted {
-        "HALTED"
+        "HALTED".to_string()
     } else if is_running {
-        "RUNNING"
+        "RUNNING".to_string()
+    } else if let Some(reason) = stop_reason {
+        reason.to_string()
     } else {
-        "PAUSED"
+        "PAUSED".to_string()
     };
     let mut footer_text = format!("Cycles/sec: {} | State: {}", cycles_per_second, state_label);
     if is_halted {
         footer_text.push_str(" | CPU HALTED");
     }
+    if let Some(link) = usart1_link {
+        footer_text.push_str(&format!(" | USART1: {}", link));
+    }
     let limit_input: Element<'a, Message> = if debug_mode {
         text_input("Limit", cycles_limit_input)
             .on_input(on_cycles_limit_input)
@@ -126,6 +198,15 @@ pub fn view<'a, Message: 'a + Clone>(
     .padding(4);
 
     let right_panel = {
+        let breakpoints_panel: Element<'a, Message> = if debug_mode {
+            breakpoints_panel
+        } else {
+            iced::widget::Space::new()
+                .width(Length::Shrink)
+                .height(Length::Shrink)
+                .into()
+        };
+
         let registers_button: Element<'a, Message> = if debug_mode {
             button("Registers").on_press(toggle_registers).width(Length::Fill).into()
         } else {
@@ -153,16 +234,28 @@ pub fn view<'a, Message: 'a + Clone>(
                 .into()
         };
 
+        let io_log_button: Element<'a, Message> = if debug_mode {
+            button("I/O log").on_press(toggle_io_log).width(Length::Fill).into()
+        } else {
+            iced::widget::Space::new()
+                .width(Length::Shrink)
+                .height(Length::Shrink)
+                .into()
+        };
+
         container(
             iced::widget::column![
                 button("Start").on_press(start).width(Length::Fill),
                 button("Stop").on_press(stop).width(Length::Fill),
                 button("Reset").on_press(reset).width(Length::Fill),
                 step_button,
+                breakpoints_panel,
                 iced::widget::Space::new().height(Length::Fill),
                 registers_button,
                 deassembly_button,
                 memory_button,
+                io_log_button,
+                button("PPI").on_press(toggle_ppi).width(Length::Fill),
             ]
             .spacing(8),
         )
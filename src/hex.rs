@@ -0,0 +1,238 @@
+use std::fmt::Write;
+
+const BYTES_PER_RECORD: usize = 16;
+
+// a run of bytes laid down at consecutive addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+// file formats Compile to bin can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Binary,
+    IntelHex,
+    SRecord,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 3] = [OutputFormat::Binary, OutputFormat::IntelHex, OutputFormat::SRecord];
+
+    // the first one is appended to file names without any of them
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            OutputFormat::Binary => &["bin"],
+            OutputFormat::IntelHex => &["hex", "ihx"],
+            OutputFormat::SRecord => &["s19", "srec", "mot"],
+        }
+    }
+
+    // the S-record header carries the file name without its extension
+    pub fn write(self, segments: &[Segment], start: Option<u16>, name: &str) -> Vec<u8> {
+        match self {
+            OutputFormat::Binary => write_binary(segments),
+            OutputFormat::IntelHex => write_intel_hex(segments, start).into_bytes(),
+            OutputFormat::SRecord => write_srecord(segments, start, name).into_bytes(),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            OutputFormat::Binary => "Binary",
+            OutputFormat::IntelHex => "Intel HEX",
+            OutputFormat::SRecord => "Motorola S-record",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// segments split into records of at most 16 bytes
+fn records(segments: &[Segment]) -> impl Iterator<Item = (u16, &[u8])> {
+    segments.iter().flat_map(|segment| {
+        segment
+            .data
+            .chunks(BYTES_PER_RECORD)
+            .enumerate()
+            .map(move |(index, chunk)| (segment.start.wrapping_add((index * BYTES_PER_RECORD) as u16), chunk))
+    })
+}
+
+fn write_record(out: &mut String, prefix: &str, bytes: &[u8], checksum: u8) {
+    out.push_str(prefix);
+    for byte in bytes {
+        let _ = write!(out, "{:02X}", byte);
+    }
+    let _ = writeln!(out, "{:02X}", checksum);
+}
+
+fn byte_sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn intel_record(out: &mut String, record_type: u8, address: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(data);
+    let checksum = byte_sum(&bytes).wrapping_neg();
+    write_record(out, ":", &bytes, checksum);
+}
+
+// data records (00), start segment address (03) with CS = 0 when a start is known, end of file (01)
+pub fn write_intel_hex(segments: &[Segment], start: Option<u16>) -> String {
+    let mut out = String::new();
+    for (address, data) in records(segments) {
+        intel_record(&mut out, 0x00, address, data);
+    }
+    if let Some(start) = start {
+        let mut cs_ip = vec![0, 0];
+        cs_ip.extend_from_slice(&start.to_be_bytes());
+        intel_record(&mut out, 0x03, 0, &cs_ip);
+    }
+    intel_record(&mut out, 0x01, 0, &[]);
+    out
+}
+
+fn s_record(out: &mut String, record_type: char, address: u16, data: &[u8]) {
+    let mut bytes = vec![(data.len() + 3) as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.extend_from_slice(data);
+    let checksum = !byte_sum(&bytes);
+    write_record(out, &format!("S{}", record_type), &bytes, checksum);
+}
+
+// S19: S0 header, S1 data, S5 record count and S9 with the start address (0 when unknown)
+pub fn write_srecord(segments: &[Segment], start: Option<u16>, header: &str) -> String {
+    let mut out = String::new();
+    s_record(&mut out, '0', 0, header.as_bytes());
+    let mut count: u16 = 0;
+    for (address, data) in records(segments) {
+        s_record(&mut out, '1', address, data);
+        count = count.wrapping_add(1);
+    }
+    s_record(&mut out, '5', count, &[]);
+    s_record(&mut out, '9', start.unwrap_or(0), &[]);
+    out
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+// accepts data, end of file and start address records, extended addresses must stay within 64K
+pub fn read_intel_hex(text: &str) -> Result<Vec<Segment>, String> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut base: u32 = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = line
+            .strip_prefix(':')
+            .and_then(parse_hex_bytes)
+            .ok_or_else(|| format!("Line {line_number}: not an Intel HEX record"))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(format!("Line {line_number}: record length does not match its byte count"));
+        }
+        if byte_sum(&bytes) != 0 {
+            return Err(format!("Line {line_number}: checksum mismatch"));
+        }
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                let start = base + address as u32;
+                if start + data.len() as u32 > 0x10000 {
+                    return Err(format!("Line {line_number}: data beyond FFFFh"));
+                }
+                match segments.last_mut() {
+                    Some(last) if last.start as u32 + last.data.len() as u32 == start => {
+                        last.data.extend_from_slice(data)
+                    }
+                    _ => segments.push(Segment { start: start as u16, data: data.to_vec() }),
+                }
+            }
+            0x01 => break,
+            0x02 | 0x04 => {
+                let value = data.iter().fold(0u32, |value, byte| value << 8 | *byte as u32);
+                base = if bytes[3] == 0x02 { value << 4 } else { value << 16 };
+                if base > 0xFFFF {
+                    return Err(format!("Line {line_number}: extended address beyond FFFFh"));
+                }
+            }
+            0x03 | 0x05 => {}
+            other => return Err(format!("Line {line_number}: unknown record type {other:02X}")),
+        }
+    }
+    Ok(segments)
+}
+
+// a raw binary occupies consecutive addresses from the load address
+pub fn binary_segment(data: Vec<u8>, load_address: u16) -> Result<Segment, String> {
+    if load_address as usize + data.len() > 0x10000 {
+        return Err(format!(
+            "Image of {} bytes does not fit at {:04X}h",
+            data.len(),
+            load_address
+        ));
+    }
+    Ok(Segment { start: load_address, data })
+}
+
+pub fn copy_segments(segments: &[Segment], memory: &mut [u8]) {
+    for segment in segments {
+        let start = segment.start as usize;
+        memory[start..start + segment.data.len()].copy_from_slice(&segment.data);
+    }
+}
+
+// raw image from address 0 up to the last written byte, gaps filled with zeros
+pub fn write_binary(segments: &[Segment]) -> Vec<u8> {
+    let end = segments
+        .iter()
+        .map(|segment| segment.start as usize + segment.data.len())
+        .max()
+        .unwrap_or(0);
+    let mut binary = vec![0; end];
+    copy_segments(segments, &mut binary);
+    binary
+}
+
+// inclusive address ranges written by both lists of segments
+pub fn overlapping_ranges(first: &[Segment], second: &[Segment]) -> Vec<(u16, u16)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for a in first {
+        for b in second {
+            let start = (a.start as usize).max(b.start as usize);
+            let end = (a.start as usize + a.data.len()).min(b.start as usize + b.data.len());
+            if start < end {
+                ranges.push((start, end));
+            }
+        }
+    }
+    ranges.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(start, end)| (start as u16, (end - 1) as u16))
+        .collect()
+}
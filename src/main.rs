@@ -8,6 +8,7 @@ mod tests;
 pub mod cpu;
 pub mod gui;
 pub mod encoding;
+pub mod hex;
 
 pub fn main() -> iced::Result {
     daemon(
@@ -15,6 +16,7 @@ pub fn main() -> iced::Result {
         crate::gui::code_editor_app::CodeEditorApp::update,
         crate::gui::code_editor_app::CodeEditorApp::view,
     )
+    .title(crate::gui::code_editor_app::CodeEditorApp::title)
     .theme(|state: &crate::gui::code_editor_app::CodeEditorApp, _| {
         state.theme()
     })
@@ -217,3 +217,49 @@ fn cpu_cycles_per_second_runner() {
     assert!(steps >= 201);
 }
 
+#[test]
+fn intel_hex_round_trips_assembled_segments() {
+    let mut assembler = assembler::Assembler::new();
+    assembler.assemble("ORG 0\nJMP 100H\nORG 100H\nMVI A,0\nDB 0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0\nEND 100H").unwrap();
+    let segments = assembler.segments();
+
+    let text = hex::write_intel_hex(&segments, assembler.start_address());
+    assert_eq!(hex::read_intel_hex(&text).unwrap(), segments);
+
+    let mut memory = [0xFFu8; 0x10000];
+    hex::copy_segments(&segments, &mut memory);
+    assert_eq!(&memory[0..4], &[0xC3, 0x00, 0x01, 0xFF]);
+    assert_eq!(&memory[0x100..0x113], &[0x3E, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF]);
+}
+
+#[test]
+fn intel_hex_reader_rejects_bad_records() {
+    assert!(hex::read_intel_hex(":03000000C3000138").unwrap_err().contains("checksum"));
+    assert!(hex::read_intel_hex(":0300C3000139").is_err());
+    assert!(hex::read_intel_hex("03000000C3000139").is_err());
+    assert!(hex::read_intel_hex(":020000040001F9").unwrap_err().contains("FFFFh"));
+    assert_eq!(
+        hex::read_intel_hex(":020000040000FA\n:03000000C3000139\n:00000001FF\n:0100000001FE").unwrap(),
+        vec![hex::Segment { start: 0, data: vec![0xC3, 0x00, 0x01] }]
+    );
+    assert!(hex::binary_segment(vec![0; 16], 0xFFF8).is_err());
+    assert_eq!(hex::binary_segment(vec![1, 2], 0xFFFE).unwrap().start, 0xFFFE);
+}
+
+#[test]
+fn program_segments_lay_down_defined_zero_bytes() {
+    let mut assembler = assembler::Assembler::new();
+    assembler.assemble("ORG 2\nDB 0,0\nORG 10H\nNOP\nDS 4\nDB 0").unwrap();
+    let program = assembler.segments();
+    let bios = vec![hex::binary_segment(vec![0xAA; 8], 0).unwrap()];
+
+    let mut memory = [0u8; 0x10000];
+    hex::copy_segments(&bios, &mut memory);
+    hex::copy_segments(&program, &mut memory);
+    assert_eq!(&memory[0..8], &[0xAA, 0xAA, 0x00, 0x00, 0xAA, 0xAA, 0xAA, 0xAA]);
+    assert_eq!(hex::overlapping_ranges(&bios, &program), vec![(2, 3)]);
+
+    let binary = hex::write_binary(&program);
+    assert_eq!(binary.len(), 0x16);
+    assert_eq!(binary[0x10], 0x00);
+}